@@ -0,0 +1,460 @@
+/*!
+Derive macros for [`egg`](https://docs.rs/egg).
+
+These are re-exported by `egg`, so you should not need to depend on this crate directly.
+*/
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, Ident, Lit, LitStr, Meta, Result, Type,
+};
+
+/** Derive [`Language`], [`FromOp`] and [`Display`] for an `enum`.
+
+This is an alternative to `define_language!` that works on an ordinary `enum`
+definition, so it supports generic parameters, attributes and doc comments.
+Bounds on generic parameters go in the `enum` definition as usual.
+Since [`Language`] requires them, the `enum` must also implement (or derive)
+`Debug`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Hash`, and `Clone`.
+
+Variants take one of the following forms:
+- `#[op = "pi"] Pi`: an operator with no children.
+- `#[op = "+"] Add([Id; 2])`: an operator whose field holds the children,
+  which can be of any type implementing [`LanguageChildren`].
+- `Num(i32)`: a leaf holding data, which must implement `FromStr` and `Display`.
+- `Call(Symbol, Vec<Id>)`: data along with children.
+
+As with `define_language!`, variants are tried in order when parsing,
+so fallback variants like `Symbol(Symbol)` should go last.
+
+By default the [`Language::Discriminant`] is [`std::mem::Discriminant`].
+A custom type can be given with `#[language(discriminant = MyType)]`,
+in which case `MyType` must implement `From<&Self>`.
+
+Operator variants with children can be marked `#[ac]` (associative and
+commutative) or `#[commutative]` to set their [`Language::ac_kind`].
+
+# Example
+```
+use egg::*;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+enum Math<T: Debug + Display + FromStr + Ord + Hash + Clone> {
+    /// The sum of two terms.
+    #[op = "+"]
+    #[ac]
+    Add([Id; 2]),
+    #[op = "list"]
+    List(Box<[Id]>),
+    #[op = "pi"]
+    Pi,
+    Num(T),
+    Call(Symbol, Vec<Id>),
+}
+
+let expr: RecExpr<Math<i32>> = "(+ pi (list 1 (f 2)))".parse().unwrap();
+assert_eq!(expr.to_string(), "(+ pi (list 1 (f 2)))");
+assert!(matches!(expr[expr.root()], Math::Add(_)));
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Arity(usize);
+
+impl From<&Bool> for Arity {
+    fn from(node: &Bool) -> Self {
+        Arity(node.len())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+#[language(discriminant = Arity)]
+enum Bool {
+    #[op = "&"]
+    And([Id; 2]),
+    #[op = "~"]
+    Not(Id),
+    Lit(bool),
+}
+
+let node: Bool = FromOp::from_op("~", vec![Id::from(0)]).unwrap();
+assert_eq!(node.discriminant(), Arity(1));
+```
+
+[`Language`]: https://docs.rs/egg/latest/egg/trait.Language.html
+[`Language::Discriminant`]: https://docs.rs/egg/latest/egg/trait.Language.html#associatedtype.Discriminant
+[`Language::ac_kind`]: https://docs.rs/egg/latest/egg/trait.Language.html#method.ac_kind
+[`FromOp`]: https://docs.rs/egg/latest/egg/trait.FromOp.html
+[`LanguageChildren`]: https://docs.rs/egg/latest/egg/trait.LanguageChildren.html
+[`Display`]: std::fmt::Display
+**/
+#[proc_macro_derive(Language, attributes(op, language, ac, commutative))]
+pub fn derive_language(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Variant {
+    /// `#[op = "s"] V` or `#[op = "s"] V(ids)`
+    Op {
+        ident: Ident,
+        op: LitStr,
+        ids: Option<Type>,
+        /// The `AcKind` variant, from `#[ac]` or `#[commutative]`
+        ac: Option<Ident>,
+    },
+    /// `V(data)` or `V(data, ids)`
+    Data {
+        ident: Ident,
+        data: Type,
+        ids: Option<Type>,
+    },
+}
+
+fn parse_op(attrs: &[Attribute]) -> Result<Option<LitStr>> {
+    let mut op = None;
+    for attr in attrs {
+        if !attr.path().is_ident("op") {
+            continue;
+        }
+        if op.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate #[op] attribute"));
+        }
+        match &attr.meta {
+            Meta::NameValue(meta) => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => op = Some(s.clone()),
+                value => {
+                    return Err(Error::new_spanned(
+                        value,
+                        "expected a string literal, like #[op = \"+\"]",
+                    ))
+                }
+            },
+            _ => {
+                return Err(Error::new_spanned(
+                    attr,
+                    "expected an operator string, like #[op = \"+\"]",
+                ))
+            }
+        }
+    }
+    Ok(op)
+}
+
+fn parse_ac(attrs: &[Attribute]) -> Result<Option<Ident>> {
+    let mut ac = None;
+    for attr in attrs {
+        let kind = if attr.path().is_ident("ac") {
+            "AC"
+        } else if attr.path().is_ident("commutative") {
+            "C"
+        } else {
+            continue;
+        };
+        attr.meta.require_path_only()?;
+        if ac.is_some() {
+            return Err(Error::new_spanned(
+                attr,
+                "only one of #[ac] and #[commutative] can be given",
+            ));
+        }
+        ac = Some(Ident::new(kind, attr.span()));
+    }
+    Ok(ac)
+}
+
+/// Options given with `#[language(...)]` on the `enum`.
+#[derive(Default)]
+struct Options {
+    discriminant: Option<Type>,
+}
+
+fn parse_options(attrs: &[Attribute]) -> Result<Options> {
+    let mut options = Options::default();
+    for attr in attrs {
+        if !attr.path().is_ident("language") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("discriminant") {
+                options.discriminant = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown language attribute, expected `discriminant = Type`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn parse_variant(variant: &syn::Variant) -> Result<Variant> {
+    let ident = variant.ident.clone();
+    let op = parse_op(&variant.attrs)?;
+    let ac = parse_ac(&variant.attrs)?;
+    let fields: Vec<Type> = match &variant.fields {
+        Fields::Unit => vec![],
+        Fields::Unnamed(fields) => fields.unnamed.iter().map(|f| f.ty.clone()).collect(),
+        Fields::Named(fields) => {
+            return Err(Error::new_spanned(
+                fields,
+                "named fields are not supported, use a tuple variant",
+            ))
+        }
+    };
+
+    if ac.is_some() && (op.is_none() || fields.len() != 1) {
+        return Err(Error::new(
+            variant.span(),
+            "#[ac] and #[commutative] can only be used on operators with children",
+        ));
+    }
+
+    let mut fields = fields.into_iter();
+    match (op, fields.len()) {
+        (Some(op), 0 | 1) => Ok(Variant::Op {
+            ident,
+            op,
+            ids: fields.next(),
+            ac,
+        }),
+        (Some(_), _) => Err(Error::new_spanned(
+            &variant.fields,
+            "a variant with #[op] can only have one field, holding its children",
+        )),
+        (None, 0) => Err(Error::new(
+            variant.span(),
+            "a variant without fields needs an operator, like #[op = \"pi\"]",
+        )),
+        (None, 1 | 2) => Ok(Variant::Data {
+            ident,
+            data: fields.next().unwrap(),
+            ids: fields.next(),
+        }),
+        (None, _) => Err(Error::new_spanned(
+            &variant.fields,
+            "expected a data variant `V(data)` or `V(data, children)`",
+        )),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "#[derive(Language)] can only be used on enums",
+            ))
+        }
+    };
+    let variants = data
+        .variants
+        .iter()
+        .map(parse_variant)
+        .collect::<Result<Vec<_>>>()?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let options = parse_options(&input.attrs)?;
+    let (discriminant_ty, discriminant) = match options.discriminant {
+        Some(ty) => (
+            quote!(#ty),
+            quote!(<#ty as ::std::convert::From<&Self>>::from(self)),
+        ),
+        None => (
+            quote!(::std::mem::Discriminant<Self>),
+            quote!(::std::mem::discriminant(self)),
+        ),
+    };
+
+    let children_trait = quote!(::egg::LanguageChildren);
+    let mut matches = vec![];
+    let mut children = vec![];
+    let mut children_mut = vec![];
+    let mut display = vec![];
+    let mut from_op = vec![];
+    let mut ac_kinds = vec![];
+    let mut with_arity = vec![];
+    for variant in &variants {
+        match variant {
+            Variant::Op {
+                ident,
+                op,
+                ids: None,
+                ..
+            } => {
+                matches.push(quote!((#name::#ident, #name::#ident) => true,));
+                children.push(quote!(#name::#ident => &[],));
+                children_mut.push(quote!(#name::#ident => &mut [],));
+                display.push(quote!(#name::#ident => f.write_str(#op),));
+                with_arity.push(quote!(#name::#ident => (arity == 0).then(|| #name::#ident),));
+                from_op.push(quote! {
+                    if op == #op && children.is_empty() {
+                        return Ok(#name::#ident);
+                    }
+                });
+            }
+            Variant::Op {
+                ident,
+                op,
+                ids: Some(ids),
+                ac,
+            } => {
+                if let Some(kind) = ac {
+                    ac_kinds.push(quote!(#name::#ident(..) => ::egg::AcKind::#kind,));
+                }
+                matches.push(quote! {
+                    (#name::#ident(l), #name::#ident(r)) =>
+                        #children_trait::len(l) == #children_trait::len(r),
+                });
+                children.push(quote!(#name::#ident(ids) => #children_trait::as_slice(ids),));
+                children_mut
+                    .push(quote!(#name::#ident(ids) => #children_trait::as_mut_slice(ids),));
+                display.push(quote!(#name::#ident(..) => f.write_str(#op),));
+                with_arity.push(quote! {
+                    #name::#ident(_) => <#ids as #children_trait>::can_be_length(arity).then(|| {
+                        let ids = ::std::vec![::egg::Id::from(0); arity];
+                        #name::#ident(<#ids as #children_trait>::from_vec(ids))
+                    }),
+                });
+                from_op.push(quote! {
+                    if op == #op && <#ids as #children_trait>::can_be_length(children.len()) {
+                        return Ok(#name::#ident(<#ids as #children_trait>::from_vec(children)));
+                    }
+                });
+            }
+            Variant::Data {
+                ident,
+                data,
+                ids: None,
+            } => {
+                matches.push(quote!((#name::#ident(d1), #name::#ident(d2)) => d1 == d2,));
+                children.push(quote!(#name::#ident(_) => &[],));
+                children_mut.push(quote!(#name::#ident(_) => &mut [],));
+                display.push(quote!(#name::#ident(data) => ::std::fmt::Display::fmt(data, f),));
+                with_arity.push(quote! {
+                    #name::#ident(data) => (arity == 0).then(|| #name::#ident(data.clone())),
+                });
+                from_op.push(quote! {
+                    if children.is_empty() {
+                        if let Ok(data) = op.parse::<#data>() {
+                            return Ok(#name::#ident(data));
+                        }
+                    }
+                });
+            }
+            Variant::Data {
+                ident,
+                data,
+                ids: Some(ids),
+            } => {
+                matches.push(quote! {
+                    (#name::#ident(d1, l), #name::#ident(d2, r)) =>
+                        d1 == d2 && #children_trait::len(l) == #children_trait::len(r),
+                });
+                children.push(quote!(#name::#ident(_, ids) => #children_trait::as_slice(ids),));
+                children_mut
+                    .push(quote!(#name::#ident(_, ids) => #children_trait::as_mut_slice(ids),));
+                display.push(quote!(#name::#ident(data, _) => ::std::fmt::Display::fmt(data, f),));
+                with_arity.push(quote! {
+                    #name::#ident(data, _) => <#ids as #children_trait>::can_be_length(arity).then(|| {
+                        let ids = ::std::vec![::egg::Id::from(0); arity];
+                        #name::#ident(data.clone(), <#ids as #children_trait>::from_vec(ids))
+                    }),
+                });
+                from_op.push(quote! {
+                    if <#ids as #children_trait>::can_be_length(children.len()) {
+                        if let Ok(data) = op.parse::<#data>() {
+                            let children = <#ids as #children_trait>::from_vec(children);
+                            return Ok(#name::#ident(data, children));
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    let ac_kind = if ac_kinds.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            fn ac_kind(&self) -> ::egg::AcKind {
+                match self {
+                    #(#ac_kinds)*
+                    #[allow(unreachable_patterns)]
+                    _ => ::egg::AcKind::None,
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::egg::Language for #name #ty_generics #where_clause {
+            type Discriminant = #discriminant_ty;
+
+            #[inline(always)]
+            fn discriminant(&self) -> Self::Discriminant {
+                #discriminant
+            }
+
+            #[inline(always)]
+            fn matches(&self, other: &Self) -> bool {
+                ::std::mem::discriminant(self) == ::std::mem::discriminant(other)
+                    && match (self, other) {
+                        #(#matches)*
+                        #[allow(unreachable_patterns)]
+                        _ => false,
+                    }
+            }
+
+            fn children(&self) -> &[::egg::Id] {
+                match self {
+                    #(#children)*
+                }
+            }
+
+            fn children_mut(&mut self) -> &mut [::egg::Id] {
+                match self {
+                    #(#children_mut)*
+                }
+            }
+
+            fn with_arity(&self, arity: usize) -> ::std::option::Option<Self> {
+                match self {
+                    #(#with_arity)*
+                }
+            }
+
+            #ac_kind
+
+        }
+
+        impl #impl_generics ::std::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match self {
+                    #(#display)*
+                }
+            }
+        }
+
+        impl #impl_generics ::egg::FromOp for #name #ty_generics #where_clause {
+            type Error = ::egg::FromOpError;
+
+            fn from_op(
+                op: &str,
+                children: ::std::vec::Vec<::egg::Id>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                #(#from_op)*
+                Err(::egg::FromOpError::new(op, children))
+            }
+        }
+    })
+}
@@ -0,0 +1,645 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::*;
+use thiserror::Error;
+
+/// A bound variable, referring to the binder `n` levels above it.
+///
+/// The innermost enclosing binder is `$0`, the one above it is `$1`, and so on.
+/// Since variables have no names, alpha-equivalent terms are equal,
+/// so they are hash-consed into the same e-class.
+///
+/// A [`DeBruijnIndex`] parses from and prints as `$n`,
+/// so it can be used as a data variant in [`define_language!`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeBruijnIndex(pub usize);
+
+/// An error from parsing a [`DeBruijnIndex`].
+#[derive(Debug, Error)]
+#[error("de Bruijn index {0:?} should be a `$` followed by a number")]
+pub struct DeBruijnIndexParseError(String);
+
+impl FromStr for DeBruijnIndex {
+    type Err = DeBruijnIndexParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix('$')
+            .and_then(|n| n.parse().ok())
+            .map(DeBruijnIndex)
+            .ok_or_else(|| DeBruijnIndexParseError(s.to_owned()))
+    }
+}
+
+impl Display for DeBruijnIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+/** A [`Language`] with binders, whose bound variables are [`DeBruijnIndex`]es.
+
+Using de Bruijn indices instead of names means alpha-equivalent terms
+share an e-class, and patterns can match under binders directly.
+To move a term into or out of a binder, use the [`Substitute`] applier,
+which does capture-avoiding substitution, so you don't have to write
+explicit substitution rules.
+
+If your [`Analysis`] needs to know which variables are free in an e-class,
+you can compute them with [`BinderLanguage::free_vars`].
+
+# Example
+```
+use egg::{rewrite as rw, *};
+
+define_language! {
+    enum Lambda {
+        "lam" = Lam(Id),
+        "app" = App([Id; 2]),
+        Index(DeBruijnIndex),
+        Symbol(Symbol),
+    }
+}
+
+impl BinderLanguage for Lambda {
+    fn index(&self) -> Option<DeBruijnIndex> {
+        match self {
+            Lambda::Index(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn from_index(index: DeBruijnIndex) -> Self {
+        Lambda::Index(index)
+    }
+
+    fn binds(&self, _child: usize) -> usize {
+        match self {
+            Lambda::Lam(_) => 1,
+            _ => 0,
+        }
+    }
+}
+
+let rules: &[Rewrite<Lambda, ()>] = &[
+    rw!("beta"; "(app (lam ?body) ?arg)" => {
+        Substitute::new("?body".parse().unwrap(), "?arg".parse().unwrap())
+    }),
+];
+
+// (λx. λy. x) y  ==>  λz. y
+let start = "(app (lam (lam $1)) y)".parse().unwrap();
+let runner = Runner::default().with_expr(&start).run(rules);
+let goal = "(lam y)".parse().unwrap();
+assert_eq!(runner.egraph.lookup_expr(&goal), Some(runner.egraph.find(runner.roots[0])));
+```
+**/
+pub trait BinderLanguage: Language {
+    /// Returns the index of this e-node if it is a bound variable.
+    fn index(&self) -> Option<DeBruijnIndex>;
+
+    /// Makes a bound variable e-node with the given index.
+    fn from_index(index: DeBruijnIndex) -> Self;
+
+    /// Returns how many variables this e-node binds in its `child`th child.
+    ///
+    /// For example, a lambda binds one variable in its body,
+    /// and a `let` binds one variable in its body but none in its definition.
+    fn binds(&self, child: usize) -> usize;
+
+    /// Computes the indices free in this e-node, given the indices
+    /// free in each of its children.
+    ///
+    /// An e-class can hold terms with different free variables,
+    /// like `(* $0 0)` and `0`, so an [`Analysis`] should intersect
+    /// these sets when merging e-classes.
+    /// Every term is then equivalent to one using only the variables in
+    /// the intersection.
+    fn free_vars<'a, F>(&self, mut child_free: F) -> BTreeSet<usize>
+    where
+        F: FnMut(Id) -> &'a BTreeSet<usize>,
+    {
+        if let Some(DeBruijnIndex(i)) = self.index() {
+            return BTreeSet::from([i]);
+        }
+        let mut free = BTreeSet::new();
+        for (i, &child) in self.children().iter().enumerate() {
+            let bound = self.binds(i);
+            free.extend(child_free(child).range(bound..).map(|v| v - bound));
+        }
+        free
+    }
+
+    /// Substitutes `arg` for the variable bound just outside of `body`,
+    /// as in beta-reduction.
+    ///
+    /// `$0` in `body` is replaced by `arg`, with the free variables of
+    /// `arg` shifted so they are not captured by binders inside `body`.
+    /// The other free variables of `body` are shifted down,
+    /// since the binder is removed.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// # define_language! {
+    /// #     enum Lambda {
+    /// #         "lam" = Lam(Id),
+    /// #         "app" = App([Id; 2]),
+    /// #         Index(DeBruijnIndex),
+    /// #         Symbol(Symbol),
+    /// #     }
+    /// # }
+    /// # impl BinderLanguage for Lambda {
+    /// #     fn index(&self) -> Option<DeBruijnIndex> {
+    /// #         match self { Lambda::Index(i) => Some(*i), _ => None }
+    /// #     }
+    /// #     fn from_index(index: DeBruijnIndex) -> Self { Lambda::Index(index) }
+    /// #     fn binds(&self, _: usize) -> usize { matches!(self, Lambda::Lam(_)) as usize }
+    /// # }
+    /// let body: RecExpr<Lambda> = "(lam (app $1 (app $0 $2)))".parse().unwrap();
+    /// let arg = "(app f $0)".parse().unwrap();
+    /// let result = Lambda::substitute(&body, &arg);
+    /// assert_eq!(result.to_string(), "(lam (app (app f $1) (app $0 $1)))");
+    /// ```
+    fn substitute(body: &RecExpr<Self>, arg: &RecExpr<Self>) -> RecExpr<Self> {
+        let mut result = RecExpr::default();
+        substitute_with(body, arg, &mut |node| result.add(node));
+        result
+    }
+}
+
+/// Substitutes `arg` into `body`, adding the resulting nodes with `add`.
+fn substitute_with<L, F>(body: &RecExpr<L>, arg: &RecExpr<L>, add: &mut F) -> Id
+where
+    L: BinderLanguage,
+    F: FnMut(L) -> Id,
+{
+    struct Substitution<'a, L, F> {
+        body: &'a RecExpr<L>,
+        arg: &'a RecExpr<L>,
+        add: &'a mut F,
+        // memoized results, keyed by node and the number of binders above it
+        body_memo: HashMap<(Id, usize), Id>,
+        arg_memo: HashMap<(Id, usize, usize), Id>,
+    }
+
+    impl<L: BinderLanguage, F: FnMut(L) -> Id> Substitution<'_, L, F> {
+        fn body(&mut self, id: Id, depth: usize) -> Id {
+            if let Some(&result) = self.body_memo.get(&(id, depth)) {
+                return result;
+            }
+            let body = self.body;
+            let node = &body[id];
+            let result = match node.index() {
+                Some(DeBruijnIndex(i)) if i == depth => self.arg(self.arg.root(), depth, 0),
+                Some(DeBruijnIndex(i)) if i > depth => {
+                    (self.add)(L::from_index(DeBruijnIndex(i - 1)))
+                }
+                _ => {
+                    let mut new = node.clone();
+                    for (i, child) in new.children_mut().iter_mut().enumerate() {
+                        *child = self.body(*child, depth + node.binds(i));
+                    }
+                    (self.add)(new)
+                }
+            };
+            self.body_memo.insert((id, depth), result);
+            result
+        }
+
+        /// Shifts the variables of `arg` that are free (at least `cutoff`)
+        /// up by `amount`.
+        fn arg(&mut self, id: Id, amount: usize, cutoff: usize) -> Id {
+            if let Some(&result) = self.arg_memo.get(&(id, amount, cutoff)) {
+                return result;
+            }
+            let arg = self.arg;
+            let node = &arg[id];
+            let result = match node.index() {
+                Some(DeBruijnIndex(i)) if i >= cutoff => {
+                    (self.add)(L::from_index(DeBruijnIndex(i + amount)))
+                }
+                _ => {
+                    let mut new = node.clone();
+                    for (i, child) in new.children_mut().iter_mut().enumerate() {
+                        *child = self.arg(*child, amount, cutoff + node.binds(i));
+                    }
+                    (self.add)(new)
+                }
+            };
+            self.arg_memo.insert((id, amount, cutoff), result);
+            result
+        }
+    }
+
+    let mut subst = Substitution {
+        body,
+        arg,
+        add,
+        body_memo: HashMap::default(),
+        arg_memo: HashMap::default(),
+    };
+    subst.body(body.root(), 0)
+}
+
+/** An [`Applier`] that does capture-avoiding substitution,
+as described in [`BinderLanguage::substitute`].
+
+The e-class bound to `body` is treated as the body of a binder,
+and `arg` is substituted for the bound variable.
+The result is unioned with the matched e-class.
+
+Substitution works directly on e-classes: the e-nodes of the `body`
+e-class (and of the e-classes below it) are substituted into, and the
+results for the e-nodes of each e-class are unioned together,
+so no term has to be extracted first.
+The results are memoized by e-class and number of enclosing binders,
+and e-classes that hold a term without the substituted variable
+are left as they are.
+
+In each e-class, only the e-nodes of the smallest terms with the fewest
+free variables are substituted into, as found by a fixpoint over the
+e-classes reachable from `body` and `arg`.
+The other e-nodes are equal to these, and substituting into them
+(into beta-redexes or unfolded recursive calls, for example) would keep
+adding copies of the same terms.
+E-nodes that lead back to an e-class that is being substituted into
+(cycles in the e-graph) are skipped, since they have no finite term,
+as are the e-nodes added by the substitution itself.
+
+See [`BinderLanguage`] for an example.
+**/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitute<L> {
+    body: Var,
+    arg: Pattern<L>,
+}
+
+impl<L: Language> Substitute<L> {
+    /// Create a new [`Substitute`] applier, which substitutes the
+    /// instantiation of `arg` into the e-class bound to `body`.
+    pub fn new(body: Var, arg: Pattern<L>) -> Self {
+        Substitute { body, arg }
+    }
+}
+
+impl<L, N> Applier<L, N> for Substitute<L>
+where
+    L: BinderLanguage,
+    N: Analysis<L>,
+{
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<L>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        let arg = egraph.add_instantiation(&self.arg.ast, subst);
+        let mut substitution = ClassSubstitution {
+            egraph,
+            arg,
+            levels: HashMap::default(),
+            body_memo: HashMap::default(),
+            arg_memo: HashMap::default(),
+            visiting: HashSet::default(),
+            unions: vec![],
+        };
+        substitution.compute_levels(&[subst[self.body], arg]);
+        let result = substitution.body(subst[self.body], 0);
+        for (a, b) in substitution.unions {
+            if egraph.are_explanations_enabled() {
+                egraph.union_trusted(a, b, rule_name);
+            } else {
+                egraph.union(a, b);
+            }
+        }
+        let result = match result {
+            Some(result) => result,
+            None => return vec![],
+        };
+        let did_union = match searcher_ast {
+            Some(ast) => {
+                let from = egraph.add_instantiation_noncanonical(ast, subst);
+                egraph.union_trusted(from, result, rule_name)
+            }
+            None => egraph.union(eclass, result),
+        };
+        if did_union {
+            vec![result]
+        } else {
+            vec![]
+        }
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        let mut vars = self.arg.vars();
+        if !vars.contains(&self.body) {
+            vars.push(self.body);
+        }
+        vars
+    }
+}
+
+/// Like [`substitute_with`], but on the e-classes of an e-graph.
+struct ClassSubstitution<'a, L: Language, N: Analysis<L>> {
+    egraph: &'a mut EGraph<L, N>,
+    arg: Id,
+    // the levels of the e-classes, see `compute_levels`
+    levels: HashMap<Id, (usize, usize)>,
+    // memoized results, keyed by e-class and the number of binders above it
+    body_memo: HashMap<(Id, usize), Option<Id>>,
+    arg_memo: HashMap<(Id, usize, usize), Option<Id>>,
+    // the e-classes currently being substituted into, to skip cycles
+    visiting: HashSet<(Id, bool)>,
+    // the results to union once the substitution is done, so that it
+    // doesn't walk into the e-nodes it adds
+    unions: Vec<(Id, Id)>,
+}
+
+impl<L: BinderLanguage, N: Analysis<L>> ClassSubstitution<'_, L, N> {
+    /// Substitutes into each e-node of the e-class `id`, with `rewrite`
+    /// giving the new children, and unions the results.
+    fn each_node<F>(&mut self, id: Id, is_arg: bool, mut rewrite: F) -> Option<Id>
+    where
+        F: FnMut(&mut Self, &L) -> Option<Id>,
+    {
+        if !self.visiting.insert((id, is_arg)) {
+            return None;
+        }
+        let level = self.level(id);
+        let mut result = None;
+        for node in self.egraph[id].nodes.clone() {
+            if self.node_level(&node) != level {
+                continue;
+            }
+            let new = match rewrite(self, &node) {
+                Some(new) => new,
+                None => continue,
+            };
+            match result {
+                None => result = Some(new),
+                Some(result) => self.unions.push((result, new)),
+            }
+        }
+        hashset_remove(&mut self.visiting, &(id, is_arg));
+        result
+    }
+
+    /// Adds `node` with its children substituted by `child`, if they all can be.
+    fn add_with<F>(&mut self, node: &L, mut child: F) -> Option<Id>
+    where
+        F: FnMut(&mut Self, Id, usize) -> Option<Id>,
+    {
+        let mut new = node.clone();
+        for (i, c) in new.children_mut().iter_mut().enumerate() {
+            *c = child(self, *c, node.binds(i))?;
+        }
+        Some(self.egraph.add(new))
+    }
+
+    /// Computes the levels of the e-classes reachable from `roots`.
+    ///
+    /// The level of a term is a number that its free variables are all
+    /// below, and its size.
+    /// The level of an e-class is the smallest level of its terms.
+    /// Like in [`Extractor`], the levels are found by iterating to a fixpoint,
+    /// and e-classes without a finite term are left out.
+    fn compute_levels(&mut self, roots: &[Id]) {
+        let mut reachable = vec![];
+        let mut todo: Vec<Id> = roots.iter().map(|&id| self.egraph.find(id)).collect();
+        let mut seen: HashSet<Id> = todo.iter().copied().collect();
+        while let Some(id) = todo.pop() {
+            reachable.push(id);
+            for node in &self.egraph[id].nodes {
+                for &child in node.children() {
+                    let child = self.egraph.find(child);
+                    if seen.insert(child) {
+                        todo.push(child);
+                    }
+                }
+            }
+        }
+
+        let mut did_something = true;
+        while did_something {
+            did_something = false;
+            for &id in &reachable {
+                let level = self.egraph[id]
+                    .nodes
+                    .iter()
+                    .filter_map(|node| self.node_level(node))
+                    .min();
+                if let Some(level) = level {
+                    if self.levels.get(&id).map_or(true, |&old| level < old) {
+                        self.levels.insert(id, level);
+                        did_something = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the level of an e-class, see [`Self::compute_levels`].
+    fn level(&self, id: Id) -> Option<(usize, usize)> {
+        self.levels.get(&self.egraph.find(id)).copied()
+    }
+
+    /// Returns the level of the term with this e-node at the root, if the
+    /// levels of its children are known.
+    fn node_level(&self, node: &L) -> Option<(usize, usize)> {
+        if let Some(DeBruijnIndex(i)) = node.index() {
+            return Some((i + 1, 1));
+        }
+        let (mut level, mut size) = (0, 1usize);
+        for (i, &child) in node.children().iter().enumerate() {
+            let (l, n) = self.level(child)?;
+            level = level.max(l.saturating_sub(node.binds(i)));
+            size = size.saturating_add(n);
+        }
+        Some((level, size))
+    }
+
+    fn body(&mut self, id: Id, depth: usize) -> Option<Id> {
+        let id = self.egraph.find(id);
+        if self.level(id).map_or(false, |(level, _)| level <= depth) {
+            return Some(id);
+        }
+        if let Some(&result) = self.body_memo.get(&(id, depth)) {
+            return result;
+        }
+        let result = self.each_node(id, false, |this, node| match node.index() {
+            Some(DeBruijnIndex(i)) if i == depth => this.arg(this.arg, depth, 0),
+            Some(DeBruijnIndex(i)) if i > depth => {
+                Some(this.egraph.add(L::from_index(DeBruijnIndex(i - 1))))
+            }
+            _ => this.add_with(node, |this, c, binds| this.body(c, depth + binds)),
+        });
+        self.body_memo.insert((id, depth), result);
+        result
+    }
+
+    /// Shifts the variables of the e-class `id` that are free (at least `cutoff`)
+    /// up by `amount`.
+    fn arg(&mut self, id: Id, amount: usize, cutoff: usize) -> Option<Id> {
+        let id = self.egraph.find(id);
+        if amount == 0 || self.level(id).map_or(false, |(level, _)| level <= cutoff) {
+            return Some(id);
+        }
+        if let Some(&result) = self.arg_memo.get(&(id, amount, cutoff)) {
+            return result;
+        }
+        let result = self.each_node(id, true, |this, node| match node.index() {
+            Some(DeBruijnIndex(i)) if i >= cutoff => {
+                Some(this.egraph.add(L::from_index(DeBruijnIndex(i + amount))))
+            }
+            _ => this.add_with(node, |this, c, binds| this.arg(c, amount, cutoff + binds)),
+        });
+        self.arg_memo.insert((id, amount, cutoff), result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::collections::BTreeSet;
+
+    define_language! {
+        enum Lambda {
+            "lam" = Lam(Id),
+            "let" = Let([Id; 2]),
+            "app" = App([Id; 2]),
+            Index(DeBruijnIndex),
+            Symbol(Symbol),
+        }
+    }
+
+    impl BinderLanguage for Lambda {
+        fn index(&self) -> Option<DeBruijnIndex> {
+            match self {
+                Lambda::Index(i) => Some(*i),
+                _ => None,
+            }
+        }
+
+        fn from_index(index: DeBruijnIndex) -> Self {
+            Lambda::Index(index)
+        }
+
+        fn binds(&self, child: usize) -> usize {
+            match (self, child) {
+                (Lambda::Lam(_), _) | (Lambda::Let(_), 1) => 1,
+                _ => 0,
+            }
+        }
+    }
+
+    fn substitute(body: &str, arg: &str) -> String {
+        let body = body.parse().unwrap();
+        let arg = arg.parse().unwrap();
+        Lambda::substitute(&body, &arg).to_string()
+    }
+
+    #[test]
+    fn parse_index() {
+        assert_eq!("$12".parse::<DeBruijnIndex>().unwrap(), DeBruijnIndex(12));
+        assert_eq!(DeBruijnIndex(3).to_string(), "$3");
+        assert!("12".parse::<DeBruijnIndex>().is_err());
+        assert!("$x".parse::<DeBruijnIndex>().is_err());
+    }
+
+    #[test]
+    fn substitute_terms() {
+        assert_eq!(substitute("$0", "x"), "x");
+        assert_eq!(substitute("(app $0 $1)", "x"), "(app x $0)");
+        // the argument's free variables are shifted under binders
+        assert_eq!(substitute("(lam (app $1 $0))", "$0"), "(lam (app $1 $0))");
+        assert_eq!(
+            substitute("(lam (app $1 $0))", "(lam $1)"),
+            "(lam (app (lam $2) $0))"
+        );
+        // `let` only binds in its body
+        assert_eq!(substitute("(let $0 $1)", "$3"), "(let $3 $4)");
+    }
+
+    #[test]
+    fn free_vars() {
+        let expr: RecExpr<Lambda> = "(app (lam (app $0 $2)) (let $0 (app $0 $1)))"
+            .parse()
+            .unwrap();
+        let mut free: Vec<BTreeSet<usize>> = vec![];
+        for node in expr.as_ref() {
+            let vars = node.free_vars(|id| &free[usize::from(id)]);
+            free.push(vars);
+        }
+        assert_eq!(free.last().unwrap(), &BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn alpha_equivalence() {
+        let mut egraph = EGraph::<Lambda, ()>::default().with_explanations_enabled();
+        // both are λx. λy. x, and get the same e-class
+        let a = egraph.add_expr(&"(app (lam (lam $1)) f)".parse().unwrap());
+        let b = egraph.add_expr(&"(app (lam (lam $1)) g)".parse().unwrap());
+        assert_ne!(a, b);
+        assert_eq!(
+            egraph[a].nodes[0].children()[0],
+            egraph[b].nodes[0].children()[0]
+        );
+
+        let rules: Vec<Rewrite<Lambda, ()>> = vec![
+            rewrite!("beta"; "(app (lam ?body) ?arg)" => {
+                Substitute::new("?body".parse().unwrap(), "?arg".parse().unwrap())
+            }),
+            rewrite!("let"; "(let ?arg ?body)" => {
+                Substitute::new("?body".parse().unwrap(), "?arg".parse().unwrap())
+            }),
+        ];
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        let mut egraph = runner.egraph;
+        let start = "(app (lam (lam $1)) f)".parse().unwrap();
+        let goal = "(lam f)".parse().unwrap();
+        assert_eq!(egraph.lookup_expr(&goal), Some(egraph.find(a)));
+        let mut explanation = egraph.explain_equivalence(&start, &goal);
+        assert_eq!(explanation.get_flat_strings().len(), 2);
+
+        // substitution is capture-avoiding
+        let expr = "(let $0 (lam (app $0 $1)))".parse().unwrap();
+        let id = egraph.add_expr(&expr);
+        egraph.rebuild();
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        let goal = "(lam (app $0 $1))".parse().unwrap();
+        assert_eq!(
+            runner.egraph.lookup_expr(&goal),
+            Some(runner.egraph.find(id))
+        );
+        let captured = "(lam (app $0 $0))".parse().unwrap();
+        assert_eq!(runner.egraph.lookup_expr(&captured), None);
+    }
+
+    #[test]
+    fn substitute_eclass() {
+        let mut egraph = EGraph::<Lambda, ()>::default();
+        let f = egraph.add_expr(&"(app f $0)".parse().unwrap());
+        let g = egraph.add_expr(&"(app g $0)".parse().unwrap());
+        egraph.union(f, g);
+        let id = egraph.add_expr(&"(let x (app f $0))".parse().unwrap());
+        egraph.rebuild();
+
+        let rules: Vec<Rewrite<Lambda, ()>> = vec![rewrite!("let"; "(let ?arg ?body)" => {
+            Substitute::new("?body".parse().unwrap(), "?arg".parse().unwrap())
+        })];
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        // both e-nodes of the body are substituted into
+        for expr in ["(app f x)", "(app g x)"] {
+            let expr = expr.parse().unwrap();
+            assert_eq!(
+                runner.egraph.lookup_expr(&expr),
+                Some(runner.egraph.find(id))
+            );
+        }
+    }
+}
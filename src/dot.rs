@@ -11,7 +11,7 @@ use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{Error, ErrorKind, Result, Write};
 use std::path::Path;
 
-use crate::{egraph::EGraph, Analysis, Language};
+use crate::{egraph::EGraph, Analysis, Language, Symbol};
 
 /**
 A wrapper for an [`EGraph`] that can output [GraphViz] for
@@ -57,8 +57,25 @@ pub struct Dot<'a, L: Language, N: Analysis<L>> {
     /// Whether or not to anchor the edges in the output.
     /// True by default.
     pub use_anchors: bool,
+    /// Whether or not to color the e-nodes by the rule that added them.
+    /// False by default.
+    pub provenance_colors: bool,
 }
 
+// colors for the rules, the e-nodes not added by a rule stay white
+const PROVENANCE_COLORS: &[&str] = &[
+    "lightblue",
+    "lightpink",
+    "palegreen",
+    "khaki",
+    "plum",
+    "lightsalmon",
+    "paleturquoise",
+    "wheat",
+    "lightgray",
+    "thistle",
+];
+
 impl<'a, L, N> Dot<'a, L, N>
 where
     L: Language + Display,
@@ -84,6 +101,17 @@ where
         self
     }
 
+    /// Set whether or not to color the e-nodes by the rule that added them,
+    /// see [`EGraph::with_provenance_enabled`].
+    ///
+    /// Each rule gets a color, and the rule and iteration that added each
+    /// e-node is shown in its tooltip.
+    /// Does nothing if provenance tracking is disabled.
+    pub fn with_provenance_colors(mut self, provenance_colors: bool) -> Self {
+        self.provenance_colors = provenance_colors;
+        self
+    }
+
     /// Renders the `Dot` to a .png file with the given filename.
     /// Requires a `dot` binary to be on your `$PATH`.
     pub fn to_png(&self, filename: impl AsRef<Path>) -> Result<()> {
@@ -192,12 +220,36 @@ where
             writeln!(f, "  {}", line)?;
         }
 
+        let provenance = if self.provenance_colors {
+            self.egraph.provenance_by_enode()
+        } else {
+            Default::default()
+        };
+        let mut rules: Vec<Symbol> = provenance.values().filter_map(|p| p.rule).collect();
+        rules.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        rules.dedup();
+
         // define all the nodes, clustered by eclass
         for class in self.egraph.classes() {
             writeln!(f, "  subgraph cluster_{} {{", class.id)?;
             writeln!(f, "    style=dotted")?;
             for (i, node) in class.iter().enumerate() {
-                writeln!(f, "    {}.{}[label = \"{}\"]", class.id, i, node)?;
+                write!(f, "    {}.{}[label = \"{}\"", class.id, i, node)?;
+                if let Some(p) = provenance.get(node) {
+                    let rule = p.rule.map_or("none", |r| r.as_str());
+                    write!(
+                        f,
+                        ", tooltip = \"{} @ {}\"",
+                        rule.escape_debug(),
+                        p.iteration
+                    )?;
+                    if let Some(r) = p.rule {
+                        let color = rules.iter().position(|&s| s == r).unwrap();
+                        let color = PROVENANCE_COLORS[color % PROVENANCE_COLORS.len()];
+                        write!(f, ", style = filled, fillcolor = {}", color)?;
+                    }
+                }
+                writeln!(f, "]")?;
             }
             writeln!(f, "  }}")?;
         }
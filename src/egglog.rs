@@ -0,0 +1,643 @@
+/*!
+Moving e-graphs and rules to and from [egglog]
+
+Use the [`Egglog`] struct to write an [`EGraph`] as an egglog program,
+and [`parse_egglog_rules`] to read egglog rules as [`Rewrite`]s.
+
+[egglog]: https://github.com/egraphs-good/egglog
+!*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug, Display, Formatter};
+
+use thiserror::Error;
+
+use crate::*;
+
+// names that can't be used as constructors in egglog
+const RESERVED: &[&str] = &[
+    "op",
+    "let",
+    "set",
+    "union",
+    "delete",
+    "subsume",
+    "panic",
+    "rewrite",
+    "birewrite",
+    "rule",
+    "run",
+    "run-schedule",
+    "ruleset",
+    "datatype",
+    "sort",
+    "function",
+    "constructor",
+    "relation",
+    "check",
+    "extract",
+    "fail",
+    "push",
+    "pop",
+    "print-function",
+    "print-size",
+    "input",
+    "output",
+    "include",
+    "and",
+    "or",
+    "not",
+    "xor",
+    "min",
+    "max",
+    "abs",
+    "log",
+    "sqrt",
+    "true",
+    "false",
+];
+
+/**
+A wrapper for an [`EGraph`] that can output an [egglog] program.
+
+The [`EGraph::egglog`] method creates `Egglog`s.
+The program declares a datatype with one constructor per operator,
+binds each e-class to a global with `let`, and adds the other e-nodes of
+the e-class with `union`.
+Each e-class is defined by the first of its e-nodes whose children are
+already defined, so e-classes made only of cycles can't be defined.
+They are left out and listed in a comment at the end of the program.
+
+# Operator names
+
+`egg` languages are untyped, so everything lives in a single egglog sort,
+and each operator becomes a constructor of that sort.
+Operators that are plain identifiers (a letter, then letters, digits, and
+single `_` or `-`) are used as constructor names as they are, unless they
+are egglog keywords or builtins.
+Other operators are escaped as `op__` followed by the operator, where every
+character other than an ASCII letter or digit is written as `_`, its
+hexadecimal code point, and `x`; so `+` becomes `op___2bx`.
+When an operator is used with several numbers of children, the constructor
+name also gets `__n` and the number of children, like `op___2dx__n1`.
+[`parse_egglog_rules`] undoes this, so rules can be written against the
+exported constructors.
+
+# Example
+
+```
+use egg::*;
+
+let mut egraph = EGraph::<SymbolLang, ()>::default();
+let a = egraph.add_expr(&"(+ x 1)".parse().unwrap());
+let b = egraph.add_expr(&"(inc x)".parse().unwrap());
+egraph.union(a, b);
+egraph.rebuild();
+
+// Egglog implements std::fmt::Display
+assert_eq!(
+    egraph.egglog().with_sort("Math").to_string(),
+    "(datatype Math
+  (op___2bx Math Math)
+  (op__1)
+  (inc Math)
+  (x)
+)
+(let c__0 (x))
+(let c__2 (inc c__0))
+(let c__1 (op__1))
+(union c__2 (op___2bx c__0 c__1))
+"
+);
+```
+
+[egglog]: https://github.com/egraphs-good/egglog
+**/
+pub struct Egglog<'a, L: Language, N: Analysis<L>> {
+    pub(crate) egraph: &'a EGraph<L, N>,
+    /// The name of the egglog sort of the e-classes.
+    /// `Expr` by default.
+    pub sort: String,
+}
+
+impl<'a, L: Language, N: Analysis<L>> Egglog<'a, L, N> {
+    /// Set the name of the egglog sort of the e-classes.
+    pub fn with_sort(mut self, sort: impl Into<String>) -> Self {
+        self.sort = sort.into();
+        self
+    }
+}
+
+impl<'a, L: Language, N: Analysis<L>> Debug for Egglog<'a, L, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("Egglog").field(self.egraph).finish()
+    }
+}
+
+impl<'a, L, N> Display for Egglog<'a, L, N>
+where
+    L: Language + Display,
+    N: Analysis<L>,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let egraph = self.egraph;
+        let mut classes: Vec<&EClass<L, N::Data>> = egraph.classes().collect();
+        classes.sort_by_key(|class| class.id);
+
+        let mut arities: BTreeMap<String, BTreeSet<usize>> = Default::default();
+        for node in classes.iter().flat_map(|class| class.iter()) {
+            let op = node.to_string();
+            arities.entry(op).or_default().insert(node.len());
+        }
+        let mut names: HashMap<(String, usize), String> = Default::default();
+        for (op, ops_arities) in &arities {
+            for &arity in ops_arities {
+                let mut name = encode_op(op, &self.sort);
+                if ops_arities.len() > 1 {
+                    name = format!("{}__n{}", name, arity);
+                }
+                names.insert((op.clone(), arity), name);
+            }
+        }
+
+        writeln!(f, "(datatype {}", self.sort)?;
+        for (op, ops_arities) in &arities {
+            for &arity in ops_arities {
+                write!(f, "  ({}", names[&(op.clone(), arity)])?;
+                for _ in 0..arity {
+                    write!(f, " {}", self.sort)?;
+                }
+                writeln!(f, ")")?;
+            }
+        }
+        writeln!(f, ")")?;
+
+        let term = |node: &L| {
+            let mut term = format!("({}", names[&(node.to_string(), node.len())]);
+            for &child in node.children() {
+                term += &format!(" c__{}", egraph.find(child));
+            }
+            term + ")"
+        };
+
+        // define the e-classes in topological order, starting from the leaves,
+        // and checking the parents of each e-class once it is defined
+        let mut defined: HashMap<Id, &L> = Default::default();
+        let mut todo: Vec<Id> = classes.iter().rev().map(|class| class.id).collect();
+        while let Some(id) = todo.pop() {
+            if defined.contains_key(&id) {
+                continue;
+            }
+            let class = &egraph[id];
+            let is_defined = |child| defined.contains_key(&egraph.find(child));
+            if let Some(node) = class.iter().find(|node| node.all(is_defined)) {
+                writeln!(f, "(let c__{} {})", id, term(node))?;
+                defined.insert(id, node);
+                let mut parents: Vec<Id> = class.parents().map(|p| egraph.find(p)).collect();
+                parents.sort_unstable_by(|a, b| b.cmp(a));
+                todo.extend(parents);
+            }
+        }
+
+        let mut dropped = vec![];
+        for class in &classes {
+            let first = match defined.get(&class.id) {
+                Some(first) => first,
+                None => {
+                    dropped.push(format!("c__{}", class.id));
+                    continue;
+                }
+            };
+            for node in class.iter() {
+                if node != *first && node.all(|child| defined.contains_key(&egraph.find(child))) {
+                    writeln!(f, "(union c__{} {})", class.id, term(node))?;
+                }
+            }
+        }
+        if !dropped.is_empty() {
+            let dropped = dropped.join(" ");
+            writeln!(f, "; e-classes without an acyclic term: {}", dropped)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_plain(op: &str) -> bool {
+    let mut chars = op.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !op.contains("__")
+        && !op.ends_with('_')
+}
+
+fn encode_op(op: &str, sort: &str) -> String {
+    if is_plain(op) && op != sort && !RESERVED.contains(&op) {
+        return op.to_string();
+    }
+    let mut name = String::from("op__");
+    for c in op.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else {
+            name += &format!("_{:x}x", c as u32);
+        }
+    }
+    name
+}
+
+fn decode_name(name: &str) -> Option<String> {
+    let mut parts: Vec<&str> = name.split("__").collect();
+    let is_arity = |s: &str| {
+        s.strip_prefix('n').map_or(false, |n| {
+            !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+        })
+    };
+    if parts.len() > 1 && is_arity(parts[parts.len() - 1]) && parts != ["op", parts[1]] {
+        parts.pop();
+    }
+    match parts[..] {
+        [op] if !op.is_empty() => Some(op.to_string()),
+        ["op", escaped] if !escaped.is_empty() => {
+            let mut op = String::new();
+            let mut chars = escaped.chars();
+            while let Some(c) = chars.next() {
+                if c.is_ascii_alphanumeric() {
+                    op.push(c);
+                } else if c == '_' {
+                    let hex: String = chars.by_ref().take_while(|&c| c != 'x').collect();
+                    op.push(
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)?,
+                    );
+                } else {
+                    return None;
+                }
+            }
+            Some(op)
+        }
+        _ => None,
+    }
+}
+
+/// An error type for failures when parsing egglog rules with
+/// [`parse_egglog_rules`].
+#[derive(Debug, Error)]
+pub enum EgglogParseError<E> {
+    /// The input is not a sequence of well-formed s-expressions.
+    #[error("bad egglog syntax: {0}")]
+    Syntax(String),
+
+    /// A rule uses an egglog feature that can't be turned into a [`Rewrite`],
+    /// like a `:when` condition.
+    #[error("unsupported egglog rule: {0}")]
+    Unsupported(String),
+
+    /// A constructor name is not a valid escaped operator,
+    /// see the [operator names](Egglog#operator-names).
+    #[error("bad constructor name: {0}")]
+    BadName(String),
+
+    /// Attempting to parse an operator into a value of type `L` failed.
+    #[error(transparent)]
+    BadOp(E),
+
+    /// The rule could not be made into a [`Rewrite`],
+    /// see [`Rewrite::new`].
+    #[error("{0}")]
+    BadRule(String),
+}
+
+enum Form {
+    Atom(String),
+    Str(String),
+    List(Vec<Form>),
+}
+
+impl Display for Form {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Form::Atom(s) => write!(f, "{}", s),
+            Form::Str(s) => write!(f, "{:?}", s),
+            Form::List(forms) => {
+                write!(f, "(")?;
+                for (i, form) in forms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", form)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn parse_forms(program: &str) -> Result<Vec<Form>, String> {
+    let mut stack: Vec<Vec<Form>> = vec![vec![]];
+    let mut chars = program.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => stack.push(vec![]),
+            ')' => {
+                if stack.len() == 1 {
+                    return Err("unexpected )".into());
+                }
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Form::List(list));
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err("unterminated string".into()),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                stack.last_mut().unwrap().push(Form::Str(s));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "();\"".contains(c) {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+                stack.last_mut().unwrap().push(Form::Atom(atom));
+            }
+        }
+    }
+    if stack.len() > 1 {
+        return Err("missing )".into());
+    }
+    Ok(stack.pop().unwrap())
+}
+
+fn is_literal(atom: &str) -> bool {
+    let digits = atom.strip_prefix('-').unwrap_or(atom);
+    digits.starts_with(|c: char| c.is_ascii_digit()) || atom == "true" || atom == "false"
+}
+
+fn parse_pattern<L: FromOp>(
+    form: &Form,
+    ast: &mut PatternAst<L>,
+) -> Result<Id, EgglogParseError<L::Error>> {
+    use EgglogParseError::*;
+    let node = match form {
+        Form::Atom(atom) if !is_literal(atom) => {
+            let var = format!("?{}", atom)
+                .parse()
+                .map_err(|_| Syntax(atom.clone()))?;
+            ENodeOrVar::Var(var)
+        }
+        Form::Atom(literal) | Form::Str(literal) => {
+            ENodeOrVar::ENode(L::from_op(literal, vec![]).map_err(BadOp)?)
+        }
+        Form::List(list) => match &list[..] {
+            [Form::Atom(name), args @ ..] => {
+                let op = decode_name(name).ok_or_else(|| BadName(name.clone()))?;
+                let children = args
+                    .iter()
+                    .map(|arg| parse_pattern(arg, ast))
+                    .collect::<Result<_, _>>()?;
+                ENodeOrVar::ENode(L::from_op(&op, children).map_err(BadOp)?)
+            }
+            _ => return Err(Syntax(format!("expected a term, found {}", form))),
+        },
+    };
+    Ok(ast.add(node))
+}
+
+/**
+Parses the `rewrite` and `birewrite` rules of an [egglog] program.
+
+Each `(rewrite lhs rhs)` becomes a [`Rewrite`] named after its source text,
+and each `(birewrite lhs rhs)` also becomes the reverse rewrite, whose name
+ends in `-rev`, like with [`rewrite!`].
+In the terms, bare identifiers are pattern variables, numbers, booleans, and
+strings are leaves, and constructor names are unescaped as described in
+[operator names](Egglog#operator-names).
+Other commands, like declarations, are skipped;
+rules with options like `:when` or `:ruleset` are rejected.
+
+# Example
+
+```
+use egg::*;
+
+let program = "
+    (datatype Math (op___2bx Math Math) (Zero))
+    ; addition is commutative
+    (birewrite (op___2bx a b) (op___2bx b a))
+    (rewrite (op___2bx a (Zero)) a)
+";
+let rules: Vec<Rewrite<SymbolLang, ()>> = parse_egglog_rules(program).unwrap();
+assert_eq!(rules.len(), 3);
+assert_eq!(rules[1].name.as_str(), "(birewrite (op___2bx a b) (op___2bx b a))-rev");
+
+let start = "(+ Zero x)".parse().unwrap();
+let runner = Runner::default().with_expr(&start).run(&rules);
+let x = runner.egraph.lookup_expr(&"x".parse().unwrap());
+assert_eq!(x, Some(runner.egraph.find(runner.roots[0])));
+```
+
+[egglog]: https://github.com/egraphs-good/egglog
+**/
+pub fn parse_egglog_rules<L, N>(
+    program: &str,
+) -> Result<Vec<Rewrite<L, N>>, EgglogParseError<L::Error>>
+where
+    L: Language + FromOp + Sync + Send + 'static,
+    N: Analysis<L>,
+{
+    use EgglogParseError::*;
+    let mut rules = vec![];
+    for form in parse_forms(program).map_err(Syntax)? {
+        let list = match &form {
+            Form::List(list) => list,
+            _ => continue,
+        };
+        let bidirectional = match list.first() {
+            Some(Form::Atom(command)) if command == "rewrite" => false,
+            Some(Form::Atom(command)) if command == "birewrite" => true,
+            _ => continue,
+        };
+        let (lhs, rhs) = match &list[1..] {
+            [lhs, rhs] => (lhs, rhs),
+            [_, _, option, ..] => {
+                return Err(Unsupported(format!("option {} in {}", option, form)))
+            }
+            _ => return Err(Syntax(format!("expected two terms in {}", form))),
+        };
+
+        let mut lhs_ast = PatternAst::default();
+        parse_pattern(lhs, &mut lhs_ast)?;
+        let mut rhs_ast = PatternAst::default();
+        parse_pattern(rhs, &mut rhs_ast)?;
+        let (lhs, rhs) = (Pattern::new(lhs_ast), Pattern::new(rhs_ast));
+
+        let name = form.to_string();
+        let reverse = if bidirectional {
+            let reverse = Rewrite::new(name.clone() + "-rev", rhs.clone(), lhs.clone());
+            Some(reverse.map_err(BadRule)?)
+        } else {
+            None
+        };
+        rules.push(Rewrite::new(name, lhs, rhs).map_err(BadRule)?);
+        rules.extend(reverse);
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_name, encode_op};
+    use crate::{SymbolLang as S, *};
+
+    #[test]
+    fn egglog_names() {
+        for op in [
+            "+", "x", "my_op", "a-b", "a_", "a__b", "0", "n2", "op", "let", "Expr", "é",
+        ] {
+            let name = encode_op(op, "Expr");
+            assert_eq!(decode_name(&name).as_deref(), Some(op), "{}", name);
+            let overloaded = format!("{}__n3", name);
+            assert_eq!(decode_name(&overloaded).as_deref(), Some(op));
+        }
+        assert_eq!(encode_op("my_op", "Expr"), "my_op");
+        assert_eq!(encode_op("Expr", "Expr"), "op__Expr");
+        assert_eq!(encode_op("0", "Expr"), "op__0");
+        assert_eq!(decode_name("a__b__c"), None);
+        assert_eq!(decode_name("op___zzx"), None);
+    }
+
+    #[test]
+    fn egglog_export() {
+        let mut egraph = EGraph::<S, ()>::default();
+        let x = egraph.add_expr(&"(- x)".parse().unwrap());
+        let y = egraph.add_expr(&"(- (f y) y)".parse().unwrap());
+        // a cycle: (f y) = y
+        let fy = egraph.add_expr(&"(f y)".parse().unwrap());
+        let y_leaf = egraph.add(S::leaf("y"));
+        egraph.union(fy, y_leaf);
+        egraph.union(x, y);
+        egraph.rebuild();
+
+        let program = egraph.egglog().to_string();
+        let lines: Vec<&str> = program.lines().collect();
+        assert_eq!(
+            lines[..6],
+            [
+                "(datatype Expr",
+                "  (op___2dx__n1 Expr)",
+                "  (op___2dx__n2 Expr Expr)",
+                "  (f Expr)",
+                "  (x)",
+                "  (y)",
+            ]
+        );
+        let x_class = egraph.find(x);
+        let y_class = egraph.find(y_leaf);
+        assert!(lines.contains(&&*format!("(let c__{} (y))", y_class)));
+        assert!(lines.contains(&&*format!("(union c__{} (f c__{}))", y_class, y_class)));
+        assert!(lines.contains(&&*format!(
+            "(union c__{} (op___2dx__n2 c__{} c__{}))",
+            x_class, y_class, y_class
+        )));
+        assert_eq!(lines.iter().filter(|l| l.starts_with("(let")).count(), 3);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("(union")).count(), 2);
+        // every e-class was defined
+        assert!(!program.contains(';'));
+    }
+
+    #[test]
+    fn egglog_export_pruned() {
+        let fold = ConstantFolding::new(
+            |enode: &S, args: &[Option<i32>]| match (enode.op.as_str(), args) {
+                ("+", [a, b]) => Some((*a)? + (*b)?),
+                (op, []) => op.parse().ok(),
+                _ => None,
+            },
+            |c: &i32| S::leaf(c.to_string()),
+        )
+        .with_pruning();
+        let mut egraph = EGraph::new(fold);
+        let f = egraph.add_expr(&"(f (+ 1 2))".parse().unwrap());
+        egraph.rebuild();
+
+        // the first e-node of the e-class of `3` was pruned away
+        let three = egraph.lookup(S::leaf("3")).unwrap();
+        let program = egraph.egglog().to_string();
+        assert!(!program.contains("op___2bx"));
+        assert!(program.contains(&format!("(let c__{} (op__3))", three)));
+        let f = egraph.find(f);
+        assert!(program.contains(&format!("(let c__{} (f c__{}))", f, three)));
+        assert!(!program.contains(';'));
+    }
+
+    #[test]
+    fn egglog_rules() {
+        let mut egraph = EGraph::<S, ()>::default();
+        egraph.add_expr(&"(- (* a 2) (- b))".parse().unwrap());
+        egraph.rebuild();
+        let program = format!(
+            "{}
+            (rewrite (op___2ax x 2) (op___2bx x x))
+            (birewrite (op___2dx__n2 x (op___2dx__n1 y)) (op___2bx x y))
+            (rule ((= e (op___2bx x x))) ((union e (op___2ax x 2))))
+            (rewrite (f x \"a string\" -1) (g x 1.5 true))
+            (run 10)",
+            egraph.egglog()
+        );
+        let rules: Vec<Rewrite<S, ()>> = parse_egglog_rules(&program).unwrap();
+        let names: Vec<&str> = rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "(rewrite (op___2ax x 2) (op___2bx x x))",
+                "(birewrite (op___2dx__n2 x (op___2dx__n1 y)) (op___2bx x y))",
+                "(birewrite (op___2dx__n2 x (op___2dx__n1 y)) (op___2bx x y))-rev",
+                "(rewrite (f x \"a string\" -1) (g x 1.5 true))",
+            ]
+        );
+        let lhs: Pattern<S> = "(f ?x \"a string\" -1)".parse().unwrap();
+        assert_eq!(rules[3].searcher.get_pattern_ast(), Some(&lhs.ast));
+
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        let egraph = runner.egraph;
+        let root = egraph.lookup_expr(&"(- (* a 2) (- b))".parse().unwrap());
+        let sum = egraph.lookup_expr(&"(+ (+ a a) b)".parse().unwrap());
+        assert!(root.is_some());
+        assert_eq!(root, sum);
+    }
+
+    #[test]
+    fn egglog_rule_errors() {
+        use EgglogParseError::*;
+        let parse = |program| parse_egglog_rules::<S, ()>(program).map(|_| ());
+        assert!(matches!(parse("(rewrite (f x) x"), Err(Syntax(_))));
+        assert!(matches!(parse("(rewrite (f x) x))"), Err(Syntax(_))));
+        assert!(matches!(parse("(rewrite (f x))"), Err(Syntax(_))));
+        assert!(matches!(parse("(rewrite ((f) x) x)"), Err(Syntax(_))));
+        assert!(matches!(
+            parse("(rewrite (f x) x :when ((= x 1)))"),
+            Err(Unsupported(_))
+        ));
+        assert!(matches!(parse("(rewrite (a__b__c x) x)"), Err(BadName(_))));
+        assert!(matches!(parse("(rewrite (f x) y)"), Err(BadRule(_))));
+        assert!(matches!(parse("; nothing (rewrite\n(sort E)"), Ok(())));
+    }
+}
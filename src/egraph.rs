@@ -1,3 +1,4 @@
+use crate::machine::AcTerm;
 use crate::*;
 use std::{
     borrow::BorrowMut,
@@ -85,6 +86,22 @@ pub struct EGraph<L: Language, N: Analysis<L>> {
     /// Only manually set it if you know what you're doing.
     #[cfg_attr(feature = "serde-1", serde(skip))]
     pub clean: bool,
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    merge_check: Option<MergeCheck<L, N>>,
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    metadata: MetadataTables,
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    provenance: Option<ProvenanceTable>,
+    #[cfg_attr(feature = "serde-1", serde(default = "default_max_ac_operands"))]
+    pub(crate) max_ac_operands: usize,
+}
+
+type MergeCheck<L, N> =
+    fn(&mut N, &<N as Analysis<L>>::Data, &<N as Analysis<L>>::Data) -> Result<(), String>;
+
+#[cfg(feature = "serde-1")]
+fn default_max_ac_operands() -> usize {
+    crate::machine::DEFAULT_MAX_AC_OPERANDS
 }
 
 #[cfg(feature = "serde-1")]
@@ -122,6 +139,10 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             memo: Default::default(),
             analysis_pending: Default::default(),
             classes_by_op: Default::default(),
+            merge_check: None,
+            metadata: Default::default(),
+            provenance: None,
+            max_ac_operands: crate::machine::DEFAULT_MAX_AC_OPERANDS,
         }
     }
 
@@ -194,12 +215,125 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.classes.len()
     }
 
+    /// Checks that every merge of [`Analysis::Data`] behaves like the join
+    /// of a semilattice (see [`Lattice`]).
+    ///
+    /// Before each merge, the two values are also merged the other way
+    /// around, with themselves, and with the result, to check that
+    /// [`Analysis::merge`] is commutative, idempotent and monotone,
+    /// and that the [`DidMerge`] flags it returns are correct.
+    /// On a violation, this panics with the offending e-class and data.
+    ///
+    /// Since this calls [`Analysis::merge`] several extra times on cloned
+    /// data, it is meant for debugging and tests.
+    pub fn with_merge_checks(mut self) -> Self
+    where
+        N::Data: Clone + PartialEq,
+    {
+        self.merge_check = Some(crate::lattice::check_merge::<L, N>);
+        self
+    }
+
+    /// Adds a side table for metadata of type `T` on the e-classes,
+    /// like source locations or provenance, that doesn't need to be part of
+    /// the [`Analysis`].
+    ///
+    /// The metadata is set with [`set_metadata`](EGraph::set_metadata) and
+    /// read with [`metadata`](EGraph::metadata). Its e-class is always looked
+    /// up with [`find`](EGraph::find), and when two e-classes are unioned,
+    /// including during [`rebuild`](EGraph::rebuild), their metadata is
+    /// combined with `merge`.
+    /// There is one table per type, so wrap the metadata in a new type to
+    /// have several tables of the same underlying type.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    ///
+    /// #[derive(Debug, Clone, PartialEq)]
+    /// struct Line(usize);
+    ///
+    /// let mut egraph = EGraph::<S, ()>::default()
+    ///     .with_metadata(MetadataMerge::<Vec<Line>>::concat());
+    /// let a = egraph.add_expr(&"(+ x 1)".parse().unwrap());
+    /// let b = egraph.add_expr(&"(+ 1 x)".parse().unwrap());
+    /// egraph.set_metadata(a, vec![Line(3)]);
+    /// egraph.set_metadata(b, vec![Line(7)]);
+    ///
+    /// egraph.union(a, b);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.metadata(b), Some(&vec![Line(3), Line(7)]));
+    /// ```
+    pub fn with_metadata<T>(mut self, merge: MetadataMerge<T>) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.metadata.insert_table(merge);
+        self
+    }
+
+    /// Sets the metadata of type `T` of an e-class, returning the old one.
+    ///
+    /// Panics if there is no table for `T`, see [`EGraph::with_metadata`].
+    pub fn set_metadata<T: 'static>(&mut self, id: Id, value: T) -> Option<T> {
+        let id = self.find(id);
+        self.metadata.insert(id, value)
+    }
+
+    /// Returns the metadata of type `T` of an e-class, if any.
+    ///
+    /// Panics if there is no table for `T`, see [`EGraph::with_metadata`].
+    pub fn metadata<T: 'static>(&self, id: Id) -> Option<&T> {
+        self.metadata.get(self.find(id))
+    }
+
+    /// Returns a mutable reference to the metadata of type `T` of an e-class, if any.
+    ///
+    /// Panics if there is no table for `T`, see [`EGraph::with_metadata`].
+    pub fn metadata_mut<T: 'static>(&mut self, id: Id) -> Option<&mut T> {
+        let id = self.find(id);
+        self.metadata.get_mut(id)
+    }
+
+    /// Removes the metadata of type `T` of an e-class, returning it.
+    ///
+    /// Panics if there is no table for `T`, see [`EGraph::with_metadata`].
+    pub fn remove_metadata<T: 'static>(&mut self, id: Id) -> Option<T> {
+        let id = self.find(id);
+        self.metadata.remove(id)
+    }
+
+    /// Sets the most operands that an e-class of an
+    /// [`AC`](AcKind::AC) operator is flattened into when e-matching,
+    /// which is 6 by default.
+    ///
+    /// Matching considers every way of splitting the operands in two,
+    /// so it takes time exponential in this limit.
+    /// Matches that need more operands are dropped, and a warning is logged.
+    ///
+    /// Panics if `max` is less than 2 or more than 32.
+    pub fn with_max_ac_operands(mut self, max: usize) -> Self {
+        assert!(
+            (2..=32).contains(&max),
+            "the most AC operands should be between 2 and 32, not {}",
+            max
+        );
+        self.max_ac_operands = max;
+        self
+    }
+
     /// Enable explanations for this `EGraph`.
     /// This allows the egraph to explain why two expressions are
     /// equivalent with the [`explain_equivalence`](EGraph::explain_equivalence) function.
+    ///
+    /// This switches compact explanations from
+    /// [`with_compact_explanations_enabled`](EGraph::with_compact_explanations_enabled)
+    /// back to full ones, which panics if the egraph is not empty.
     pub fn with_explanations_enabled(mut self) -> Self {
-        if self.explain.is_some() {
-            return self;
+        if let Some(explain) = &self.explain {
+            if !explain.compact {
+                return self;
+            }
         }
         if self.total_size() > 0 {
             panic!("Need to set explanations enabled before adding any expressions to the egraph.");
@@ -208,6 +342,30 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self
     }
 
+    /// Like [`with_explanations_enabled`](EGraph::with_explanations_enabled), but
+    /// uses a compact representation that uses less memory.
+    ///
+    /// Only the proof forest (one connection per e-node) is stored,
+    /// and explanations are reconstructed from it on demand.
+    /// As a consequence, explanations are never length optimized
+    /// (see [`with_explanation_length_optimization`](EGraph::with_explanation_length_optimization)).
+    ///
+    /// This switches full explanations from
+    /// [`with_explanations_enabled`](EGraph::with_explanations_enabled)
+    /// to compact ones, which panics if the egraph is not empty.
+    pub fn with_compact_explanations_enabled(mut self) -> Self {
+        if let Some(explain) = &self.explain {
+            if explain.compact {
+                return self;
+            }
+        }
+        if self.total_size() > 0 {
+            panic!("Need to set explanations enabled before adding any expressions to the egraph.");
+        }
+        self.explain = Some(Explain::new_compact());
+        self
+    }
+
     /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
     /// Use this function to turn this algorithm off.
     pub fn without_explanation_length_optimization(mut self) -> Self {
@@ -221,6 +379,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
     /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
     /// Use this function to turn this algorithm on again if you have turned it off.
+    /// This has no effect with [`with_compact_explanations_enabled`](EGraph::with_compact_explanations_enabled).
     pub fn with_explanation_length_optimization(mut self) -> Self {
         if let Some(explain) = &mut self.explain {
             explain.optimize_explanation_lengths = true;
@@ -358,6 +517,9 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     /// Calling this function on an uncanonical `Id` returns a representative based on the how it
     /// was obtained (see [`add_uncanoncial`](EGraph::add_uncanonical),
     /// [`add_expr_uncanonical`](EGraph::add_expr_uncanonical))
+    ///
+    /// Like [`Extractor::find_best`], this does not put operators declared AC
+    /// in a canonical form; use [`RecExpr::normalize_ac`] on the result for that.
     pub fn id_to_expr(&self, id: Id) -> RecExpr<L> {
         let mut res = Default::default();
         let mut cache = Default::default();
@@ -440,6 +602,122 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self
     }
 
+    /// Enable provenance tracking for this `EGraph`.
+    ///
+    /// This records which rule first added each e-node, and in which
+    /// [`Runner`] iteration, see [`Provenance`].
+    /// Unlike explanations, this only stores a little data for each e-node,
+    /// so it is cheap enough to find which rules make an e-graph blow up.
+    /// E-nodes already in the e-graph are recorded as added by no rule.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, rewrite as rw};
+    ///
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rw!("mul-commutes"; "(* ?x ?y)" => "(* ?y ?x)"),
+    ///     rw!("mul-two"; "(* ?x 2)" => "(<< ?x 1)"),
+    /// ];
+    /// let mut egraph = EGraph::default().with_provenance_enabled();
+    /// let start = egraph.add_expr(&"(* a 2)".parse().unwrap());
+    /// let egraph = Runner::default().with_egraph(egraph).run(rules).egraph;
+    ///
+    /// let shift = "(<< a 1)".parse().unwrap();
+    /// let shift = egraph.lookup_expr(&shift).unwrap();
+    /// assert_eq!(egraph.find(shift), egraph.find(start));
+    /// let shift_node = egraph[shift].iter().find(|n| n.op == "<<".into()).unwrap();
+    /// let provenance = egraph.enode_provenance(shift_node).unwrap();
+    /// assert_eq!(provenance.rule, Some("mul-two".into()));
+    /// assert_eq!(provenance.iteration, 0);
+    ///
+    /// let counts = egraph.provenance_counts();
+    /// assert_eq!(counts[&Some("mul-commutes".into())], 1);
+    /// assert_eq!(counts[&Some("mul-two".into())], 2);
+    /// assert_eq!(counts[&None], 3);
+    /// ```
+    pub fn with_provenance_enabled(mut self) -> Self {
+        if self.provenance.is_none() {
+            let nodes = vec![Provenance::default(); self.nodes.len()];
+            self.provenance = Some(ProvenanceTable {
+                current: Provenance::default(),
+                nodes,
+            });
+        }
+        self
+    }
+
+    /// Check if provenance tracking is enabled.
+    pub fn is_provenance_enabled(&self) -> bool {
+        self.provenance.is_some()
+    }
+
+    /// Sets the [`Provenance`] of the e-nodes added from now on.
+    ///
+    /// The [`Runner`] calls this before applying each rule, so this is
+    /// only needed for custom ways of running rules.
+    /// Does nothing if provenance tracking is disabled.
+    pub fn set_current_provenance(&mut self, rule: Option<Symbol>, iteration: usize) {
+        if let Some(provenance) = &mut self.provenance {
+            provenance.current = Provenance { rule, iteration };
+        }
+    }
+
+    /// Returns the [`Provenance`] of the e-node that was added with the
+    /// given (uncanonical) id, see [`EGraph::nodes`].
+    ///
+    /// Returns `None` if provenance tracking is disabled.
+    pub fn node_provenance(&self, id: Id) -> Option<Provenance> {
+        let provenance = self.provenance.as_ref()?;
+        provenance.nodes.get(usize::from(id)).copied()
+    }
+
+    /// Returns the [`Provenance`] of an e-node, that of the first e-node
+    /// added to the e-graph that is the same up to canonicalization.
+    ///
+    /// This looks through all the e-nodes, so it is slow.
+    /// Returns `None` if provenance tracking is disabled or if the e-node
+    /// is not in the e-graph.
+    pub fn enode_provenance(&self, enode: &L) -> Option<Provenance> {
+        let provenance = self.provenance.as_ref()?;
+        let enode = enode.clone().map_children(|id| self.find(id));
+        self.nodes
+            .iter()
+            .position(|node| node.clone().map_children(|id| self.find(id)) == enode)
+            .map(|i| provenance.nodes[i])
+    }
+
+    /// Returns the [`Provenance`] of every canonical e-node in the e-graph,
+    /// as given by [`EGraph::enode_provenance`].
+    ///
+    /// Returns an empty map if provenance tracking is disabled.
+    pub fn provenance_by_enode(&self) -> HashMap<L, Provenance> {
+        let mut map = HashMap::default();
+        if let Some(provenance) = &self.provenance {
+            for (node, p) in self.nodes.iter().zip(&provenance.nodes) {
+                let node = node.clone().map_children(|id| self.find(id));
+                map.entry(node).or_insert(*p);
+            }
+        }
+        map
+    }
+
+    /// Counts the e-nodes that each rule added, with `None` counting
+    /// the e-nodes that were not added by a rule.
+    ///
+    /// This counts all the e-nodes ever added, even those that are now the
+    /// same as others up to canonicalization, so it is a measure of how much
+    /// work each rule caused.
+    /// Returns an empty map if provenance tracking is disabled.
+    pub fn provenance_counts(&self) -> HashMap<Option<Symbol>, usize> {
+        let mut counts = HashMap::default();
+        if let Some(provenance) = &self.provenance {
+            for p in &provenance.nodes {
+                *counts.entry(p.rule).or_default() += 1;
+            }
+        }
+        counts
+    }
+
     /// Check if explanations are enabled.
     pub fn are_explanations_enabled(&self) -> bool {
         self.explain.is_some()
@@ -509,6 +787,58 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         }
     }
 
+    /// Get an explanation for why `expr` exists in the e-graph.
+    ///
+    /// The explanation starts from a term that was added with
+    /// [`add_expr`](EGraph::add_expr) (for example by [`Runner::with_expr`]),
+    /// or a sub-term of one, in the same e-class, and rewrites it to `expr`.
+    /// This can be used to justify the terms returned by an [`Extractor`].
+    ///
+    /// Panics if `expr` is not represented in the e-graph, or if no term
+    /// in its e-class was added with [`add_expr`](EGraph::add_expr).
+    ///
+    /// # Example
+    /// ```
+    /// use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("mul-one"; "(* ?x 1)" => "?x")];
+    /// let mut runner = Runner::default()
+    ///     .with_explanations_enabled()
+    ///     .with_expr(&"(+ (* a 1) b)".parse().unwrap())
+    ///     .run(rules);
+    ///
+    /// let extractor = Extractor::new(&runner.egraph, AstSize);
+    /// let (_, best) = extractor.find_best(runner.roots[0]);
+    /// assert_eq!(best.to_string(), "(+ a b)");
+    ///
+    /// let mut explanation = runner.egraph.explain_existence(&best);
+    /// assert_eq!(
+    ///     explanation.get_flat_strings(),
+    ///     vec!["(+ (* a 1) b)", "(+ (Rewrite=> mul-one a) b)"]
+    /// );
+    /// ```
+    pub fn explain_existence(&mut self, expr: &RecExpr<L>) -> Explanation<L> {
+        if self.lookup_expr(expr).is_none() {
+            panic!(
+                "Tried to explain the existence of {:?}, which is not in the e-graph",
+                expr
+            );
+        }
+        let id = self.add_expr_uncanonical(expr);
+        let class = self.find(id);
+        let input = if let Some(explain) = &self.explain {
+            explain.find_input(class)
+        } else {
+            panic!("Use runner.with_explanations_enabled() or egraph.with_explanations_enabled() before running to get explanations.")
+        };
+        match input {
+            Some(input) => self.explain_id_equivalence(input, id),
+            None => panic!(
+                "Tried to explain the existence of {:?}, but no term in its e-class was added with add_expr",
+                expr
+            ),
+        }
+    }
+
     /// Get an explanation for why an expression matches a pattern.
     pub fn explain_matches(
         &mut self,
@@ -570,6 +900,16 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             egraph: self,
             config: vec![],
             use_anchors: true,
+            provenance_colors: false,
+        }
+    }
+
+    /// Creates an [`Egglog`] to write this egraph as an egglog program.
+    /// See [`Egglog`].
+    pub fn egglog(&self) -> Egglog<L, N> {
+        Egglog {
+            egraph: self,
+            sort: "Expr".into(),
         }
     }
 }
@@ -645,6 +985,11 @@ where
                 .map(|(k, v)| (self.map_discriminant(k), v))
                 .collect(),
             clean: src_egraph.clean,
+            // the predicates are on `A::Data`, so they cannot be carried over
+            merge_check: None,
+            metadata: src_egraph.metadata,
+            provenance: src_egraph.provenance,
+            max_ac_operands: src_egraph.max_ac_operands,
         }
     }
 }
@@ -815,16 +1160,30 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     /// assert_eq!(plus, egraph.add_expr(&plus_recexpr));
     /// ```
     ///
+    /// When explanations are enabled, the added term and each of its sub-terms
+    /// are recorded as inputs of the e-graph, see
+    /// [`explain_existence`](EGraph::explain_existence).
+    ///
     /// [`add_expr`]: EGraph::add_expr()
     pub fn add_expr(&mut self, expr: &RecExpr<L>) -> Id {
-        let id = self.add_expr_uncanonical(expr);
-        self.find(id)
+        let ids = self.add_expr_ids(expr);
+        if let Some(explain) = &mut self.explain {
+            for &id in &ids {
+                explain.add_input(id, self.unionfind.find(id));
+            }
+        }
+        self.find(*ids.last().unwrap())
     }
 
     /// Similar to [`add_expr`](EGraph::add_expr) but the `Id` returned may not be canonical
     ///
     /// Calling [`id_to_expr`](EGraph::id_to_expr) on this `Id` return a copy of `expr` when explanations are enabled
     pub fn add_expr_uncanonical(&mut self, expr: &RecExpr<L>) -> Id {
+        *self.add_expr_ids(expr).last().unwrap()
+    }
+
+    // adds `expr` uncanonically, returning the ids of all of its nodes
+    fn add_expr_ids(&mut self, expr: &RecExpr<L>) -> Vec<Id> {
         let mut new_ids = Vec::with_capacity(expr.len());
         let mut new_node_q = Vec::with_capacity(expr.len());
         for node in expr {
@@ -838,7 +1197,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             }
             new_ids.push(next_id);
         }
-        *new_ids.last().unwrap()
+        new_ids
     }
 
     /// Adds a [`Pattern`] and a substitution to the [`EGraph`], returning
@@ -854,18 +1213,30 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     /// Like [`add_uncanonical`](EGraph::add_uncanonical), when explanations are enabled calling
     /// Calling [`id_to_expr`](EGraph::id_to_expr) on this `Id` return an correspond to the
     /// instantiation of the pattern
-    fn add_instantiation_noncanonical(&mut self, pat: &PatternAst<L>, subst: &Subst) -> Id {
+    pub(crate) fn add_instantiation_noncanonical(
+        &mut self,
+        pat: &PatternAst<L>,
+        subst: &Subst,
+    ) -> Id {
+        let seqs = crate::pattern::has_sequence_vars(pat.as_ref());
         let mut new_ids = Vec::with_capacity(pat.len());
         let mut new_node_q = Vec::with_capacity(pat.len());
         for node in pat {
             match node {
+                // spliced into the parent by `instantiate_node`
+                ENodeOrVar::Var(var) if var.is_sequence() => {
+                    new_ids.push(Id::from(0));
+                    new_node_q.push(false);
+                }
                 ENodeOrVar::Var(var) => {
                     let id = self.find(subst[*var]);
                     new_ids.push(id);
                     new_node_q.push(false);
                 }
                 ENodeOrVar::ENode(node) => {
-                    let new_node = node.clone().map_children(|i| new_ids[usize::from(i)]);
+                    let new_node = crate::pattern::instantiate_node(pat, seqs, node, subst, |i| {
+                        new_ids[usize::from(i)]
+                    });
                     let size_before = self.unionfind.size();
                     let next_id = self.add_uncanonical(new_node);
                     if self.unionfind.size() > size_before {
@@ -1003,13 +1374,16 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             let id = self.find(existing_id);
             // when explanations are enabled, we need a new representative for this expr
             if let Some(explain) = self.explain.as_mut() {
-                if let Some(existing_explain) = explain.uncanon_memo.get(&original) {
-                    *existing_explain
+                if let Some(existing_explain) = explain.uncanonical_id(&original, &self.nodes) {
+                    existing_explain
                 } else {
                     let new_id = self.unionfind.make_set();
                     explain.add(original.clone(), new_id);
                     debug_assert_eq!(Id::from(self.nodes.len()), new_id);
                     self.nodes.push(original);
+                    if let Some(provenance) = &mut self.provenance {
+                        provenance.nodes.push(provenance.current);
+                    }
                     self.unionfind.union(id, new_id);
                     explain.union(existing_id, new_id, Justification::Congruence);
                     new_id
@@ -1043,6 +1417,9 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
         debug_assert_eq!(Id::from(self.nodes.len()), id);
         self.nodes.push(original);
+        if let Some(provenance) = &mut self.provenance {
+            provenance.nodes.push(provenance.current);
+        }
 
         // add this enode to the parent lists of its children
         enode.for_each(|child| {
@@ -1115,6 +1492,81 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.perform_union(from, to, Some(Justification::Rule(reason.into())))
     }
 
+    /// Adds the terms that a match of `pattern` in `eclass` with `subst`
+    /// sees modulo AC (see [`Language::ac_kind`]) but that may be new to the
+    /// e-graph, each unioned with a term of e-nodes it is equal to modulo AC.
+    /// The unions are justified by [`Justification::Ac`].
+    ///
+    /// Afterwards, the instantiation of `pattern` is in `eclass` by congruence,
+    /// so explanations can justify the match.
+    pub(crate) fn union_modulo_ac(
+        &mut self,
+        pattern: &PatternAst<L>,
+        eclass: Id,
+        subst: &Subst,
+    ) -> bool {
+        let steps = match crate::machine::ac_witnesses(self, pattern, eclass, subst) {
+            Some(steps) => steps,
+            None => {
+                warn!(
+                    "Could not find the match of {:?} in {} again",
+                    pattern, eclass
+                );
+                return false;
+            }
+        };
+        let mut did_something = false;
+        for [from, to] in &steps {
+            let from = self.add_ac_term(from);
+            let to = self.add_ac_term(to);
+            did_something |= self.perform_union(from, to, Some(Justification::Ac));
+        }
+        did_something
+    }
+
+    fn add_ac_term(&mut self, term: &AcTerm<L>) -> Id {
+        match term {
+            AcTerm::Class(id) => *id,
+            AcTerm::Node(op, children) => {
+                let mut node = op.clone();
+                let [left, right] = children.as_ref();
+                let ids = [self.add_ac_term(left), self.add_ac_term(right)];
+                node.children_mut().copy_from_slice(&ids);
+                self.add_uncanonical(node)
+            }
+        }
+    }
+
+    /// Unions two e-classes on behalf of an [`Analysis`], typically from
+    /// [`Analysis::modify`].
+    ///
+    /// Like [`union_trusted`](EGraph::union_trusted), but the union is justified
+    /// by a structured [`Justification::Analysis`], which shows up in explanations
+    /// and can be checked with [`Explanation::check_proof_with_analysis`].
+    /// `from` should be the (uncanonical) term whose analysis data triggered the union.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default().with_explanations_enabled();
+    /// let from = egraph.add_expr(&"(+ 1 2)".parse().unwrap());
+    /// let to = egraph.add_expr(&"3".parse().unwrap());
+    /// egraph.union_analysis(from, to, AnalysisJustification::new("constant_fold", from, "3"));
+    /// egraph.rebuild();
+    /// let mut explanation = egraph.explain_id_equivalence(from, to);
+    /// assert_eq!(
+    ///     explanation.get_flat_strings(),
+    ///     vec!["(+ 1 2)", "(Analysis=> constant_fold[3] 3)"]
+    /// );
+    /// ```
+    pub fn union_analysis(
+        &mut self,
+        from: Id,
+        to: Id,
+        justification: AnalysisJustification,
+    ) -> bool {
+        self.perform_union(from, to, Some(Justification::Analysis(justification)))
+    }
+
     /// Unions two eclasses given their ids.
     ///
     /// The given ids need not be canonical.
@@ -1146,13 +1598,16 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         let mut id1 = self.find_mut(enode_id1);
         let mut id2 = self.find_mut(enode_id2);
         if id1 == id2 {
-            if let Some(Justification::Rule(_)) = rule {
+            if let Some(Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac) =
+                rule
+            {
                 if let Some(explain) = &mut self.explain {
                     explain.alternate_rewrite(enode_id1, enode_id2, rule.unwrap());
                 }
             }
             return false;
         }
+        let (first, second) = (id1, id2);
         // make sure class2 has fewer parents
         let class1_parents = self.classes[&id1].parents.len();
         let class2_parents = self.classes[&id2].parents.len();
@@ -1166,6 +1621,10 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
         // make id1 the new root
         self.unionfind.union(id1, id2);
+        self.metadata.union(id1, first, second);
+        if let Some(explain) = &mut self.explain {
+            explain.union_inputs(id1, id2);
+        }
 
         assert_ne!(id1, id2);
         let class2 = self.classes.remove(&id2).unwrap();
@@ -1173,6 +1632,11 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         assert_eq!(id1, class1.id);
 
         self.pending.extend(class2.parents.iter().copied());
+        if let Some(check) = self.merge_check {
+            if let Err(e) = check(&mut self.analysis, &class1.data, &class2.data) {
+                panic!("bad merge in e-class {}: {}", id1, e)
+            }
+        }
         let did_merge = self.analysis.merge(&mut class1.data, class2.data);
         if did_merge.0 {
             self.analysis_pending.extend(class1.parents.iter().copied());
@@ -1201,6 +1665,453 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         N::modify(self, id)
     }
 
+    /// Recomputes the analysis data of every e-class from scratch.
+    ///
+    /// This is useful when the [`Analysis`] changed in a way that makes the
+    /// current data wrong, for example after changing its parameters.
+    /// See [`recompute_analysis_from`](EGraph::recompute_analysis_from)
+    /// to only recompute the data that depends on some e-classes.
+    pub fn recompute_analysis(&mut self) {
+        let ids: Vec<Id> = self.classes.keys().copied().collect();
+        self.recompute_analysis_from(ids)
+    }
+
+    /// Recomputes the analysis data of the given dirty e-classes,
+    /// and of all the e-classes above them.
+    ///
+    /// The old data of those e-classes is thrown away:
+    /// [`Analysis::make`] is run again for each of their e-nodes, bottom-up,
+    /// and the results are merged and propagated through the parents to a
+    /// fixpoint, like in [`rebuild`](EGraph::rebuild), which this calls.
+    /// The data of other e-classes is kept as is.
+    ///
+    /// Unlike [`set_analysis_data`](EGraph::set_analysis_data), this works
+    /// when the new data has less information than the old one.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    ///
+    /// // the depth of each e-class, where the depth of a leaf is a parameter
+    /// struct Depth(usize);
+    /// impl Analysis<S> for Depth {
+    ///     type Data = usize;
+    ///     fn make(egraph: &mut EGraph<S, Self>, enode: &S, _id: Id) -> usize {
+    ///         let depth = |id: &Id| egraph[*id].data;
+    ///         enode.children.iter().map(depth).max().map_or(egraph.analysis.0, |d| d + 1)
+    ///     }
+    ///     fn merge(&mut self, a: &mut usize, b: usize) -> DidMerge {
+    ///         egg::merge_min(a, b)
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::new(Depth(0));
+    /// let f = egraph.add_expr(&"(f (g x))".parse().unwrap());
+    /// egraph.rebuild();
+    /// assert_eq!(egraph[f].data, 2);
+    ///
+    /// egraph.analysis.0 = 10;
+    /// egraph.recompute_analysis();
+    /// assert_eq!(egraph[f].data, 12);
+    /// ```
+    pub fn recompute_analysis_from(&mut self, dirty: impl IntoIterator<Item = Id>) {
+        // invalidate the dirty classes and everything above them
+        let mut invalid = HashSet::default();
+        let mut todo: Vec<Id> = dirty.into_iter().collect();
+        while let Some(id) = todo.pop() {
+            let id = self.find_mut(id);
+            if invalid.insert(id) {
+                todo.extend(self.classes[&id].parents.iter().copied());
+            }
+        }
+
+        // make each invalid class from an e-node whose children are all
+        // valid or remade, retrying the parents whenever a class is remade
+        let mut remade = HashSet::default();
+        let mut todo: Vec<Id> = invalid.iter().copied().collect();
+        while let Some(id) = todo.pop() {
+            if remade.contains(&id) {
+                continue;
+            }
+            let ready = |child: Id| {
+                let child = self.find(child);
+                !invalid.contains(&child) || remade.contains(&child)
+            };
+            let class = &self.classes[&id];
+            let node = class.nodes.iter().find(|n| n.all(ready)).cloned();
+            if let Some(node) = node {
+                let data = N::make(self, &node, id);
+                self.classes.get_mut(&id).unwrap().data = data;
+                remade.insert(id);
+                for &parent in &self.classes[&id].parents {
+                    let parent = self.find(parent);
+                    if invalid.contains(&parent) && !remade.contains(&parent) {
+                        todo.push(parent);
+                    }
+                }
+            }
+        }
+
+        // classes on a cycle with no ready e-node start from their first one
+        for &id in &invalid {
+            if !remade.contains(&id) {
+                let node = self.classes[&id].nodes[0].clone();
+                let data = N::make(self, &node, id);
+                self.classes.get_mut(&id).unwrap().data = data;
+            }
+        }
+
+        self.propagate_analysis(&invalid);
+    }
+
+    /// Merges the data of all the e-nodes of the given classes,
+    /// and propagates it through the e-graph.
+    fn propagate_analysis(&mut self, classes: &HashSet<Id>) {
+        for &id in classes {
+            let id = self.find_mut(id);
+            let nodes = self.classes[&id].nodes.clone();
+            for node in &nodes {
+                self.merge_node_data(node, id);
+            }
+            N::modify(self, id);
+        }
+        self.rebuild();
+    }
+
+    /// Switches this e-graph to a different [`Analysis`], recomputing
+    /// all the analysis data with it.
+    ///
+    /// Unlike [`copy_without_unions`](EGraph::copy_without_unions), this keeps
+    /// the e-classes, the unions and the explanations, so for example
+    /// equality saturation can run with a cheap analysis, and extraction with
+    /// a more expensive one.
+    ///
+    /// Merge checks from [`with_merge_checks`](EGraph::with_merge_checks)
+    /// are about the old data, so they are not carried over.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    ///
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let a = egraph.add_expr(&"(* x 2)".parse().unwrap());
+    /// let b = egraph.add_expr(&"(+ x x)".parse().unwrap());
+    /// let x = egraph.add(S::leaf("x"));
+    /// egraph.union(a, b);
+    /// egraph.rebuild();
+    ///
+    /// let fold = ConstantFolding::new(
+    ///     |enode: &S, args: &[Option<i32>]| match (enode.op.as_str(), args) {
+    ///         ("+", [a, b]) => Some((*a)? + (*b)?),
+    ///         ("*", [a, b]) => Some((*a)? * (*b)?),
+    ///         (op, []) => op.parse().ok(),
+    ///         _ => None,
+    ///     },
+    ///     |c: &i32| S::leaf(c.to_string()),
+    /// );
+    /// let mut egraph = egraph.with_analysis(fold);
+    /// assert_eq!(egraph.find(a), egraph.find(b));
+    /// assert_eq!(egraph[a].data, None);
+    ///
+    /// let three = egraph.add(S::leaf("3"));
+    /// egraph.union(x, three);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph[a].data, Some(6));
+    /// ```
+    pub fn with_analysis<M: Analysis<L>>(self, analysis: M) -> EGraph<L, M> {
+        let mut classes = self.classes;
+        let mut egraph = EGraph {
+            analysis,
+            explain: self.explain,
+            unionfind: self.unionfind,
+            memo: self.memo,
+            pending: self.pending,
+            nodes: self.nodes,
+            analysis_pending: Default::default(),
+            classes: Default::default(),
+            classes_by_op: self.classes_by_op,
+            clean: false,
+            merge_check: None,
+            metadata: self.metadata,
+            provenance: self.provenance,
+            max_ac_operands: self.max_ac_operands,
+        };
+
+        // every e-node only has children added before it, so making the data
+        // at the first e-node of each class goes bottom-up
+        for i in 0..egraph.nodes.len() {
+            let id = egraph.find_mut(Id::from(i));
+            if let Some(class) = hashmap_remove(&mut classes, &id) {
+                let node = egraph.nodes[i].clone();
+                let data = M::make(&mut egraph, &node, id);
+                let class = EClass {
+                    id,
+                    nodes: class.nodes,
+                    data,
+                    parents: class.parents,
+                };
+                egraph.classes.insert(id, class);
+            }
+        }
+
+        let ids = egraph.classes.keys().copied().collect();
+        egraph.propagate_analysis(&ids);
+        egraph
+    }
+
+    /// Saves the e-graph in a compact binary snapshot, which can be loaded
+    /// with [`EGraph::load_snapshot`].
+    ///
+    /// Snapshots are much smaller and faster than serializing with `serde`,
+    /// and restore the e-graph's indices so it does not need a
+    /// [`rebuild`](EGraph::rebuild) after loading.
+    /// They contain the e-nodes, the union-find, the e-classes, the indices,
+    /// the [`Provenance`] if it is enabled, and the analysis data,
+    /// which is encoded by `codec`.
+    /// The e-classes and indices are written in the order they iterate in,
+    /// and loaded back in that order. With the `deterministic` feature, whose
+    /// maps iterate in insertion order, a run continued from the loaded
+    /// e-graph then does the same as one continued from the original.
+    /// Explanations, metadata, data predicates and the [`Analysis`] itself are
+    /// not saved.
+    ///
+    /// The e-graph must be clean, that is, rebuilt since its last change.
+    /// The e-nodes are written with their [`Display`] implementation and read
+    /// back with [`FromOp`], so those should agree, like they do for
+    /// [`define_language!`] languages.
+    /// Writing many small pieces, this should be given a buffered writer.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// use std::convert::TryInto;
+    ///
+    /// let mut egraph = EGraph::new(AstDepth);
+    /// let a = egraph.add_expr(&"(+ x (* y 2))".parse().unwrap());
+    /// let b = egraph.add_expr(&"(+ x z)".parse().unwrap());
+    /// let y2 = egraph.lookup_expr(&"(* y 2)".parse().unwrap()).unwrap();
+    /// let z = egraph.lookup(S::leaf("z")).unwrap();
+    /// egraph.union(y2, z);
+    /// egraph.rebuild();
+    ///
+    /// let codec = (
+    ///     |depth: &usize, out: &mut Vec<u8>| out.extend((*depth as u32).to_le_bytes()),
+    ///     |bytes: &[u8]| match bytes.try_into() {
+    ///         Ok(bytes) => Ok(u32::from_le_bytes(bytes) as usize),
+    ///         Err(e) => Err(e.to_string()),
+    ///     },
+    /// );
+    /// let mut snapshot = vec![];
+    /// egraph.save_snapshot(&codec, &mut snapshot).unwrap();
+    ///
+    /// // the snapshot could also be memory-mapped, or read from a file
+    /// let loaded = EGraph::load_snapshot(AstDepth, &codec, &snapshot[..]).unwrap();
+    /// assert!(loaded.clean);
+    /// assert_eq!(loaded.find(a), loaded.find(b));
+    /// assert_eq!(loaded[a].data, 1);
+    /// assert_eq!(loaded.number_of_classes(), egraph.number_of_classes());
+    /// assert_eq!(loaded.total_number_of_nodes(), egraph.total_number_of_nodes());
+    ///
+    /// let pattern: Pattern<S> = "(+ x ?y)".parse().unwrap();
+    /// assert_eq!(pattern.search(&loaded).len(), 1);
+    /// # struct AstDepth;
+    /// # impl Analysis<S> for AstDepth {
+    /// #     type Data = usize;
+    /// #     fn make(egraph: &mut EGraph<S, Self>, enode: &S, _id: Id) -> usize {
+    /// #         enode.children.iter().map(|&c| egraph[c].data + 1).max().unwrap_or(0)
+    /// #     }
+    /// #     fn merge(&mut self, a: &mut usize, b: usize) -> DidMerge {
+    /// #         merge_min(a, b)
+    /// #     }
+    /// # }
+    /// ```
+    pub fn save_snapshot(
+        &self,
+        codec: &impl SnapshotCodec<N::Data>,
+        writer: impl std::io::Write,
+    ) -> Result<(), SnapshotError>
+    where
+        L: Display,
+    {
+        if !self.clean {
+            return Err(SnapshotError::NotClean);
+        }
+        let mut w = SnapshotWriter::new(writer)?;
+
+        w.usize(self.nodes.len())?;
+        for node in &self.nodes {
+            w.node(node)?;
+        }
+        for &parent in self.unionfind.parents() {
+            w.id(parent)?;
+        }
+
+        w.usize(self.classes.len())?;
+        for (&id, class) in &self.classes {
+            w.id(id)?;
+            w.usize(class.nodes.len())?;
+            for node in &class.nodes {
+                w.node(node)?;
+            }
+            w.usize(class.parents.len())?;
+            for &parent in &class.parents {
+                w.id(parent)?;
+            }
+            w.data(codec, &class.data)?;
+        }
+
+        w.usize(self.memo.len())?;
+        for (node, &id) in &self.memo {
+            w.node(node)?;
+            w.id(id)?;
+        }
+
+        // the operators are written as the first e-node that has them
+        let mut first_nodes = HashMap::<L::Discriminant, usize>::default();
+        for (i, node) in self.nodes.iter().enumerate() {
+            first_nodes.entry(node.discriminant()).or_insert(i);
+        }
+        w.usize(self.classes_by_op.len())?;
+        for (op, ids) in &self.classes_by_op {
+            w.usize(first_nodes[op])?;
+            w.usize(ids.len())?;
+            for &id in ids {
+                w.id(id)?;
+            }
+        }
+
+        match &self.provenance {
+            None => w.u64(0)?,
+            Some(provenance) => {
+                w.u64(1)?;
+                w.provenance(provenance.current)?;
+                for &p in &provenance.nodes {
+                    w.provenance(p)?;
+                }
+            }
+        }
+
+        w.finish()
+    }
+
+    /// Loads an e-graph from a snapshot written by [`EGraph::save_snapshot`],
+    /// decoding the analysis data with `codec`.
+    ///
+    /// The snapshot is read as a stream, so `reader` can be a buffered file,
+    /// or a byte slice, for example from a memory-mapped file.
+    /// The loaded e-graph is clean, and its analysis is `analysis`.
+    /// This fails if the snapshot is from a newer version of the format
+    /// (see [`SNAPSHOT_VERSION`]) or is malformed.
+    pub fn load_snapshot(
+        analysis: N,
+        codec: &impl SnapshotCodec<N::Data>,
+        reader: impl std::io::Read,
+    ) -> Result<Self, SnapshotError>
+    where
+        L: FromOp,
+    {
+        let mut r = SnapshotReader::new(reader)?;
+        let mut egraph = Self::new(analysis);
+
+        let (n, capacity) = r.len()?;
+        egraph.nodes.reserve(capacity);
+        for _ in 0..n {
+            egraph.nodes.push(r.node(n)?);
+        }
+        let mut parents = Vec::with_capacity(capacity);
+        for _ in 0..n {
+            parents.push(r.id(n)?);
+        }
+        egraph.unionfind = UnionFind::from_parents(parents)
+            .ok_or_else(|| SnapshotError::Corrupt("the union-find has a cycle".into()))?;
+
+        let (n_classes, _) = r.len()?;
+        for _ in 0..n_classes {
+            let id = r.id(n)?;
+            if egraph.find(id) != id {
+                return Err(SnapshotError::Corrupt(format!(
+                    "e-class {} is not canonical",
+                    id
+                )));
+            }
+            let (len, capacity) = r.len()?;
+            let mut nodes = Vec::with_capacity(capacity);
+            for _ in 0..len {
+                nodes.push(r.node(n)?);
+            }
+            let (len, capacity) = r.len()?;
+            let mut parents = Vec::with_capacity(capacity);
+            for _ in 0..len {
+                parents.push(r.id(n)?);
+            }
+            let data = r.data(codec)?;
+            let class = EClass {
+                id,
+                nodes,
+                data,
+                parents,
+            };
+            if egraph.classes.insert(id, class).is_some() {
+                let message = format!("e-class {} appears twice", id);
+                return Err(SnapshotError::Corrupt(message));
+            }
+        }
+        // every e-class is a root, so they are all the roots if there are as many
+        let roots = (0..n).filter(|&i| egraph.find(Id::from(i)) == Id::from(i));
+        if roots.count() != egraph.classes.len() {
+            let message = "the e-classes are not the roots of the union-find";
+            return Err(SnapshotError::Corrupt(message.into()));
+        }
+
+        let (len, capacity) = r.len()?;
+        egraph.memo.reserve(capacity);
+        for _ in 0..len {
+            let node = r.node(n)?;
+            let id = r.id(n)?;
+            egraph.memo.insert(node, id);
+        }
+
+        let (len, capacity) = r.len()?;
+        egraph.classes_by_op.reserve(capacity);
+        for _ in 0..len {
+            let op = egraph.nodes[usize::from(r.id(n)?)].discriminant();
+            let (len, capacity) = r.len()?;
+            let mut ids = HashSet::with_capacity_and_hasher(capacity, Default::default());
+            for _ in 0..len {
+                let id = r.id(n)?;
+                if !egraph.classes.contains_key(&id) {
+                    let message = format!("e-class {} of an operator is not an e-class", id);
+                    return Err(SnapshotError::Corrupt(message));
+                }
+                ids.insert(id);
+            }
+            if egraph.classes_by_op.insert(op, ids).is_some() {
+                let message = "an operator appears twice";
+                return Err(SnapshotError::Corrupt(message.into()));
+            }
+        }
+
+        match r.u64()? {
+            0 => {}
+            1 => {
+                let current = r.provenance()?;
+                let mut nodes = Vec::with_capacity(capacity);
+                for _ in 0..n {
+                    nodes.push(r.provenance()?);
+                }
+                egraph.provenance = Some(ProvenanceTable { current, nodes });
+            }
+            tag => {
+                let message = format!("bad provenance tag {}", tag);
+                return Err(SnapshotError::Corrupt(message));
+            }
+        }
+
+        r.finish()?;
+        egraph.clean = true;
+        Ok(egraph)
+    }
+
     /// Returns a more debug-able representation of the egraph.
     ///
     /// [`EGraph`]s implement [`Debug`], but it ain't pretty. It
@@ -1345,14 +2256,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             while let Some(class_id) = self.analysis_pending.pop() {
                 let node = self.nodes[usize::from(class_id)].clone();
                 let class_id = self.find_mut(class_id);
-                let node_data = N::remake(self, &node, class_id);
-                let class = self.classes.get_mut(&class_id).unwrap();
-
-                let did_merge = self.analysis.merge(&mut class.data, node_data);
-                if did_merge.0 {
-                    self.analysis_pending.extend(class.parents.iter().copied());
-                    N::modify(self, class_id)
-                }
+                self.merge_node_data(&node, class_id);
             }
         }
 
@@ -1362,6 +2266,24 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         n_unions
     }
 
+    /// Merges the data of `node` into its class, queueing the class's
+    /// parents if that changed anything.
+    fn merge_node_data(&mut self, node: &L, class_id: Id) {
+        let node_data = N::remake(self, node, class_id);
+        let class = self.classes.get_mut(&class_id).unwrap();
+
+        if let Some(check) = self.merge_check {
+            if let Err(e) = check(&mut self.analysis, &class.data, &node_data) {
+                panic!("bad merge in e-class {}: {}", class_id, e)
+            }
+        }
+        let did_merge = self.analysis.merge(&mut class.data, node_data);
+        if did_merge.0 {
+            self.analysis_pending.extend(class.parents.iter().copied());
+            N::modify(self, class_id)
+        }
+    }
+
     /// Restores the egraph invariants of congruence and enode uniqueness.
     ///
     /// As mentioned
@@ -1480,6 +2402,93 @@ mod tests {
         egraph.rebuild();
     }
 
+    #[test]
+    fn recompute_analysis() {
+        use SymbolLang as S;
+
+        // the cheapest cost of each e-class, given the cost of each leaf
+        struct Cost(HashMap<Symbol, usize>);
+        impl Analysis<S> for Cost {
+            type Data = usize;
+            fn make(egraph: &mut EGraph<S, Self>, enode: &S, _id: Id) -> usize {
+                let cost = egraph.analysis.0.get(&enode.op).copied().unwrap_or(1);
+                enode.fold(cost, |sum, id| sum + egraph[id].data)
+            }
+            fn merge(&mut self, a: &mut usize, b: usize) -> DidMerge {
+                merge_min(a, b)
+            }
+        }
+
+        crate::init_logger();
+        let mut egraph = EGraph::new(Cost(Default::default()));
+        let fx = egraph.add_expr(&"(f (h x x))".parse().unwrap());
+        let gy = egraph.add_expr(&"(g y)".parse().unwrap());
+        let x = egraph.add(S::leaf("x"));
+        egraph.rebuild();
+        assert_eq!(egraph[fx].data, 4);
+
+        // the costs only go up, so merging can't update them
+        egraph.analysis.0.insert("x".into(), 5);
+        egraph.analysis.0.insert("y".into(), 5);
+        egraph.recompute_analysis_from([x]);
+        assert_eq!(egraph[x].data, 5);
+        assert_eq!(egraph[fx].data, 12);
+        // y was not dirty
+        assert_eq!(egraph[gy].data, 2);
+
+        egraph.recompute_analysis();
+        assert_eq!(egraph[gy].data, 6);
+
+        // cycles get the cheapest cost through their other e-nodes
+        egraph.union(x, fx);
+        egraph.rebuild();
+        egraph.analysis.0.insert("x".into(), 1);
+        egraph.recompute_analysis();
+        assert_eq!(egraph[fx].data, 1);
+    }
+
+    #[test]
+    fn with_analysis() {
+        use SymbolLang as S;
+
+        crate::init_logger();
+        let mut egraph = EGraph::<S, ()>::default().with_explanations_enabled();
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+        let two = egraph.add_expr(&"2".parse().unwrap());
+        egraph.union_trusted(x, two, "x is 2");
+        // a cycle
+        egraph.union_trusted(x, fx, "x is f x");
+        let sum = egraph.add_expr(&"(+ (f x) 1)".parse().unwrap());
+        egraph.rebuild();
+
+        let fold = ConstantFolding::new(
+            |enode: &S, args: &[Option<i32>]| match (enode.op.as_str(), args) {
+                ("+", [a, b]) => Some((*a)? + (*b)?),
+                (op, []) => op.parse().ok(),
+                _ => None,
+            },
+            |c: &i32| S::leaf(c.to_string()),
+        );
+        let mut egraph = egraph.with_analysis(fold);
+        assert!(egraph.clean);
+        assert_eq!(egraph[fx].data, Some(2));
+        assert_eq!(egraph[sum].data, Some(3));
+        assert_eq!(egraph.lookup(S::leaf("3")), Some(egraph.find(sum)));
+
+        let explanation = egraph
+            .explain_equivalence(&"(f x)".parse().unwrap(), &"2".parse().unwrap())
+            .get_flat_strings();
+        assert_eq!(
+            explanation,
+            vec![
+                "(f x)",
+                "(Rewrite<= \"x is f x\" x)",
+                "(Rewrite=> \"x is 2\" 2)"
+            ]
+        );
+    }
+
     #[cfg(all(feature = "serde-1", feature = "serde_json"))]
     #[test]
     fn test_serde() {
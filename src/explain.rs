@@ -1,15 +1,18 @@
 #![allow(clippy::only_used_in_recursion)]
 use crate::Symbol;
 use crate::{
-    util::pretty_print, Analysis, EClass, ENodeOrVar, FromOp, HashMap, HashSet, Id, Language,
-    PatternAst, RecExpr, Rewrite, UnionFind, Var,
+    util::{hashmap_remove, pretty_print, BuildHasher},
+    Analysis, EClass, ENodeOrVar, FromOp, HashMap, HashSet, Id, Language, PatternAst, RecExpr,
+    Rewrite, UnionFind, Var,
 };
 
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::BuildHasher as _;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use num_bigint::BigUint;
 use num_traits::identities::{One, Zero};
@@ -20,15 +23,71 @@ type ProofCost = BigUint;
 const CONGRUENCE_LIMIT: usize = 2;
 const GREEDY_NUM_ITERS: usize = 2;
 
-/// A justification for a union, either via a rule or congruence.
+/// A justification for a union, either via a rule, congruence, an analysis,
+/// or associativity and commutativity.
 /// A direct union with a justification is also stored as a rule.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Justification {
     /// Justification by a rule with this name.
     Rule(Symbol),
     /// Justification by congruence.
     Congruence,
+    /// Justification by an [`Analysis`], see [`AnalysisJustification`].
+    Analysis(AnalysisJustification),
+    /// Justification by the associativity and commutativity of operators
+    /// (see [`Language::ac_kind`]), when a pattern matched modulo AC.
+    /// The two terms have the same [`RecExpr::normalize_ac`] form.
+    ///
+    /// It shows up as the rule `ac` in explanations.
+    Ac,
+}
+
+/// The reason an [`Analysis`] unioned two terms,
+/// usually from [`Analysis::modify`].
+///
+/// This stores the name of the analysis, the e-node whose data triggered
+/// the union, and that data, printed.
+/// It prints as `name[data]`, with the data quoted and escaped like a Rust
+/// string if it is empty or has spaces, brackets, parentheses or quotes.
+/// See [`EGraph::union_analysis`](super::EGraph::union_analysis).
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalysisJustification {
+    /// The name of the analysis, e.g. `constant_fold`.
+    pub name: Symbol,
+    /// The (uncanonical) id of the e-node whose analysis data triggered the union.
+    pub node: Id,
+    /// The analysis data that justified the union, e.g. the folded constant.
+    pub data: Arc<str>,
+}
+
+impl AnalysisJustification {
+    /// Create a new justification for the analysis `name`, given the e-node
+    /// that triggered the union and its printed `data`.
+    pub fn new(name: impl Into<Symbol>, node: Id, data: impl Into<Arc<str>>) -> Self {
+        AnalysisJustification {
+            name: name.into(),
+            node,
+            data: data.into(),
+        }
+    }
+}
+
+impl Display for AnalysisJustification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plain = !self.data.is_empty()
+            && !self
+                .data
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || "()[]\"\\;".contains(c));
+        if plain {
+            write!(f, "{}[{}]", self.name, self.data)
+        } else {
+            write!(f, "{}[{:?}]", self.name, self.data)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -61,8 +120,16 @@ pub struct Explain<L: Language> {
         ))
     )]
     pub uncanon_memo: HashMap<L, Id>,
+    // In compact mode, `uncanon_memo` stays empty and this indexes the ids of the
+    // uncanonical enodes by the enodes stored in `EGraph::nodes`, so they are not
+    // stored twice. It is brought up to date on demand, see `uncanonical_id`.
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    uncanon_ids: hashbrown::HashTable<Id>,
     /// By default, egg uses a greedy algorithm to find shorter explanations when they are extracted.
     pub optimize_explanation_lengths: bool,
+    /// In compact mode, only the proof forest (each enode's parent connection) is kept.
+    /// Explanations are rebuilt from it on demand, and are not length optimized.
+    pub compact: bool,
     // For a given pair of enodes in the same eclass,
     // stores the length of the shortest found explanation
     // and the Id of the neighbor for retrieving
@@ -71,6 +138,9 @@ pub struct Explain<L: Language> {
     // That is, less than or equal to the result of `distance_between`
     #[cfg_attr(feature = "serde-1", serde(skip))]
     shortest_explanation_memo: HashMap<(Id, Id), (ProofCost, Id)>,
+    // for each eclass, the earliest enode of a term added with `EGraph::add_expr`,
+    // used as the starting point of existence explanations
+    inputs: HashMap<Id, Id>,
 }
 
 pub(crate) struct ExplainNodes<'a, L: Language> {
@@ -105,6 +175,55 @@ pub type TreeExplanation<L> = Vec<Rc<TreeTerm<L>>>;
 /// See [`FlatTerm`] for more details on how to find this rewrite.
 pub type FlatExplanation<L> = Vec<FlatTerm<L>>;
 
+/// Summary statistics of an [`Explanation`],
+/// computed by [`get_stats`](Explanation::get_stats).
+#[derive(Debug, Clone, Default)]
+pub struct ExplanationStats {
+    /// The number of rewrites in the flattened explanation.
+    pub num_steps: usize,
+    /// How many times each rule (or union reason) is used, by direction.
+    pub rule_uses: HashMap<Symbol, RuleUses>,
+    /// The maximum nesting of congruence sub-proofs in the tree explanation.
+    pub congruence_depth: usize,
+    /// The size of the largest term in the flattened explanation.
+    pub max_term_size: usize,
+}
+
+/// How many times a rule was used in an explanation, see [`ExplanationStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuleUses {
+    /// Uses rewriting the previous term to the next one (see [`FlatTerm::has_rewrite_forward`]).
+    pub forward: usize,
+    /// Uses rewriting the next term to the previous one (see [`FlatTerm::has_rewrite_backward`]).
+    pub backward: usize,
+}
+
+impl RuleUses {
+    /// The total number of uses in either direction.
+    pub fn total(&self) -> usize {
+        self.forward + self.backward
+    }
+}
+
+impl ExplanationStats {
+    /// The union reasons used in the explanation which are not the name of one of `rules`.
+    /// These come from [`union_trusted`](super::EGraph::union_trusted),
+    /// [`union`](super::EGraph::union) or an analysis.
+    pub fn trusted_unions<'a, R, L, N>(&self, rules: R) -> HashSet<Symbol>
+    where
+        R: IntoIterator<Item = &'a Rewrite<L, N>>,
+        L: Language + 'a,
+        N: Analysis<L> + 'a,
+    {
+        let rule_names: HashSet<Symbol> = rules.into_iter().map(|rule| rule.name).collect();
+        self.rule_uses
+            .keys()
+            .filter(|name| !rule_names.contains(*name))
+            .copied()
+            .collect()
+    }
+}
+
 /// A vector of equalities based on enode ids. Each entry represents
 /// two enode ids that are equal and why.
 pub type UnionEqualities = Vec<(Id, Id, Symbol)>;
@@ -142,6 +261,8 @@ impl<L: Language + Display + FromOp> Explanation<L> {
     /// "Rewrite=>" indicates that the previous term is rewritten to the current term
     /// and "Rewrite<=" indicates that the current term is rewritten to the previous term.
     /// The name of the rule or the reason provided to [`union_instantiations`](super::EGraph::union_instantiations).
+    /// Unions made with [`union_analysis`](super::EGraph::union_analysis) are instead wrapped with
+    /// `(Analysis=> analysis-name[data] expression)` or `(Analysis<= analysis-name[data] expression)`.
     ///
     /// Example explanation:
     /// ```text
@@ -235,6 +356,25 @@ impl<L: Language + Display + FromOp> Explanation<L> {
             .collect()
     }
 
+    /// Render the tree-style explanation as a self-contained HTML page.
+    ///
+    /// Each step of the explanation is listed with the rule justifying it and
+    /// its direction ("=>" or "<="), and the rewritten sub-term is highlighted.
+    /// Child proofs (see [`TreeTerm::child_proofs`]) are shown as collapsible
+    /// sub-proofs, so long explanations can be browsed one congruence at a time.
+    pub fn get_html(&self) -> String {
+        let mut s = HTML_HEADER.to_string();
+        write_html_proof(&mut s, &self.explanation_trees).unwrap();
+        s.push_str(HTML_FOOTER);
+        s
+    }
+
+    /// Write the HTML rendering of this explanation to a file.
+    /// See [`get_html`](Explanation::get_html) for details.
+    pub fn to_html(&self, filename: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(filename, self.get_html())
+    }
+
     fn get_sexp(&self) -> Sexp {
         let mut items = vec![Sexp::String("Explanation".to_string())];
         for e in self.explanation_trees.iter() {
@@ -367,13 +507,70 @@ impl<L: Language> Explanation<L> {
         }
     }
 
+    /// Compute summary statistics for this explanation, see [`ExplanationStats`].
+    pub fn get_stats(&mut self) -> ExplanationStats {
+        let congruence_depth = Self::congruence_depth(&self.explanation_trees);
+        let flat_explanation = self.make_flat_explanation();
+        let mut stats = ExplanationStats {
+            num_steps: flat_explanation.len() - 1,
+            congruence_depth,
+            ..Default::default()
+        };
+        for term in flat_explanation {
+            stats.max_term_size = stats.max_term_size.max(term.size());
+            if let Some((rule, is_forward)) = term.get_rewrite() {
+                let uses = stats.rule_uses.entry(rule).or_default();
+                if is_forward {
+                    uses.forward += 1;
+                } else {
+                    uses.backward += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    fn congruence_depth(proof: &[Rc<TreeTerm<L>>]) -> usize {
+        let mut depth = 0;
+        for term in proof {
+            for child in &term.child_proofs {
+                let child_depth = Self::congruence_depth(child);
+                if child.len() > 1 {
+                    depth = depth.max(child_depth + 1);
+                } else {
+                    depth = depth.max(child_depth);
+                }
+            }
+        }
+        depth
+    }
+
     /// Check the validity of the explanation with respect to the given rules.
     /// This only is able to check rule applications when the rules are implement `get_pattern_ast`.
+    /// Steps justified by [`Justification::Ac`] are checked to be equal modulo AC.
+    /// Steps justified by an analysis are accepted, see
+    /// [`check_proof_with_analysis`](Explanation::check_proof_with_analysis) to check them.
     pub fn check_proof<'a, R, N>(&mut self, rules: R)
     where
         R: IntoIterator<Item = &'a Rewrite<L, N>>,
         L: 'a,
         N: Analysis<L> + 'a,
+    {
+        self.check_proof_with_analysis(rules, |_, _, _| true)
+    }
+
+    /// Like [`check_proof`](Explanation::check_proof), but also checks
+    /// each step justified by [`Justification::Analysis`].
+    ///
+    /// `check_analysis` is given the justification, the term before the union
+    /// (the term that triggered it) and the term after the union,
+    /// and should return whether the step is valid.
+    pub fn check_proof_with_analysis<'a, R, N, F>(&mut self, rules: R, mut check_analysis: F)
+    where
+        R: IntoIterator<Item = &'a Rewrite<L, N>>,
+        L: 'a,
+        N: Analysis<L> + 'a,
+        F: FnMut(&AnalysisJustification, &FlatTerm<L>, &FlatTerm<L>) -> bool,
     {
         let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
         let rule_table = Explain::make_rule_table(rules.as_slice());
@@ -390,21 +587,43 @@ impl<L: Language> Explanation<L> {
             assert!(has_forward ^ has_backward);
 
             if has_forward {
-                assert!(self.check_rewrite_at(current, next, &rule_table, true));
+                assert!(Self::check_rewrite_at(
+                    current,
+                    next,
+                    &rule_table,
+                    &mut check_analysis,
+                    true
+                ));
             } else {
-                assert!(self.check_rewrite_at(current, next, &rule_table, false));
+                assert!(Self::check_rewrite_at(
+                    current,
+                    next,
+                    &rule_table,
+                    &mut check_analysis,
+                    false
+                ));
             }
         }
     }
 
-    fn check_rewrite_at<N: Analysis<L>>(
-        &self,
+    fn check_rewrite_at<N, F>(
         current: &FlatTerm<L>,
         next: &FlatTerm<L>,
         table: &HashMap<Symbol, &Rewrite<L, N>>,
+        check_analysis: &mut F,
         is_forward: bool,
-    ) -> bool {
+    ) -> bool
+    where
+        N: Analysis<L>,
+        F: FnMut(&AnalysisJustification, &FlatTerm<L>, &FlatTerm<L>) -> bool,
+    {
         if is_forward && next.forward_rule.is_some() {
+            if next.ac {
+                return current.equal_modulo_ac(next);
+            }
+            if let Some(analysis) = &next.analysis {
+                return check_analysis(analysis, current, next);
+            }
             let rule_name = next.forward_rule.as_ref().unwrap();
             if let Some(rule) = table.get(rule_name) {
                 Explanation::check_rewrite(current, next, rule)
@@ -413,6 +632,12 @@ impl<L: Language> Explanation<L> {
                 true
             }
         } else if !is_forward && next.backward_rule.is_some() {
+            if next.ac {
+                return next.equal_modulo_ac(current);
+            }
+            if let Some(analysis) = &next.analysis {
+                return check_analysis(analysis, next, current);
+            }
             let rule_name = next.backward_rule.as_ref().unwrap();
             if let Some(rule) = table.get(rule_name) {
                 Explanation::check_rewrite(next, current, rule)
@@ -421,7 +646,7 @@ impl<L: Language> Explanation<L> {
             }
         } else {
             for (left, right) in current.children.iter().zip(next.children.iter()) {
-                if !self.check_rewrite_at(left, right, table, is_forward) {
+                if !Self::check_rewrite_at(left, right, table, check_analysis, is_forward) {
                     return false;
                 }
             }
@@ -469,6 +694,12 @@ pub struct TreeTerm<L: Language> {
     pub backward_rule: Option<Symbol>,
     /// A rule rewriting the last TreeTerm's final term to this TreeTerm's initial term.
     pub forward_rule: Option<Symbol>,
+    /// When the rewrite into this TreeTerm was performed by an analysis, its justification.
+    /// The rule above is then the name of the analysis.
+    pub analysis: Option<AnalysisJustification>,
+    /// Whether the rewrite into this TreeTerm only reorders the operands of AC operators,
+    /// see [`Justification::Ac`]. The rule above is then `ac`.
+    pub ac: bool,
     /// A list of child proofs, each transforming the initial term to the final term for that child.
     pub child_proofs: Vec<TreeExplanation<L>>,
 
@@ -483,6 +714,8 @@ impl<L: Language> TreeTerm<L> {
             node,
             backward_rule: None,
             forward_rule: None,
+            analysis: None,
+            ac: false,
             child_proofs,
             current: Id::from(0),
             last: Id::from(0),
@@ -514,6 +747,8 @@ impl<L: Language> TreeTerm<L> {
             node: self.node.clone(),
             backward_rule: self.backward_rule,
             forward_rule: self.forward_rule,
+            analysis: self.analysis.clone(),
+            ac: self.ac,
             children: self
                 .child_proofs
                 .iter()
@@ -528,6 +763,8 @@ impl<L: Language> TreeTerm<L> {
             node: self.node.clone(),
             backward_rule: self.backward_rule,
             forward_rule: self.forward_rule,
+            analysis: self.analysis.clone(),
+            ac: self.ac,
             children: self
                 .child_proofs
                 .iter()
@@ -573,6 +810,8 @@ impl<L: Language> TreeTerm<L> {
 
         proof[0].backward_rule = self.backward_rule;
         proof[0].forward_rule = self.forward_rule;
+        proof[0].analysis.clone_from(&self.analysis);
+        proof[0].ac = self.ac;
 
         proof
     }
@@ -590,6 +829,9 @@ impl<L: Language> TreeTerm<L> {
 /// the rule to this FlatTerm.
 /// Rules are either the string of the name of the rule or the reason provided to
 /// [`union_instantiations`](super::EGraph::union_instantiations).
+/// For unions made by an analysis, the rule is the name of the analysis
+/// and [`analysis`](FlatTerm::analysis) holds the full justification.
+/// For unions modulo AC, the rule is `ac` and [`ac`](FlatTerm::ac) is set.
 ///
 #[derive(Debug, Clone, Eq)]
 pub struct FlatTerm<L: Language> {
@@ -600,6 +842,11 @@ pub struct FlatTerm<L: Language> {
     pub backward_rule: Option<Symbol>,
     /// A rule rewriting the last FlatTerm to this FlatTerm.
     pub forward_rule: Option<Symbol>,
+    /// When the rewrite into this FlatTerm was performed by an analysis, its justification.
+    pub analysis: Option<AnalysisJustification>,
+    /// Whether the rewrite into this FlatTerm only reorders the operands of AC operators,
+    /// see [`Justification::Ac`].
+    pub ac: bool,
     /// The children of this FlatTerm.
     pub children: FlatExplanation<L>,
 }
@@ -638,7 +885,50 @@ impl<L: Language> FlatTerm<L> {
         )
     }
 
+    // the rule annotating this term or a child, and whether it is forward
+    fn get_rewrite(&self) -> Option<(Symbol, bool)> {
+        if let Some(rule) = self.forward_rule {
+            Some((rule, true))
+        } else if let Some(rule) = self.backward_rule {
+            Some((rule, false))
+        } else {
+            self.children.iter().find_map(|child| child.get_rewrite())
+        }
+    }
+
+    // adds this term, without the rewrite annotations, to `expr`
+    fn add_to(&self, expr: &mut RecExpr<L>) -> Id {
+        let mut node = self.node.clone();
+        for (id, child) in node.children_mut().iter_mut().zip(&self.children) {
+            *id = child.add_to(expr);
+        }
+        expr.add(node)
+    }
+
+    fn equal_modulo_ac(&self, other: &FlatTerm<L>) -> bool {
+        let normalize = |term: &FlatTerm<L>| {
+            let mut expr = RecExpr::default();
+            term.add_to(&mut expr);
+            expr.normalize_ac()
+        };
+        normalize(self) == normalize(other)
+    }
+
+    fn size(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|child| child.size())
+            .sum::<usize>()
+    }
+
     fn combine_rewrites(&mut self, other: &FlatTerm<L>) {
+        if other.analysis.is_some() {
+            assert!(self.analysis.is_none());
+            self.analysis.clone_from(&other.analysis);
+        }
+        self.ac |= other.ac;
+
         if other.forward_rule.is_some() {
             assert!(self.forward_rule.is_none());
             self.forward_rule = other.forward_rule;
@@ -681,19 +971,11 @@ impl<L: Language + Display + FromOp> FlatTerm<L> {
         };
 
         if let Some(rule_name) = &self.backward_rule {
-            expr = Sexp::List(vec![
-                Sexp::String("Rewrite<=".to_string()),
-                Sexp::String((*rule_name).to_string()),
-                expr,
-            ]);
+            expr = annotate_rewrite(expr, "<=", rule_name, &self.analysis);
         }
 
         if let Some(rule_name) = &self.forward_rule {
-            expr = Sexp::List(vec![
-                Sexp::String("Rewrite=>".to_string()),
-                Sexp::String((*rule_name).to_string()),
-                expr,
-            ]);
+            expr = annotate_rewrite(expr, "=>", rule_name, &self.analysis);
         }
 
         expr
@@ -705,6 +987,156 @@ impl<L: Language + Display + FromOp> FlatTerm<L> {
     }
 }
 
+// wraps a rewritten expression with the rule or analysis justifying it
+fn annotate_rewrite(
+    expr: Sexp,
+    direction: &str,
+    rule_name: &Symbol,
+    analysis: &Option<AnalysisJustification>,
+) -> Sexp {
+    if let Some(analysis) = analysis {
+        Sexp::List(vec![
+            Sexp::String(format!("Analysis{}", direction)),
+            Sexp::String(analysis.to_string()),
+            expr,
+        ])
+    } else {
+        Sexp::List(vec![
+            Sexp::String(format!("Rewrite{}", direction)),
+            Sexp::String(rule_name.to_string()),
+            expr,
+        ])
+    }
+}
+
+const HTML_HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>egg explanation</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+ol.proof { font-family: monospace; padding-left: 3em; }
+ol.proof > li { margin: 0.4em 0; }
+.justification { font-family: sans-serif; font-size: 0.85em; color: #555; }
+.forward { color: #1a7f37; }
+.backward { color: #b35900; }
+.rewritten { background: #fff3b0; border-radius: 3px; padding: 0 2px; }
+details.congruence { display: inline-block; vertical-align: top; border-left: 2px solid #ccc; padding-left: 4px; }
+details.congruence > summary { cursor: pointer; }
+</style>
+</head>
+<body>
+<h1>Explanation</h1>
+"#;
+
+const HTML_FOOTER: &str = "</body>\n</html>\n";
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_html_proof<L: Language + Display + FromOp>(
+    out: &mut String,
+    proof: &[Rc<TreeTerm<L>>],
+) -> fmt::Result {
+    use std::fmt::Write;
+    writeln!(out, "<ol class=\"proof\">")?;
+    for (i, step) in proof.iter().enumerate() {
+        write!(out, "<li><span class=\"justification\">")?;
+        if let Some((class, arrow, name)) = step.rewrite_label() {
+            write!(
+                out,
+                "<span class=\"{}\">{} {}</span>",
+                class,
+                escape_html(arrow),
+                escape_html(&name)
+            )?;
+        } else if i == 0 {
+            write!(out, "start")?;
+        } else {
+            write!(out, "congruence")?;
+        }
+        write!(out, "</span><br>")?;
+        step.write_html(out)?;
+        writeln!(out, "</li>")?;
+    }
+    writeln!(out, "</ol>")
+}
+
+impl<L: Language + Display + FromOp> TreeTerm<L> {
+    // the direction and name of the rule rewriting into this term, if any
+    fn rewrite_label(&self) -> Option<(&'static str, &'static str, String)> {
+        let (class, arrow, rule) = if let Some(rule) = self.forward_rule {
+            ("forward", "=>", rule)
+        } else if let Some(rule) = self.backward_rule {
+            ("backward", "<=", rule)
+        } else {
+            return None;
+        };
+        let name = match &self.analysis {
+            Some(analysis) => analysis.to_string(),
+            None => rule.to_string(),
+        };
+        Some((class, arrow, name))
+    }
+
+    fn write_html(&self, out: &mut String) -> fmt::Result {
+        use std::fmt::Write;
+        let label = self.rewrite_label();
+        if let Some((class, arrow, name)) = &label {
+            write!(
+                out,
+                "<span class=\"rewritten {}\" title=\"{}\">",
+                class,
+                escape_html(&format!("{} {}", arrow, name))
+            )?;
+        }
+
+        let op = escape_html(&self.node.to_string());
+        if self.node.is_leaf() {
+            write!(out, "{}", op)?;
+        } else {
+            write!(out, "({}", op)?;
+            for child in &self.child_proofs {
+                write!(out, " ")?;
+                if child.len() == 1 {
+                    child[0].write_html(out)?;
+                } else {
+                    let first = child[0].get_initial_flat_term().remove_rewrites();
+                    let last = child[child.len() - 1]
+                        .get_last_flat_term()
+                        .remove_rewrites();
+                    write!(
+                        out,
+                        "<details class=\"congruence\"><summary>{} &rarr; {}</summary>",
+                        escape_html(&first.to_string()),
+                        escape_html(&last.to_string())
+                    )?;
+                    write_html_proof(out, child)?;
+                    write!(out, "</details>")?;
+                }
+            }
+            write!(out, ")")?;
+        }
+
+        if label.is_some() {
+            write!(out, "</span>")?;
+        }
+        Ok(())
+    }
+}
+
 impl<L: Language + Display + FromOp> Display for TreeTerm<L> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let mut buf = String::new();
@@ -753,19 +1185,11 @@ impl<L: Language + Display + FromOp> TreeTerm<L> {
         };
 
         if let Some(rule_name) = &self.backward_rule {
-            expr = Sexp::List(vec![
-                Sexp::String("Rewrite<=".to_string()),
-                Sexp::String((*rule_name).to_string()),
-                expr,
-            ]);
+            expr = annotate_rewrite(expr, "<=", rule_name, &self.analysis);
         }
 
         if let Some(rule_name) = &self.forward_rule {
-            expr = Sexp::List(vec![
-                Sexp::String("Rewrite=>".to_string()),
-                Sexp::String((*rule_name).to_string()),
-                expr,
-            ]);
+            expr = annotate_rewrite(expr, "=>", rule_name, &self.analysis);
         }
 
         expr
@@ -779,6 +1203,8 @@ impl<L: Language> FlatTerm<L> {
             node,
             backward_rule: None,
             forward_rule: None,
+            analysis: None,
+            ac: false,
             children,
         }
     }
@@ -787,8 +1213,9 @@ impl<L: Language> FlatTerm<L> {
     /// The lhs must be guaranteed to match.
     pub fn rewrite(&self, lhs: &PatternAst<L>, rhs: &PatternAst<L>) -> FlatTerm<L> {
         let mut bindings = Default::default();
-        self.make_bindings(lhs, lhs.len() - 1, &mut bindings);
-        FlatTerm::from_pattern(rhs, rhs.len() - 1, &bindings)
+        let mut seq_bindings = Default::default();
+        self.make_bindings(lhs, lhs.len() - 1, &mut bindings, &mut seq_bindings);
+        FlatTerm::from_pattern(rhs, rhs.len() - 1, &bindings, &seq_bindings)
     }
 
     /// Checks if this term or any child has a [`forward_rule`](FlatTerm::forward_rule).
@@ -813,19 +1240,31 @@ impl<L: Language> FlatTerm<L> {
         pattern: &[ENodeOrVar<L>],
         location: usize,
         bindings: &HashMap<Var, &FlatTerm<L>>,
+        seq_bindings: &HashMap<Var, &[FlatTerm<L>]>,
     ) -> FlatTerm<L> {
         match &pattern[location] {
             ENodeOrVar::Var(var) => (*bindings.get(var).unwrap()).clone(),
             ENodeOrVar::ENode(node) => {
-                let children = node.fold(vec![], |mut acc, child| {
-                    acc.push(FlatTerm::from_pattern(
-                        pattern,
-                        usize::from(child),
-                        bindings,
-                    ));
-                    acc
-                });
-                FlatTerm::new(node.clone(), children)
+                let mut children = vec![];
+                for &child in node.children() {
+                    match &pattern[usize::from(child)] {
+                        ENodeOrVar::Var(var) if var.is_sequence() => {
+                            children.extend_from_slice(seq_bindings.get(var).unwrap())
+                        }
+                        _ => children.push(FlatTerm::from_pattern(
+                            pattern,
+                            usize::from(child),
+                            bindings,
+                            seq_bindings,
+                        )),
+                    }
+                }
+                let node = if children.len() == node.len() {
+                    node.clone()
+                } else {
+                    node.with_arity(children.len()).unwrap()
+                };
+                FlatTerm::new(node, children)
             }
         }
     }
@@ -835,6 +1274,7 @@ impl<L: Language> FlatTerm<L> {
         pattern: &[ENodeOrVar<L>],
         location: usize,
         bindings: &mut HashMap<Var, &'a FlatTerm<L>>,
+        seq_bindings: &mut HashMap<Var, &'a [FlatTerm<L>]>,
     ) {
         match &pattern[location] {
             ENodeOrVar::Var(var) => {
@@ -849,13 +1289,47 @@ impl<L: Language> FlatTerm<L> {
                 }
             }
             ENodeOrVar::ENode(node) => {
+                let seq = crate::pattern::sequence_child(pattern, node);
+                let n = self.children.len();
                 // The node must match the rewrite or the proof is invalid.
-                assert!(node.matches(&self.node));
-                let mut counter = 0;
-                node.for_each(|child| {
-                    self.children[counter].make_bindings(pattern, usize::from(child), bindings);
-                    counter += 1;
-                });
+                if seq.is_some() && n + 1 >= node.len() {
+                    assert!(node
+                        .with_arity(n)
+                        .map_or(false, |op| op.matches(&self.node)));
+                } else {
+                    assert!(node.matches(&self.node));
+                }
+                let spliced = n + 1 - node.len();
+                for (i, &child) in node.children().iter().enumerate() {
+                    let child = usize::from(child);
+                    match seq.map(|seq| i.cmp(&seq)) {
+                        None | Some(Ordering::Less) => {
+                            self.children[i].make_bindings(pattern, child, bindings, seq_bindings)
+                        }
+                        Some(Ordering::Greater) => self.children[i - 1 + spliced].make_bindings(
+                            pattern,
+                            child,
+                            bindings,
+                            seq_bindings,
+                        ),
+                        Some(Ordering::Equal) => {
+                            let var = match &pattern[child] {
+                                ENodeOrVar::Var(var) => *var,
+                                ENodeOrVar::ENode(_) => unreachable!(),
+                            };
+                            let terms = &self.children[i..i + spliced];
+                            if let Some(existing) = seq_bindings.get(&var) {
+                                if existing != &terms {
+                                    panic!(
+                                        "Invalid proof: binding for variable {:?} does not match between {:?} \n and \n {:?}",
+                                        var, existing, terms);
+                                }
+                            } else {
+                                seq_bindings.insert(var, terms);
+                            }
+                        }
+                    }
+                }
             }
         }
     }
@@ -903,14 +1377,78 @@ impl<L: Language> Explain<L> {
         Explain {
             explainfind: vec![],
             uncanon_memo: Default::default(),
+            uncanon_ids: Default::default(),
             shortest_explanation_memo: Default::default(),
             optimize_explanation_lengths: true,
+            compact: false,
+            inputs: Default::default(),
+        }
+    }
+
+    pub(crate) fn new_compact() -> Self {
+        Explain {
+            optimize_explanation_lengths: false,
+            compact: true,
+            ..Self::new()
+        }
+    }
+
+    // an estimate of the memory used by the explanation data structures, in bytes
+    pub(crate) fn approximate_size(&self) -> usize {
+        use std::mem::size_of;
+        let neighbors: usize = self
+            .explainfind
+            .iter()
+            .map(|node| node.neighbors.capacity() * size_of::<Connection>())
+            .sum();
+        self.explainfind.capacity() * size_of::<ExplainNode>()
+            + neighbors
+            + self.uncanon_memo.capacity() * (size_of::<L>() + size_of::<Id>())
+            + self.uncanon_ids.capacity() * (size_of::<Id>() + 1)
+            + self.shortest_explanation_memo.capacity() * size_of::<((Id, Id), (ProofCost, Id))>()
+            + self.inputs.capacity() * size_of::<(Id, Id)>()
+    }
+
+    // records that `node`, in the canonical eclass `class`, is part of an input term
+    pub(crate) fn add_input(&mut self, node: Id, class: Id) {
+        let input = self.inputs.entry(class).or_insert(node);
+        *input = node.min(*input);
+    }
+
+    // moves the input of the eclass `from` to `root` when they are unioned
+    pub(crate) fn union_inputs(&mut self, root: Id, from: Id) {
+        if let Some(input) = hashmap_remove(&mut self.inputs, &from) {
+            self.add_input(input, root);
+        }
+    }
+
+    // the earliest input term in the given (canonical) eclass
+    pub(crate) fn find_input(&self, class: Id) -> Option<Id> {
+        self.inputs.get(&class).copied()
+    }
+
+    // the id of an existing uncanonical enode equal to `node`, given all the enodes
+    pub(crate) fn uncanonical_id(&mut self, node: &L, nodes: &[L]) -> Option<Id> {
+        if !self.compact {
+            return self.uncanon_memo.get(node).copied();
+        }
+        let hasher = BuildHasher::default();
+        let hash = |id: &Id| hasher.hash_one(&nodes[usize::from(*id)]);
+        // enodes are added in order, so only the new ones need to be indexed
+        for i in self.uncanon_ids.len()..nodes.len() {
+            let id = Id::from(i);
+            self.uncanon_ids.insert_unique(hash(&id), id, hash);
         }
+        self.uncanon_ids
+            .find(hasher.hash_one(node), |id| nodes[usize::from(*id)] == *node)
+            .copied()
     }
 
     pub(crate) fn add(&mut self, node: L, set: Id) -> Id {
         assert_eq!(self.explainfind.len(), usize::from(set));
-        self.uncanon_memo.insert(node, set);
+        if !self.compact {
+            self.uncanon_memo.insert(node, set);
+        }
         self.explainfind.push(ExplainNode {
             neighbors: vec![],
             parent_connection: Connection {
@@ -940,7 +1478,8 @@ impl<L: Language> Explain<L> {
     }
 
     pub(crate) fn alternate_rewrite(&mut self, node1: Id, node2: Id, justification: Justification) {
-        if node1 == node2 {
+        // alternate rewrites are only used to shorten explanations
+        if node1 == node2 || self.compact {
             return;
         }
         if let Some((cost, _)) = self.shortest_explanation_memo.get(&(node1, node2)) {
@@ -983,19 +1522,26 @@ impl<L: Language> Explain<L> {
         self.make_leader(node1);
         self.explainfind[usize::from(node1)].parent_connection.next = node2;
 
-        if let Justification::Rule(_) = justification {
-            self.shortest_explanation_memo
-                .insert((node1, node2), (BigUint::one(), node2));
-            self.shortest_explanation_memo
-                .insert((node2, node1), (BigUint::one(), node1));
-        }
-
         let pconnection = Connection {
             justification: justification.clone(),
             is_rewrite_forward: true,
             next: node2,
             current: node1,
         };
+        if self.compact {
+            self.explainfind[usize::from(node1)].parent_connection = pconnection;
+            return;
+        }
+
+        if let Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac =
+            justification
+        {
+            self.shortest_explanation_memo
+                .insert((node1, node2), (BigUint::one(), node2));
+            self.shortest_explanation_memo
+                .insert((node2, node1), (BigUint::one(), node1));
+        }
+
         let other_pconnection = Connection {
             justification,
             is_rewrite_forward: false,
@@ -1012,11 +1558,28 @@ impl<L: Language> Explain<L> {
     }
     pub(crate) fn get_union_equalities(&self) -> UnionEqualities {
         let mut equalities = vec![];
+        let mut add_equality = |from: Id, to: Id, justification: &Justification| match justification
+        {
+            Justification::Rule(r) => equalities.push((from, to, *r)),
+            Justification::Analysis(analysis) => equalities.push((from, to, analysis.name)),
+            Justification::Ac => equalities.push((from, to, Symbol::from("ac"))),
+            Justification::Congruence => {}
+        };
         for node in &self.explainfind {
-            for neighbor in &node.neighbors {
-                if neighbor.is_rewrite_forward {
-                    if let Justification::Rule(r) = neighbor.justification {
-                        equalities.push((neighbor.current, neighbor.next, r));
+            if self.compact {
+                // only the parent connections are stored, and they may point backwards
+                let parent = &node.parent_connection;
+                if parent.current != parent.next {
+                    if parent.is_rewrite_forward {
+                        add_equality(parent.current, parent.next, &parent.justification);
+                    } else {
+                        add_equality(parent.next, parent.current, &parent.justification);
+                    }
+                }
+            } else {
+                for neighbor in &node.neighbors {
+                    if neighbor.is_rewrite_forward {
+                        add_equality(neighbor.current, neighbor.next, &neighbor.justification);
                     }
                 }
             }
@@ -1115,7 +1678,7 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
         unionfind: &mut UnionFind,
         classes: &HashMap<Id, EClass<L, N::Data>>,
     ) -> Explanation<L> {
-        if self.optimize_explanation_lengths {
+        if self.optimize_explanation_lengths && !self.compact {
             self.calculate_shortest_explanations::<N>(left, right, classes, unionfind);
         }
 
@@ -1177,7 +1740,9 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
     fn get_neighbor(&self, current: Id, next: Id) -> Connection {
         for neighbor in &self.explainfind[usize::from(current)].neighbors {
             if neighbor.next == next {
-                if let Justification::Rule(_) = neighbor.justification {
+                if let Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac =
+                    neighbor.justification
+                {
                     return neighbor.clone();
                 }
             }
@@ -1259,9 +1824,22 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
         }
 
         let term = match connection.justification {
-            Justification::Rule(name) => {
+            Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac => {
                 let mut rewritten =
                     (*self.node_to_explanation(connection.next, node_explanation_cache)).clone();
+                let name = match connection.justification {
+                    Justification::Analysis(analysis) => {
+                        let name = analysis.name;
+                        rewritten.analysis = Some(analysis);
+                        name
+                    }
+                    Justification::Rule(name) => name,
+                    Justification::Ac => {
+                        rewritten.ac = true;
+                        Symbol::from("ac")
+                    }
+                    Justification::Congruence => unreachable!(),
+                };
                 if connection.is_rewrite_forward {
                     rewritten.forward_rule = Some(name);
                 } else {
@@ -1433,7 +2011,9 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
             Justification::Congruence => {
                 self.congruence_distance(connection.current, connection.next, distance_memo)
             }
-            Justification::Rule(_) => BigUint::one(),
+            Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac => {
+                BigUint::one()
+            }
         }
     }
 
@@ -1472,7 +2052,9 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
                     Justification::Congruence => {
                         self.congruence_distance(current, next, distance_memo)
                     }
-                    Justification::Rule(_) => BigUint::one(),
+                    Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac => {
+                        BigUint::one()
+                    }
                 };
                 distance_memo.parent_distance[usize::from(parent)] = (self.parent(parent), cost);
             }
@@ -1502,8 +2084,24 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
             }
         }
 
+        // without neighbors, group the enodes by eclass up front
+        let mut compact_enodes: HashMap<Id, HashSet<Id>> = Default::default();
+        if self.compact {
+            for i in 0..self.explainfind.len() {
+                let enode = Id::from(i);
+                compact_enodes
+                    .entry(unionfind.find(enode))
+                    .or_default()
+                    .insert(enode);
+            }
+        }
+
         'outer: for eclass in classes.keys() {
-            let enodes = self.find_all_enodes(*eclass);
+            let enodes = if self.compact {
+                hashmap_remove(&mut compact_enodes, eclass).unwrap_or_default()
+            } else {
+                self.find_all_enodes(*eclass)
+            };
             // find all congruence nodes
             let mut cannon_enodes: HashMap<L, Vec<Id>> = Default::default();
             for enode in &enodes {
@@ -1591,7 +2189,9 @@ impl<'x, L: Language> ExplainNodes<'x, L> {
             }
 
             for neighbor in &self.explainfind[usize::from(current)].neighbors {
-                if let Justification::Rule(_) = neighbor.justification {
+                if let Justification::Rule(_) | Justification::Analysis(_) | Justification::Ac =
+                    neighbor.justification
+                {
                     let neighbor_cost = cost_so_far.clone() + 1_u32;
                     todo.push(HeapState {
                         item: neighbor.clone(),
@@ -1968,3 +2568,255 @@ fn simple_explain_union_trusted() {
     let mut exp = egraph.explain_equivalence(&"c".parse().unwrap(), &"d".parse().unwrap());
     assert_eq!(exp.make_flat_explanation().len(), 4)
 }
+
+#[test]
+fn explain_union_analysis() {
+    use crate::{AnalysisJustification, EGraph, Rewrite, SymbolLang};
+    crate::init_logger();
+    let mut egraph = EGraph::new(()).with_explanations_enabled();
+
+    let one = egraph.add_uncanonical(SymbolLang::leaf("1"));
+    let two = egraph.add_uncanonical(SymbolLang::leaf("2"));
+    let sum = egraph.add_uncanonical(SymbolLang::new("+", vec![one, two]));
+    let three = egraph.add_uncanonical(SymbolLang::leaf("3"));
+    let f = egraph.add_uncanonical(SymbolLang::new("f", vec![three]));
+    egraph.union_analysis(
+        sum,
+        three,
+        AnalysisJustification::new("constant_fold", sum, "3"),
+    );
+    egraph.rebuild();
+
+    let mut exp = egraph.explain_equivalence(&"3".parse().unwrap(), &"(+ 1 2)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec!["3", "(Analysis<= constant_fold[3] (+ 1 2))"]
+    );
+    let flat = exp.make_flat_explanation();
+    assert_eq!(flat[1].backward_rule, Some("constant_fold".into()));
+
+    let mut checked = vec![];
+    let no_rules: &[Rewrite<SymbolLang, ()>] = &[];
+    exp.check_proof_with_analysis(no_rules, |analysis, from, to| {
+        checked.push((analysis.clone(), from.to_string(), to.to_string()));
+        true
+    });
+    assert_eq!(checked.len(), 1);
+    assert_eq!(checked[0].0.data.as_ref(), "3");
+    assert_eq!(checked[0].0.node, sum);
+    let interval = AnalysisJustification::new("interval", sum, "[1, 2]");
+    assert_eq!(interval.to_string(), r#"interval["[1, 2]"]"#);
+    assert_eq!(checked[0].2, "3");
+
+    let fsum = egraph.add_expr(&"(f (+ 1 2))".parse().unwrap());
+    assert_eq!(egraph.find(fsum), egraph.find(f));
+    let mut exp =
+        egraph.explain_equivalence(&"(f (+ 1 2))".parse().unwrap(), &"(f 3)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec!["(f (+ 1 2))", "(f (Analysis=> constant_fold[3] 3))"]
+    );
+    assert_eq!(
+        egraph.get_union_equalities(),
+        vec![(sum, three, "constant_fold".into())]
+    );
+}
+
+#[test]
+fn explain_existence() {
+    use crate::{rewrite as rw, EGraph, Rewrite, Runner, SymbolLang};
+    crate::init_logger();
+    let rules: &[Rewrite<SymbolLang, ()>] = &[
+        rw!("mul-one"; "(* ?x 1)" => "?x"),
+        rw!("g-to-h"; "(g ?x)" => "(h ?x)"),
+    ];
+    let mut egraph = EGraph::default().with_explanations_enabled();
+    egraph.add_expr(&"(f (* a 1))".parse().unwrap());
+    egraph.add_expr(&"(g b)".parse().unwrap());
+    let mut egraph = Runner::default().with_egraph(egraph).run(rules).egraph;
+
+    // made by a rewrite below the input
+    let mut exp = egraph.explain_existence(&"(f a)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec!["(f (* a 1))", "(f (Rewrite=> mul-one a))"]
+    );
+    exp.check_proof(rules);
+    // made by a rewrite at the root of the input
+    let mut exp = egraph.explain_existence(&"(h b)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec!["(g b)", "(Rewrite=> g-to-h (h b))"]
+    );
+    // sub-terms of the inputs are inputs too
+    let mut exp = egraph.explain_existence(&"a".parse().unwrap());
+    assert_eq!(exp.get_flat_strings(), vec!["a"]);
+    let mut exp = egraph.explain_existence(&"b".parse().unwrap());
+    assert_eq!(exp.get_flat_strings(), vec!["b"]);
+    // `a` is the earliest input in the e-class of `(* a 1)`
+    let mut exp = egraph.explain_existence(&"(* a 1)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec!["a", "(Rewrite<= mul-one (* a 1))"]
+    );
+    exp.check_proof(rules);
+
+    // (g a) is only there by congruence
+    let a = egraph.lookup_expr(&"a".parse().unwrap()).unwrap();
+    let b = egraph.lookup_expr(&"b".parse().unwrap()).unwrap();
+    egraph.union_trusted(a, b, "a=b");
+    egraph.rebuild();
+    let mut exp = egraph.explain_existence(&"(g a)".parse().unwrap());
+    assert_eq!(
+        exp.get_flat_strings(),
+        vec![
+            "(g b)",
+            "(g (Rewrite<= a=b (* a 1)))",
+            "(g (Rewrite=> mul-one a))"
+        ]
+    );
+
+    // the e-class of both inputs starts from the earliest one
+    let fa = egraph.lookup_expr(&"(f a)".parse().unwrap()).unwrap();
+    let gb = egraph.lookup_expr(&"(g b)".parse().unwrap()).unwrap();
+    egraph.union_trusted(gb, fa, "g=f");
+    egraph.rebuild();
+    let exp = egraph.explain_existence(&"(g b)".parse().unwrap());
+    assert_eq!(exp.explanation_trees[0].to_string(), "(f (* a 1))");
+}
+
+#[test]
+fn explanation_html() {
+    use crate::{EGraph, SymbolLang};
+    crate::init_logger();
+    let mut egraph = EGraph::<SymbolLang, ()>::default().with_explanations_enabled();
+    egraph.add_expr(&"(f (g a))".parse().unwrap());
+    egraph.union_instantiations(
+        &"a".parse().unwrap(),
+        &"b".parse().unwrap(),
+        &Default::default(),
+        "a<b",
+    );
+    egraph.union_instantiations(
+        &"(g c)".parse().unwrap(),
+        &"(g b)".parse().unwrap(),
+        &Default::default(),
+        "gc-gb",
+    );
+    egraph.rebuild();
+
+    let explanation =
+        egraph.explain_equivalence(&"(f (g a))".parse().unwrap(), &"(f (g c))".parse().unwrap());
+    let html = explanation.get_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<details class=\"congruence\"><summary>(g a) &rarr; (g c)</summary>"));
+    assert!(html.contains("<span class=\"forward\">=&gt; a&lt;b</span>"));
+    assert!(html.contains("<span class=\"backward\">&lt;= gc-gb</span>"));
+    assert!(html.contains("<span class=\"rewritten backward\" title=\"&lt;= gc-gb\">(g c)</span>"));
+}
+
+#[test]
+fn explanation_stats() {
+    use crate::{rewrite as rw, EGraph, Rewrite, SymbolLang};
+    crate::init_logger();
+    let mut egraph = EGraph::<SymbolLang, ()>::default().with_explanations_enabled();
+    egraph.add_expr(&"(f (g a))".parse().unwrap());
+    egraph.union_instantiations(
+        &"a".parse().unwrap(),
+        &"b".parse().unwrap(),
+        &Default::default(),
+        "a-b",
+    );
+    egraph.union_instantiations(
+        &"(g c)".parse().unwrap(),
+        &"(g b)".parse().unwrap(),
+        &Default::default(),
+        "gc-gb",
+    );
+    egraph.rebuild();
+
+    let mut explanation =
+        egraph.explain_equivalence(&"(f (g a))".parse().unwrap(), &"(f (g c))".parse().unwrap());
+    let stats = explanation.get_stats();
+    assert_eq!(stats.num_steps, 2);
+    assert_eq!(stats.congruence_depth, 2);
+    assert_eq!(stats.max_term_size, 3);
+    assert_eq!(
+        stats.rule_uses[&Symbol::from("a-b")],
+        RuleUses {
+            forward: 1,
+            backward: 0
+        }
+    );
+    assert_eq!(stats.rule_uses[&Symbol::from("gc-gb")].backward, 1);
+
+    let rules: Vec<Rewrite<SymbolLang, ()>> = vec![rw!("a-b"; "a" => "b")];
+    let trusted = stats.trusted_unions(&rules);
+    assert_eq!(trusted.len(), 1);
+    assert!(trusted.contains(&Symbol::from("gc-gb")));
+}
+
+#[test]
+fn compact_explanations() {
+    use crate::{rewrite as rw, EGraph, Rewrite, Runner, SymbolLang};
+    crate::init_logger();
+    let rules: &[Rewrite<SymbolLang, ()>] = &[
+        rw!("comm-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+        rw!("add-zero"; "(+ ?a 0)" => "?a"),
+        rw!("mul-one"; "(* ?a 1)" => "?a"),
+    ];
+    let start = "(f (+ 0 (* x 1)))".parse().unwrap();
+    let goal = "(f x)".parse().unwrap();
+
+    let mut full = Runner::default()
+        .with_explanations_enabled()
+        .without_explanation_length_optimization()
+        .with_expr(&start)
+        .run(rules);
+    let mut compact = Runner::default()
+        .with_compact_explanations_enabled()
+        .with_expr(&start)
+        .run(rules);
+
+    let mut full_explanation = full.explain_equivalence(&start, &goal);
+    let mut compact_explanation = compact.explain_equivalence(&start, &goal);
+    compact_explanation.check_proof(rules);
+    assert_eq!(
+        full_explanation.get_flat_strings(),
+        compact_explanation.get_flat_strings()
+    );
+
+    let full_explain = full.egraph.explain.as_ref().unwrap();
+    let compact_explain = compact.egraph.explain.as_ref().unwrap();
+    assert!(compact_explain.approximate_size() < full_explain.approximate_size());
+    assert!(compact_explain.uncanon_memo.is_empty());
+    // the uncanonical enodes are still deduplicated
+    assert_eq!(
+        compact_explain.explainfind.len(),
+        full_explain.explainfind.len()
+    );
+    assert!(compact_explain
+        .explainfind
+        .iter()
+        .all(|n| n.neighbors.is_empty()));
+
+    // the full explanations also store redundant unions
+    let full_equalities = full.egraph.get_union_equalities();
+    let compact_equalities = compact.egraph.get_union_equalities();
+    assert!(!compact_equalities.is_empty());
+    assert!(compact_equalities
+        .iter()
+        .all(|equality| full_equalities.contains(equality)));
+
+    let mut copy = EGraph::<SymbolLang, ()>::default().with_compact_explanations_enabled();
+    copy.add_expr(&start);
+    copy.egraph_union(&compact.egraph);
+    assert_eq!(copy.add_expr(&start), copy.add_expr(&goal));
+
+    let egraph = EGraph::<SymbolLang, ()>::default()
+        .with_compact_explanations_enabled()
+        .with_explanations_enabled();
+    assert!(!egraph.explain.as_ref().unwrap().compact);
+    let egraph = egraph.with_compact_explanations_enabled();
+    assert!(egraph.explain.as_ref().unwrap().compact);
+}
@@ -222,12 +222,29 @@ where
 
     /// Find the cheapest (lowest cost) represented `RecExpr` in the
     /// given eclass.
+    ///
+    /// The term is returned as it is in the e-graph, so operators declared
+    /// AC (see [`Language::ac_kind`]) are not flattened or sorted, and the term
+    /// can be passed to [`EGraph::lookup_expr`] or [`EGraph::explain_existence`].
+    /// Use [`find_best_normalized`](Extractor::find_best_normalized) for the
+    /// canonical form.
     pub fn find_best(&self, eclass: Id) -> (CF::Cost, RecExpr<L>) {
         let (cost, root) = self.costs[&self.egraph.find(eclass)].clone();
         let expr = root.build_recexpr(|id| self.find_best_node(id).clone());
         (cost, expr)
     }
 
+    /// Like [`find_best`](Extractor::find_best), but puts the operators declared
+    /// [`AC`](crate::AcKind::AC) or [`C`](crate::AcKind::C) in the canonical form
+    /// given by [`RecExpr::normalize_ac`].
+    ///
+    /// The returned cost is that of the term before normalization,
+    /// and the normalized term may not be in the e-graph.
+    pub fn find_best_normalized(&self, eclass: Id) -> (CF::Cost, RecExpr<L>) {
+        let (cost, expr) = self.find_best(eclass);
+        (cost, expr.normalize_ac())
+    }
+
     /// Find the cheapest e-node in the given e-class.
     pub fn find_best_node(&self, eclass: Id) -> &L {
         &self.costs[&self.egraph.find(eclass)].1
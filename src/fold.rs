@@ -0,0 +1,252 @@
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+
+use crate::*;
+
+type EvalFn<L, V> = Arc<dyn Fn(&L, &[Option<V>]) -> Option<V> + Send + Sync>;
+type LeafFn<L, V> = Arc<dyn Fn(&V) -> L + Send + Sync>;
+
+/**
+A constant folding [`Analysis`] for any language.
+
+The data of each e-class is the constant it evaluates to, if known.
+It is computed by an evaluation function that is given an e-node and the
+constants of its children (or `None` for children that aren't constants),
+and returns the constant of the e-node, if any.
+
+When an e-class gets a constant, [`modify`](Analysis::modify) adds the
+leaf e-node for it (as made by the `leaf` function) to the e-class.
+With [`with_pruning`](ConstantFolding::with_pruning), it also removes all
+the non-leaf e-nodes from the e-class, since they aren't needed anymore.
+When explanations are enabled, the unions are justified by an
+[`AnalysisJustification`] named `constant_fold`, whose data is the constant.
+
+The evaluation function should give the leaf for a constant that same
+constant back, and two e-nodes in the same e-class should never evaluate
+to different constants; this panics when merging different constants.
+
+# Example
+
+```
+use egg::*;
+
+let fold = ConstantFolding::new(
+    |enode: &SymbolLang, args: &[Option<i32>]| match (enode.op.as_str(), args) {
+        ("+", [a, b]) => Some((*a)? + (*b)?),
+        ("*", [Some(0), _] | [_, Some(0)]) => Some(0),
+        ("*", [a, b]) => Some((*a)? * (*b)?),
+        (op, []) => op.parse().ok(),
+        _ => None,
+    },
+    |c: &i32| SymbolLang::leaf(c.to_string()),
+)
+.with_pruning();
+
+let mut egraph = EGraph::new(fold).with_explanations_enabled();
+let a = egraph.add_expr(&"(+ (* x 0) (+ 1 2))".parse().unwrap());
+egraph.rebuild();
+assert_eq!(egraph[a].data, Some(3));
+assert_eq!(egraph[a].nodes, vec![SymbolLang::leaf("3")]);
+
+let mut explanation = egraph.explain_equivalence(
+    &"(+ (* x 0) (+ 1 2))".parse().unwrap(),
+    &"3".parse().unwrap(),
+);
+let no_rules: &[Rewrite<SymbolLang, ConstantFolding<SymbolLang, i32>>] = &[];
+explanation.check_proof_with_analysis(no_rules, |justification, _, _| {
+    justification.name == "constant_fold".into()
+});
+```
+**/
+pub struct ConstantFolding<L, V> {
+    eval: EvalFn<L, V>,
+    leaf: LeafFn<L, V>,
+    prune: bool,
+}
+
+impl<L, V> ConstantFolding<L, V> {
+    /// Creates a new constant folding analysis that evaluates e-nodes with
+    /// `eval` and turns constants back into e-nodes with `leaf`.
+    pub fn new(
+        eval: impl Fn(&L, &[Option<V>]) -> Option<V> + Send + Sync + 'static,
+        leaf: impl Fn(&V) -> L + Send + Sync + 'static,
+    ) -> Self {
+        ConstantFolding {
+            eval: Arc::new(eval),
+            leaf: Arc::new(leaf),
+            prune: false,
+        }
+    }
+
+    /// Removes all the non-leaf e-nodes from e-classes with a constant.
+    pub fn with_pruning(mut self) -> Self {
+        self.prune = true;
+        self
+    }
+}
+
+impl<L, V> Clone for ConstantFolding<L, V> {
+    fn clone(&self) -> Self {
+        ConstantFolding {
+            eval: self.eval.clone(),
+            leaf: self.leaf.clone(),
+            prune: self.prune,
+        }
+    }
+}
+
+impl<L, V> Debug for ConstantFolding<L, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConstantFolding")
+            .field("prune", &self.prune)
+            .finish()
+    }
+}
+
+impl<L, V> ConstantFolding<L, V>
+where
+    L: Language,
+    V: Clone + PartialEq + Debug + Display,
+{
+    fn eval(egraph: &EGraph<L, Self>, enode: &L) -> Option<V> {
+        let args: Vec<Option<V>> = enode
+            .children()
+            .iter()
+            .map(|&child| egraph[child].data.clone())
+            .collect();
+        (egraph.analysis.eval)(enode, &args)
+    }
+
+    // a term that evaluates to `c` in the e-class, with the constant
+    // children replaced by their leaves, so the folding step is local
+    fn folded_term(egraph: &EGraph<L, Self>, id: Id, c: &V, leaf: &L) -> Option<RecExpr<L>> {
+        let enode = egraph[id]
+            .iter()
+            .find(|n| *n != leaf && Self::eval(egraph, n).as_ref() == Some(c))?;
+        Some(enode.join_recexprs(|child| match &egraph[child].data {
+            Some(c) => vec![(egraph.analysis.leaf)(c)].into(),
+            None => egraph.id_to_expr(child),
+        }))
+    }
+}
+
+impl<L, V> Analysis<L> for ConstantFolding<L, V>
+where
+    L: Language,
+    V: Clone + PartialEq + Debug + Display,
+{
+    type Data = Option<V>;
+
+    fn make(egraph: &mut EGraph<L, Self>, enode: &L, _id: Id) -> Self::Data {
+        Self::eval(egraph, enode)
+    }
+
+    fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+        merge_option(to, from, |a, b| {
+            assert_eq!(*a, b, "Merged non-equal constants");
+            DidMerge(false, false)
+        })
+    }
+
+    fn modify(egraph: &mut EGraph<L, Self>, id: Id) {
+        let c = match egraph[id].data.clone() {
+            Some(c) => c,
+            None => return,
+        };
+        let leaf = (egraph.analysis.leaf)(&c);
+        if egraph.are_explanations_enabled() {
+            if egraph.lookup(leaf.clone()) != Some(egraph.find(id)) {
+                let from = match Self::folded_term(egraph, id, &c, &leaf) {
+                    Some(term) => egraph.add_expr_uncanonical(&term),
+                    None => id,
+                };
+                let to = egraph.add_uncanonical(leaf);
+                let justification =
+                    AnalysisJustification::new("constant_fold", from, c.to_string());
+                egraph.union_analysis(from, to, justification);
+            }
+        } else {
+            let added = egraph.add(leaf);
+            egraph.union(id, added);
+        }
+
+        if egraph.analysis.prune {
+            egraph[id].nodes.retain(|n| n.is_leaf());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    fn fold() -> ConstantFolding<S, i32> {
+        ConstantFolding::new(
+            |enode: &S, args: &[Option<i32>]| match (enode.op.as_str(), args) {
+                ("+", [a, b]) => Some((*a)? + (*b)?),
+                ("*", [a, b]) => Some((*a)? * (*b)?),
+                (op, []) => op.parse().ok(),
+                _ => None,
+            },
+            |c: &i32| S::leaf(c.to_string()),
+        )
+    }
+
+    #[test]
+    fn constant_folding() {
+        crate::init_logger();
+        let mut egraph = EGraph::new(fold());
+        let a = egraph.add_expr(&"(+ x (* 2 3))".parse().unwrap());
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let six = egraph.add_expr(&"(* 2 3)".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph[a].data, None);
+        assert_eq!(egraph[six].data, Some(6));
+        assert_eq!(egraph.lookup(S::leaf("6")), Some(egraph.find(six)));
+        // not pruned
+        assert_eq!(egraph[six].len(), 2);
+
+        let one = egraph.add(S::leaf("1"));
+        egraph.union(x, one);
+        egraph.rebuild();
+        assert_eq!(egraph[a].data, Some(7));
+        assert_eq!(egraph.lookup(S::leaf("7")), Some(egraph.find(a)));
+    }
+
+    #[test]
+    fn constant_folding_explanations() {
+        crate::init_logger();
+        let mut egraph = EGraph::new(fold().with_pruning()).with_explanations_enabled();
+        let a = egraph.add_expr(&"(+ x (* 2 3))".parse().unwrap());
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let one = egraph.add_expr(&"1".parse().unwrap());
+        egraph.union_trusted(x, one, "x is one");
+        egraph.rebuild();
+        assert_eq!(egraph[a].nodes, vec![S::leaf("7")]);
+
+        let mut explanation =
+            egraph.explain_equivalence(&"(+ x (* 2 3))".parse().unwrap(), &"7".parse().unwrap());
+        let mut checked = vec![];
+        let no_rules: &[Rewrite<S, ConstantFolding<S, i32>>] = &[];
+        explanation.check_proof_with_analysis(no_rules, |justification, from, to| {
+            checked.push((
+                justification.data.to_string(),
+                from.to_string(),
+                to.to_string(),
+            ));
+            true
+        });
+        assert_eq!(
+            explanation.get_flat_strings(),
+            vec![
+                "(+ x (* 2 3))",
+                "(+ (Rewrite=> \"x is one\" 1) (* 2 3))",
+                "(+ 1 (Analysis=> constant_fold[6] 6))",
+                "(Analysis=> constant_fold[7] 7)",
+            ]
+        );
+        assert_eq!(checked.len(), 2);
+        assert_eq!(checked[0].0, "6");
+        assert_eq!(checked[1].0, "7");
+    }
+}
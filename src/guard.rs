@@ -0,0 +1,895 @@
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use log::*;
+use symbolic_expressions::{Sexp, SexpError};
+use thiserror::Error;
+
+use crate::pattern::{has_sequence_vars, instantiate_node, ENodeOrVarParseError};
+use crate::*;
+
+/// A guard on the substitutions found by a [`GuardedPattern`].
+///
+/// Guards are written as s-expressions whose arguments are terms,
+/// which may mention the variables of the pattern:
+/// - `(= a b)` holds if `a` and `b` are in the same e-class.
+/// - `(!= a b)` holds if they are not.
+/// - `(name a ...)` calls the predicate registered as `name` in
+///   [`GuardPredicates`] with the e-classes of the arguments.
+/// - `?a:name` calls the predicate registered as `name` with the e-class
+///   of `?a`, usually to check its [`Analysis::Data`]
+///   (see [`GuardPredicates::with_data`]).
+///   In a [`GuardedPattern`] this can also be written in place of the
+///   variable, like `(* ?a ?c:const)`, and is checked while matching as
+///   soon as the variable is bound.
+///
+/// Guards only look terms up; they never add to the e-graph.
+/// A term that is not in the e-graph is not equal to anything,
+/// so `(!= ?a 0)` holds when there is no `0`,
+/// and a predicate is false if any of its arguments is missing.
+/// A predicate that was never registered is false too, with a warning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Guard<L> {
+    /// `(= a b)`
+    Equal(PatternAst<L>, PatternAst<L>),
+    /// `(!= a b)`
+    NotEqual(PatternAst<L>, PatternAst<L>),
+    /// `(name a ...)`
+    Predicate(Symbol, Vec<PatternAst<L>>),
+    /// `?a:name`
+    Data(Var, Symbol),
+}
+
+impl<L: Language> Guard<L> {
+    /// The terms this guard is applied to.
+    pub fn terms(&self) -> Vec<&PatternAst<L>> {
+        match self {
+            Guard::Equal(a, b) | Guard::NotEqual(a, b) => vec![a, b],
+            Guard::Predicate(_, args) => args.iter().collect(),
+            Guard::Data(..) => vec![],
+        }
+    }
+
+    /// Returns a list of the [`Var`]s in this guard.
+    pub fn vars(&self) -> Vec<Var> {
+        if let Guard::Data(var, _) = self {
+            return vec![*var];
+        }
+        let mut vars = vec![];
+        for term in self.terms() {
+            for node in term {
+                if let ENodeOrVar::Var(v) = node {
+                    if !vars.contains(v) {
+                        vars.push(*v)
+                    }
+                }
+            }
+        }
+        vars
+    }
+
+    /// Checks this guard against a substitution.
+    ///
+    /// A predicate that is not in `predicates` fails, with a warning.
+    pub fn check<N: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, N>,
+        subst: &Subst,
+        predicates: &GuardPredicates<L, N>,
+    ) -> bool {
+        match self {
+            Guard::Equal(a, b) | Guard::NotEqual(a, b) => {
+                let a = lookup_term(egraph, a, subst);
+                let equal = a.is_some() && a == lookup_term(egraph, b, subst);
+                equal == matches!(self, Guard::Equal(..))
+            }
+            Guard::Predicate(name, args) => {
+                let predicate = match predicates.get_or_warn(*name) {
+                    Some(predicate) => predicate,
+                    None => return false,
+                };
+                let ids: Option<Vec<Id>> = args
+                    .iter()
+                    .map(|arg| lookup_term(egraph, arg, subst))
+                    .collect();
+                ids.map_or(false, |ids| predicate(egraph, &ids))
+            }
+            Guard::Data(var, name) => match predicates.get_or_warn(*name) {
+                Some(predicate) => predicate(egraph, &[egraph.find(subst[*var])]),
+                None => false,
+            },
+        }
+    }
+
+    /// The name of the predicate this guard calls, if it calls one.
+    fn predicate(&self) -> Option<Symbol> {
+        match self {
+            Guard::Predicate(name, _) | Guard::Data(_, name) => Some(*name),
+            Guard::Equal(..) | Guard::NotEqual(..) => None,
+        }
+    }
+}
+
+/// Looks up the e-class of a term under a substitution,
+/// returning `None` if it is not in the e-graph.
+pub(crate) fn lookup_term<L: Language, N: Analysis<L>>(
+    egraph: &EGraph<L, N>,
+    term: &PatternAst<L>,
+    subst: &Subst,
+) -> Option<Id> {
+    let seqs = has_sequence_vars(term.as_ref());
+    let mut ids = Vec::with_capacity(term.len());
+    for node in term {
+        let id = match node {
+            // spliced into the parent by `instantiate_node`
+            ENodeOrVar::Var(v) if v.is_sequence() => Id::from(0),
+            ENodeOrVar::Var(v) => egraph.find(subst[*v]),
+            ENodeOrVar::ENode(n) => {
+                egraph.lookup(instantiate_node(term.as_ref(), seqs, n, subst, |i| {
+                    ids[usize::from(i)]
+                }))?
+            }
+        };
+        ids.push(id);
+    }
+    ids.last().copied()
+}
+
+impl<L: Language + Display> Display for Guard<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Guard::Equal(..) => write!(f, "(=")?,
+            Guard::NotEqual(..) => write!(f, "(!=")?,
+            Guard::Predicate(name, _) => write!(f, "({}", name)?,
+            Guard::Data(var, name) => return write!(f, "{}:{}", var, name),
+        }
+        for term in self.terms() {
+            write!(f, " {}", term)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// An error from parsing a [`Guard`] or [`GuardedPattern`].
+#[derive(Debug, Error)]
+pub enum GuardParseError<E> {
+    /// The input is not a valid s-expression.
+    #[error(transparent)]
+    BadSexp(SexpError),
+
+    /// A term in the pattern or in a guard could not be parsed.
+    #[error(transparent)]
+    BadTerm(RecExprParseError<E>),
+
+    /// A guard is not an operator applied to terms or a data guard like
+    /// `?a:name`, or `=` or `!=` do not have exactly two terms.
+    #[error("expected a guard like (!= ?a 0), found {0}")]
+    BadGuard(Sexp),
+
+    /// Something other than `if` was found between guards.
+    #[error("expected `if` before each guard, found {0}")]
+    ExpectedIf(Sexp),
+
+    /// A guard uses a variable that the pattern does not bind.
+    #[error("guard variable {0} is not bound by the pattern")]
+    UnboundVar(Var),
+}
+
+type ParseError<L> = GuardParseError<ENodeOrVarParseError<<L as FromOp>::Error>>;
+
+fn parse_term<L: FromOp>(sexp: &Sexp) -> Result<PatternAst<L>, ParseError<L>> {
+    sexp.to_string().parse().map_err(GuardParseError::BadTerm)
+}
+
+/// Splits a variable written with data guards, like `?a:pos:even`,
+/// into the variable and the names of the predicates.
+/// Returns `None` if `s` has no data guards.
+fn split_data_guards(s: &str) -> Option<Result<(Var, Vec<Symbol>), ()>> {
+    let (var, names) = s.strip_prefix('?')?.split_once(':')?;
+    let var = match format!("?{}", var).parse::<Var>() {
+        Ok(var) if !var.is_sequence() => var,
+        _ => return Some(Err(())),
+    };
+    let names: Vec<&str> = names.split(':').collect();
+    if names.iter().any(|name| name.is_empty()) {
+        return Some(Err(()));
+    }
+    Some(Ok((var, names.into_iter().map(Symbol::from).collect())))
+}
+
+fn parse_guard<L: FromOp>(sexp: &Sexp) -> Result<Guard<L>, ParseError<L>> {
+    let bad = || GuardParseError::BadGuard(sexp.clone());
+    let (op, args) = match sexp {
+        Sexp::List(list) => match list.split_first() {
+            Some((Sexp::String(op), args)) => (op, args),
+            _ => return Err(bad()),
+        },
+        Sexp::String(s) => {
+            return match split_data_guards(s) {
+                Some(Ok((var, names))) if names.len() == 1 => Ok(Guard::Data(var, names[0])),
+                _ => Err(bad()),
+            }
+        }
+        _ => return Err(bad()),
+    };
+    let mut terms = args.iter().map(parse_term).collect::<Result<Vec<_>, _>>()?;
+    match op.as_str() {
+        "=" | "!=" if terms.len() != 2 => Err(bad()),
+        "=" | "!=" => {
+            let b = terms.pop().unwrap();
+            let a = terms.pop().unwrap();
+            Ok(if op == "=" {
+                Guard::Equal(a, b)
+            } else {
+                Guard::NotEqual(a, b)
+            })
+        }
+        _ => Ok(Guard::Predicate(Symbol::from(op.as_str()), terms)),
+    }
+}
+
+fn parse_sexp<E>(s: &str) -> Result<Sexp, GuardParseError<E>> {
+    symbolic_expressions::parser::parse_str(s.trim()).map_err(GuardParseError::BadSexp)
+}
+
+impl<L: FromOp> FromStr for Guard<L> {
+    type Err = ParseError<L>;
+
+    /// Parses a guard like `(!= ?a 0)` or `?a:name`,
+    /// optionally preceded by `if`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("if ").unwrap_or(s);
+        parse_guard(&parse_sexp(s)?)
+    }
+}
+
+/// A function checking the e-classes given to a [`Guard::Predicate`].
+pub type GuardPredicate<L, N> = Arc<dyn Fn(&EGraph<L, N>, &[Id]) -> bool + Send + Sync>;
+
+/// The named predicates that a [`Guard`] can call, usually to check
+/// analysis data.
+///
+/// This is cheap to clone, so one set of predicates can be shared by
+/// many [`GuardedPattern`]s.
+///
+/// ```
+/// use egg::*;
+/// let predicates = GuardPredicates::<SymbolLang, ()>::default()
+///     .with("is-leaf", |egraph, ids| egraph[ids[0]].iter().any(|n| n.is_leaf()));
+/// let guard: Guard<SymbolLang> = "(is-leaf ?a)".parse().unwrap();
+///
+/// let mut egraph = EGraph::default();
+/// let x = egraph.add_expr(&"x".parse().unwrap());
+/// let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+/// egraph.rebuild();
+///
+/// let mut subst = Subst::default();
+/// subst.insert("?a".parse().unwrap(), x);
+/// assert!(guard.check(&egraph, &subst, &predicates));
+/// subst.insert("?a".parse().unwrap(), fx);
+/// assert!(!guard.check(&egraph, &subst, &predicates));
+/// ```
+pub struct GuardPredicates<L: Language, N: Analysis<L>> {
+    predicates: Arc<HashMap<Symbol, GuardPredicate<L, N>>>,
+}
+
+impl<L: Language, N: Analysis<L>> GuardPredicates<L, N> {
+    /// Registers a predicate under `name`, replacing any previous one.
+    pub fn with(
+        mut self,
+        name: impl Into<Symbol>,
+        predicate: impl Fn(&EGraph<L, N>, &[Id]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.predicates).insert(name.into(), Arc::new(predicate));
+        self
+    }
+
+    /// Registers a predicate under `name` that checks the
+    /// [`Analysis::Data`] of the e-class it is given,
+    /// for data guards like `?a:name` (or `(name ?a)`).
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// #[derive(Default)]
+    /// struct Len;
+    /// impl Analysis<SymbolLang> for Len {
+    ///     type Data = usize;
+    ///     fn merge(&mut self, to: &mut usize, from: usize) -> DidMerge {
+    ///         merge_min(to, from)
+    ///     }
+    ///     fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang, _id: Id) -> usize {
+    ///         1 + enode.children.iter().map(|&c| egraph[c].data).sum::<usize>()
+    ///     }
+    /// }
+    ///
+    /// let predicates = GuardPredicates::default()
+    ///     .with_data("small", |&len: &usize| len < 3)
+    ///     .with_data_value("leaf", 1);
+    ///
+    /// let mut egraph = EGraph::new(Len);
+    /// let big = egraph.add_expr(&"(f (g (h x)))".parse().unwrap());
+    /// let small = egraph.add_expr(&"(f x)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let small_f: GuardedPattern<SymbolLang, Len> = "(f ?x:small)".parse().unwrap();
+    /// let small_f = small_f.with_predicates(predicates.clone());
+    /// let matches = small_f.search(&egraph);
+    /// assert_eq!(matches.len(), 1);
+    /// assert_eq!(matches[0].eclass, small);
+    ///
+    /// let leaf: GuardedPattern<SymbolLang, Len> = "(f ?x:leaf)".parse().unwrap();
+    /// let leaf = leaf.with_predicates(predicates);
+    /// let applier: Pattern<SymbolLang> = "?x".parse().unwrap();
+    /// let rules = &[Rewrite::new("leaf", leaf, applier).unwrap()];
+    /// let runner = Runner::default().with_egraph(egraph).run(rules);
+    /// let x = runner.egraph.lookup(SymbolLang::leaf("x")).unwrap();
+    /// assert_eq!(runner.egraph.find(small), x);
+    /// assert_ne!(runner.egraph.find(big), x);
+    /// ```
+    pub fn with_data(
+        self,
+        name: impl Into<Symbol>,
+        predicate: impl Fn(&N::Data) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.with(name, move |egraph, ids| {
+            ids.len() == 1 && predicate(&egraph[ids[0]].data)
+        })
+    }
+
+    /// Registers a predicate under `name` that checks if the
+    /// [`Analysis::Data`] of the e-class it is given equals `value`.
+    ///
+    /// See [`GuardPredicates::with_data`].
+    pub fn with_data_value(self, name: impl Into<Symbol>, value: N::Data) -> Self
+    where
+        N::Data: PartialEq + Send + Sync + 'static,
+    {
+        self.with_data(name, move |data: &N::Data| *data == value)
+    }
+
+    /// Returns the predicate registered under `name`.
+    pub fn get(&self, name: Symbol) -> Option<&GuardPredicate<L, N>> {
+        self.predicates.get(&name)
+    }
+
+    fn get_or_warn(&self, name: Symbol) -> Option<&GuardPredicate<L, N>> {
+        let predicate = self.get(name);
+        if predicate.is_none() {
+            warn!(
+                "Unknown guard predicate {}, register it in GuardPredicates",
+                name
+            );
+        }
+        predicate
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Default for GuardPredicates<L, N> {
+    fn default() -> Self {
+        Self {
+            predicates: Default::default(),
+        }
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Clone for GuardPredicates<L, N> {
+    fn clone(&self) -> Self {
+        Self {
+            predicates: self.predicates.clone(),
+        }
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Debug for GuardPredicates<L, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.predicates.keys()).finish()
+    }
+}
+
+/// A [`Pattern`] with [`Guard`]s that every match must pass.
+///
+/// Guards are checked while searching, so substitutions that fail them
+/// are never given to the [`Applier`] and do not count towards search limits.
+/// Data guards like `?a:name` are compiled into the [`Program`](machine::Program)
+/// that matches the pattern, so they prune candidates as soon as `?a` is bound.
+/// This can be parsed from text like `(/ ?a ?a) if (!= ?a 0)`, with any
+/// number of `if` clauses, and data guards written in place of their
+/// variable like `(* ?a ?c:const)`.
+/// Use [`with_predicates`](GuardedPattern::with_predicates) to provide
+/// the named predicates that the guards call.
+///
+/// ```
+/// use egg::*;
+/// define_language! {
+///     enum Math {
+///         Num(i32),
+///         "/" = Div([Id; 2]),
+///     }
+/// }
+///
+/// let searcher: GuardedPattern<Math, ()> = "(/ ?a ?a) if (!= ?a 0)".parse().unwrap();
+/// let rules = vec![Rewrite::new("div-cancel", searcher, "1".parse::<Pattern<_>>().unwrap()).unwrap()];
+///
+/// let runner = Runner::default()
+///     .with_expr(&"(/ 2 2)".parse().unwrap())
+///     .with_expr(&"(/ 0 0)".parse().unwrap())
+///     .run(&rules);
+/// let one = runner.egraph.lookup_expr(&"1".parse().unwrap()).unwrap();
+/// assert_eq!(runner.egraph.find(runner.roots[0]), one);
+/// assert_ne!(runner.egraph.find(runner.roots[1]), one);
+/// ```
+pub struct GuardedPattern<L: Language, N: Analysis<L>> {
+    /// The pattern to search for.
+    pub pattern: Pattern<L>,
+    /// The guards that every match must pass.
+    pub guards: Vec<Guard<L>>,
+    predicates: GuardPredicates<L, N>,
+    program: machine::Program<L>,
+}
+
+impl<L: Language, N: Analysis<L>> GuardedPattern<L, N> {
+    /// Creates a new guarded pattern, without any named predicates.
+    ///
+    /// Guards that call a named predicate find nothing until it is given
+    /// with [`with_predicates`](GuardedPattern::with_predicates).
+    ///
+    /// # Panics
+    /// Panics if a guard uses a variable that the pattern does not bind,
+    /// or a data guard is on a [sequence variable](Var::is_sequence).
+    pub fn new(pattern: Pattern<L>, guards: Vec<Guard<L>>) -> Self {
+        if let Some(var) = unbound_var(&pattern, &guards) {
+            panic!("guard variable {} is not bound by the pattern", var);
+        }
+        for guard in &guards {
+            if let Guard::Data(var, _) = guard {
+                assert!(
+                    !var.is_sequence(),
+                    "sequence variable {} cannot have a data guard",
+                    var
+                );
+            }
+        }
+        let program = machine::Program::compile_from_pat(&pattern.ast, &data_guards(&guards));
+        Self {
+            pattern,
+            guards,
+            predicates: Default::default(),
+            program,
+        }
+    }
+
+    /// Sets the named predicates that the guards can call.
+    ///
+    /// A guard calling a predicate that is not in `predicates` never holds,
+    /// so searching warns and finds nothing.
+    pub fn with_predicates(mut self, predicates: GuardPredicates<L, N>) -> Self {
+        self.predicates = predicates;
+        self
+    }
+
+    /// Returns the first predicate that a guard calls but that was not
+    /// given to [`with_predicates`](GuardedPattern::with_predicates).
+    fn unknown_predicate(&self) -> Option<Symbol> {
+        self.guards
+            .iter()
+            .filter_map(Guard::predicate)
+            .find(|&name| self.predicates.get(name).is_none())
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Clone for GuardedPattern<L, N> {
+    fn clone(&self) -> Self {
+        Self {
+            pattern: self.pattern.clone(),
+            guards: self.guards.clone(),
+            predicates: self.predicates.clone(),
+            program: self.program.clone(),
+        }
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Debug for GuardedPattern<L, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardedPattern")
+            .field("pattern", &self.pattern)
+            .field("guards", &self.guards)
+            .field("predicates", &self.predicates)
+            .finish()
+    }
+}
+
+fn unbound_var<L: Language>(pattern: &Pattern<L>, guards: &[Guard<L>]) -> Option<Var> {
+    let bound = pattern.vars();
+    guards
+        .iter()
+        .flat_map(|g| g.vars())
+        .find(|v| !bound.contains(v))
+}
+
+/// The data guards, which are compiled into the program of the pattern.
+fn data_guards<L>(guards: &[Guard<L>]) -> Vec<(Var, Symbol)> {
+    guards
+        .iter()
+        .filter_map(|guard| match guard {
+            Guard::Data(var, name) => Some((*var, *name)),
+            _ => None,
+        })
+        .collect()
+}
+
+impl<L: Language> Pattern<L> {
+    /// Attaches [`Guard`]s to this pattern.
+    /// See [`GuardedPattern`].
+    pub fn guarded<N: Analysis<L>>(self, guards: Vec<Guard<L>>) -> GuardedPattern<L, N> {
+        GuardedPattern::new(self, guards)
+    }
+}
+
+impl<L: FromOp, N: Analysis<L>> FromStr for GuardedPattern<L, N> {
+    type Err = ParseError<L>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sexp = parse_sexp(&format!("({})", s))?;
+        let list = match &sexp {
+            Sexp::List(list) if !list.is_empty() => list,
+            _ => return Err(GuardParseError::BadTerm(RecExprParseError::EmptySexp)),
+        };
+        // data guards written in place of their variable, like `?c:const`
+        let mut guards = vec![];
+        let mut ast = vec![];
+        for node in parse_term::<L>(&list[0])? {
+            let var = match &node {
+                ENodeOrVar::Var(var) => var,
+                ENodeOrVar::ENode(_) => {
+                    ast.push(node);
+                    continue;
+                }
+            };
+            let (var, names) = match split_data_guards(&var.to_string()) {
+                None => (*var, vec![]),
+                Some(Ok(split)) => split,
+                Some(Err(())) => {
+                    return Err(GuardParseError::BadGuard(Sexp::String(var.to_string())))
+                }
+            };
+            for name in names {
+                let guard = Guard::Data(var, name);
+                if !guards.contains(&guard) {
+                    guards.push(guard);
+                }
+            }
+            ast.push(ENodeOrVar::Var(var));
+        }
+        let pattern = Pattern::new(ast.into());
+        for clause in list[1..].chunks(2) {
+            match clause {
+                [Sexp::String(kw), guard] if kw == "if" => guards.push(parse_guard(guard)?),
+                [other, ..] => return Err(GuardParseError::ExpectedIf(other.clone())),
+                [] => unreachable!(),
+            }
+        }
+        if let Some(var) = unbound_var(&pattern, &guards) {
+            return Err(GuardParseError::UnboundVar(var));
+        }
+        Ok(Self::new(pattern, guards))
+    }
+}
+
+impl<L: Language + Display, N: Analysis<L>> Display for GuardedPattern<L, N> {
+    /// Data guards are written in place of their variable,
+    /// and the other guards after the pattern.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut names: HashMap<Var, String> = Default::default();
+        for (var, name) in data_guards(&self.guards) {
+            let names = names.entry(var).or_insert_with(|| var.to_string());
+            names.push(':');
+            names.push_str(name.as_str());
+        }
+        let ast: PatternAst<L> = self
+            .pattern
+            .ast
+            .iter()
+            .map(|node| match node {
+                ENodeOrVar::Var(var) => match names.get(var) {
+                    Some(name) => ENodeOrVar::Var(name.parse().unwrap()),
+                    None => node.clone(),
+                },
+                ENodeOrVar::ENode(_) => node.clone(),
+            })
+            .collect::<Vec<_>>()
+            .into();
+        Display::fmt(&ast, f)?;
+        for guard in &self.guards {
+            if !matches!(guard, Guard::Data(..)) {
+                write!(f, " if {}", guard)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Searcher<L, N> for GuardedPattern<L, N> {
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        Some(&self.pattern.ast)
+    }
+
+    fn search_with_limit(&self, egraph: &EGraph<L, N>, limit: usize) -> Vec<SearchMatches<L>> {
+        if let Some(name) = self.unknown_predicate() {
+            warn!(
+                "Unknown guard predicate {}, register it in GuardPredicates",
+                name
+            );
+            return vec![];
+        }
+        match self.pattern.ast.last().unwrap() {
+            ENodeOrVar::ENode(e) => match egraph.classes_for_op(&e.discriminant()) {
+                None => vec![],
+                Some(ids) => rewrite::search_eclasses_with_limit(self, egraph, ids, limit),
+            },
+            ENodeOrVar::Var(_) => rewrite::search_eclasses_with_limit(
+                self,
+                egraph,
+                egraph.classes().map(|e| e.id),
+                limit,
+            ),
+        }
+    }
+
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<L>> {
+        let check_data = |name: Symbol, id: Id| match self.predicates.get_or_warn(name) {
+            Some(predicate) => predicate(egraph, &[id]),
+            None => false,
+        };
+        // the data guards were checked by the program
+        let keep = |subst: &Subst| {
+            self.guards
+                .iter()
+                .filter(|guard| !matches!(guard, Guard::Data(..)))
+                .all(|guard| guard.check(egraph, subst, &self.predicates))
+        };
+        let substs = self
+            .program
+            .run_with_limit_filtered(egraph, eclass, limit, &check_data, keep);
+        if substs.is_empty() {
+            None
+        } else {
+            Some(SearchMatches {
+                eclass,
+                substs,
+                ast: Some(Cow::Borrowed(&self.pattern.ast)),
+            })
+        }
+    }
+
+    fn recompile(&mut self, egraph: &EGraph<L, N>) {
+        let counts = machine::class_counts(egraph, [&self.pattern.ast]);
+        self.program = machine::Program::compile_from_pat_with_counts(
+            &self.pattern.ast,
+            &data_guards(&self.guards),
+            counts,
+        );
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.pattern.vars()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    fn rules() -> Vec<Rewrite<S, ()>> {
+        let predicates = GuardPredicates::<S, ()>::default().with("is-leaf", |egraph, ids| {
+            egraph[ids[0]].iter().any(|n| n.is_leaf())
+        });
+        let guarded = |s: &str| {
+            s.parse::<GuardedPattern<S, ()>>()
+                .unwrap()
+                .with_predicates(predicates.clone())
+        };
+        vec![
+            rewrite!("div-cancel"; {guarded("(/ ?a ?a) if (!= ?a 0)")} => "1"),
+            rewrite!("same"; {guarded("(f ?a ?b) if (= ?a ?b)")} => "(g ?a)"),
+            rewrite!("leaf"; {guarded("(h ?a) if (is-leaf ?a) if (!= (k ?a) ?a)")} => "?a"),
+        ]
+    }
+
+    #[test]
+    fn parse_guards() {
+        let p: GuardedPattern<S, ()> = "(/ ?a ?b) if (!= ?b 0) if (= (f ?a) ?b) if (pos ?a ?b)"
+            .parse()
+            .unwrap();
+        assert_eq!(p.guards.len(), 3);
+        assert_eq!(
+            p.to_string(),
+            "(/ ?a ?b) if (!= ?b 0) if (= (f ?a) ?b) if (pos ?a ?b)"
+        );
+        let guard: Guard<S> = "if (!= ?a 0)".parse().unwrap();
+        let expected = Guard::NotEqual("?a".parse().unwrap(), "0".parse().unwrap());
+        assert_eq!(guard, expected);
+
+        let err = |s: &str| s.parse::<GuardedPattern<S, ()>>().unwrap_err().to_string();
+        assert_eq!(
+            err("(f ?a) if (!= ?b 0)"),
+            "guard variable ?b is not bound by the pattern"
+        );
+        assert_eq!(
+            err("(f ?a) when (!= ?a 0)"),
+            "expected `if` before each guard, found when"
+        );
+        assert_eq!(
+            err("(f ?a) if (!= ?a)"),
+            "expected a guard like (!= ?a 0), found (!= ?a)"
+        );
+        assert_eq!(
+            err("(f ?a) if ?a"),
+            "expected a guard like (!= ?a 0), found ?a"
+        );
+    }
+
+    #[test]
+    fn parse_data_guards() {
+        let p: GuardedPattern<S, ()> = "(+ ?a:num (f ?b ?a:two)) if (!= ?a 0) if ?b:pos"
+            .parse()
+            .unwrap();
+        let (a, b) = ("?a".parse().unwrap(), "?b".parse().unwrap());
+        assert_eq!(p.pattern.to_string(), "(+ ?a (f ?b ?a))");
+        assert_eq!(p.guards[0], Guard::Data(a, "num".into()));
+        assert_eq!(p.guards[1], Guard::Data(a, "two".into()));
+        assert_eq!(p.guards[3], Guard::Data(b, "pos".into()));
+        assert_eq!(
+            p.to_string(),
+            "(+ ?a:num:two (f ?b:pos ?a:num:two)) if (!= ?a 0)"
+        );
+        let q: GuardedPattern<S, ()> = p.to_string().parse().unwrap();
+        assert_eq!(q.to_string(), p.to_string());
+        // data guards are checked by the program
+        assert_eq!(p.program.to_string().matches("check").count(), 3);
+
+        let guard: Guard<S> = "if ?a:num".parse().unwrap();
+        assert_eq!(guard, Guard::Data(a, "num".into()));
+
+        let err = |s: &str| s.parse::<GuardedPattern<S, ()>>().unwrap_err().to_string();
+        assert_eq!(err("(f ?a:)"), "expected a guard like (!= ?a 0), found ?a:");
+        assert_eq!(
+            err("(f ?xs...:num)"),
+            "expected a guard like (!= ?a 0), found ?xs...:num"
+        );
+        assert_eq!(
+            err("(f ?a) if ?a:num:two"),
+            "expected a guard like (!= ?a 0), found ?a:num:two"
+        );
+    }
+
+    #[derive(Default)]
+    struct Num;
+    impl Analysis<S> for Num {
+        type Data = Option<i32>;
+        fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+            merge_option(to, from, |_, _| DidMerge(false, false))
+        }
+        fn make(_egraph: &mut EGraph<S, Self>, enode: &S, _id: Id) -> Self::Data {
+            enode.op.as_str().parse().ok()
+        }
+    }
+
+    #[test]
+    fn data_guards() {
+        crate::init_logger();
+        let predicates = GuardPredicates::default()
+            .with_data("num", |d: &Option<i32>| d.is_some())
+            .with_data_value("two", Some(2));
+        let mut egraph = EGraph::new(Num);
+        let a = egraph.add_expr(&"(+ 1 x)".parse().unwrap());
+        let b = egraph.add_expr(&"(+ 2 2)".parse().unwrap());
+        egraph.add_expr(&"(+ y y)".parse().unwrap());
+        egraph.rebuild();
+
+        let search = |s: &str| {
+            let pat: GuardedPattern<S, Num> = s.parse().unwrap();
+            let pat = pat.with_predicates(predicates.clone());
+            let mut ids: Vec<Id> = pat.search(&egraph).iter().map(|m| m.eclass).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(search("(+ ?a:num ?b)"), vec![a, b]);
+        assert_eq!(search("(+ ?a:num ?a)"), vec![b]);
+        assert_eq!(search("(+ ?a ?a:num)"), vec![b]);
+        assert_eq!(search("(+ ?a ?b:two)"), vec![b]);
+        assert_eq!(search("(+ ?a:two ?b)"), vec![b]);
+        assert_eq!(search("(+ ?a ?b:num)"), vec![b]);
+        assert_eq!(search("(+ ?a:num ?b:two)"), vec![b]);
+        assert_eq!(search("(+ ?a:num:two ?b)"), vec![b]);
+
+        // the same predicates work as guards after the pattern
+        assert_eq!(search("(+ ?a ?b) if ?a:num"), vec![a, b]);
+        assert_eq!(search("(+ ?a ?b) if (two ?b)"), vec![b]);
+    }
+
+    #[test]
+    fn unknown_predicates_fail() {
+        let mut egraph = EGraph::<S, ()>::default();
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        egraph.add_expr(&"(f x)".parse().unwrap());
+        egraph.rebuild();
+
+        fn is_leaf(egraph: &EGraph<S, ()>, ids: &[Id]) -> bool {
+            egraph[ids[0]].nodes.iter().all(|n| n.is_leaf())
+        }
+        let predicates = GuardPredicates::default()
+            .with("is-leaf", is_leaf)
+            .with("leaf", is_leaf);
+        for s in ["(f ?a) if (is-leaf ?a)", "(f ?a:leaf)"] {
+            let pat: GuardedPattern<S, ()> = s.parse().unwrap();
+            assert!(pat.search(&egraph).is_empty());
+            let pat = pat.with_predicates(predicates.clone());
+            assert_eq!(pat.search(&egraph).len(), 1);
+        }
+        let guard: Guard<S> = "(is-leaf ?a)".parse().unwrap();
+        let mut subst = Subst::default();
+        subst.insert("?a".parse().unwrap(), x);
+        assert!(!guard.check(&egraph, &subst, &Default::default()));
+    }
+
+    #[test]
+    fn guards_filter_matches() {
+        let mut egraph = EGraph::<S, ()>::default();
+        for s in [
+            "(/ x x)",
+            "(/ 0 0)",
+            "(f x x)",
+            "(f x y)",
+            "(h x)",
+            "(h (k y))",
+        ] {
+            egraph.add_expr(&s.parse().unwrap());
+        }
+        egraph.rebuild();
+
+        let rules = rules();
+        let n_matches = |name: &str| {
+            let rule = rules.iter().find(|r| r.name.as_str() == name).unwrap();
+            rule.search(&egraph)
+                .iter()
+                .map(|m| m.substs.len())
+                .sum::<usize>()
+        };
+        assert_eq!(n_matches("div-cancel"), 1);
+        assert_eq!(n_matches("same"), 1);
+        assert_eq!(n_matches("leaf"), 1);
+
+        // the limit only counts matches that pass the guards
+        let div = &rules[0];
+        assert_eq!(div.searcher.search_with_limit(&egraph, 1).len(), 1);
+    }
+
+    #[test]
+    fn guards_with_explanations() {
+        let start = "(/ x x)".parse().unwrap();
+        let mut runner = Runner::default()
+            .with_explanations_enabled()
+            .with_expr(&start)
+            .with_expr(&"(/ 0 0)".parse().unwrap())
+            .run(&rules());
+        let mut explanation = runner.explain_equivalence(&start, &"1".parse().unwrap());
+        explanation.check_proof(&rules());
+        let zero = runner
+            .egraph
+            .lookup_expr(&"(/ 0 0)".parse().unwrap())
+            .unwrap();
+        assert_ne!(
+            runner.egraph.find(zero),
+            runner.egraph.find(runner.roots[0])
+        );
+    }
+}
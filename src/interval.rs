@@ -0,0 +1,444 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+
+use crate::*;
+
+/**
+A closed interval of `f64`s, the data of an [`IntervalAnalysis`].
+
+Intervals support the arithmetic operators, which give an interval
+containing all the results of the operation on numbers in the operands.
+
+The bounds may be infinite. An interval with `lo > hi` is empty; all empty
+intervals are normalized to [`Interval::EMPTY`].
+
+As a [`Lattice`], intervals are ordered by reverse inclusion:
+the bottom is the full interval (nothing is known), and the join of two
+intervals is their intersection, since a value in both e-classes must be
+in both intervals.
+**/
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interval {
+    /// The lower bound, included.
+    pub lo: f64,
+    /// The upper bound, included.
+    pub hi: f64,
+}
+
+impl Interval {
+    /// The interval of all numbers.
+    pub const FULL: Interval = Interval {
+        lo: f64::NEG_INFINITY,
+        hi: f64::INFINITY,
+    };
+
+    /// The empty interval, for contradictions.
+    pub const EMPTY: Interval = Interval {
+        lo: f64::INFINITY,
+        hi: f64::NEG_INFINITY,
+    };
+
+    /// Creates the interval from `lo` to `hi`, both included.
+    /// NaN bounds are taken as infinite.
+    pub fn new(lo: f64, hi: f64) -> Self {
+        let lo = if lo.is_nan() { f64::NEG_INFINITY } else { lo };
+        let hi = if hi.is_nan() { f64::INFINITY } else { hi };
+        if lo > hi {
+            Self::EMPTY
+        } else {
+            Interval { lo, hi }
+        }
+    }
+
+    /// The interval of just `c`.
+    pub fn point(c: f64) -> Self {
+        Self::new(c, c)
+    }
+
+    /// Whether the interval is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lo > self.hi
+    }
+
+    /// Whether `x` is in the interval.
+    pub fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    /// Whether the interval does not contain zero.
+    pub fn is_nonzero(&self) -> bool {
+        !self.contains(0.0)
+    }
+
+    /// Whether everything in the interval is `> 0`.
+    pub fn is_positive(&self) -> bool {
+        self.lo > 0.0
+    }
+
+    /// Whether everything in the interval is `< 0`.
+    pub fn is_negative(&self) -> bool {
+        self.hi < 0.0
+    }
+
+    /// Whether everything in the interval is `>= 0`.
+    pub fn is_nonnegative(&self) -> bool {
+        self.lo >= 0.0
+    }
+
+    /// Whether everything in the interval is `<= 0`.
+    pub fn is_nonpositive(&self) -> bool {
+        self.hi <= 0.0
+    }
+
+    /// The sign of everything in the interval compared to zero, if it is
+    /// the same for all of them.
+    pub fn sign(&self) -> Option<Ordering> {
+        if self.is_empty() {
+            None
+        } else if self.is_positive() {
+            Some(Ordering::Greater)
+        } else if self.is_negative() {
+            Some(Ordering::Less)
+        } else if self.lo == 0.0 && self.hi == 0.0 {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+
+    /// The numbers in both intervals.
+    pub fn intersect(&self, other: &Self) -> Self {
+        Self::new(self.lo.max(other.lo), self.hi.min(other.hi))
+    }
+
+    /// The smallest interval containing both intervals.
+    pub fn hull(&self, other: &Self) -> Self {
+        if self.is_empty() {
+            *other
+        } else if other.is_empty() {
+            *self
+        } else {
+            Self::new(self.lo.min(other.lo), self.hi.max(other.hi))
+        }
+    }
+
+    fn map2(self, other: Self, f: impl FnOnce(Self, Self) -> Self) -> Self {
+        if self.is_empty() || other.is_empty() {
+            Self::EMPTY
+        } else {
+            f(self, other)
+        }
+    }
+}
+
+impl std::ops::Neg for Interval {
+    type Output = Self;
+    fn neg(self) -> Self {
+        self.map2(self, |a, _| Self::new(-a.hi, -a.lo))
+    }
+}
+
+impl std::ops::Add for Interval {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        self.map2(other, |a, b| Self::new(a.lo + b.lo, a.hi + b.hi))
+    }
+}
+
+impl std::ops::Sub for Interval {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl std::ops::Mul for Interval {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        self.map2(other, |a, b| {
+            // 0 * inf only shows up at the bounds, where it stands for 0
+            let mul = |x: f64, y: f64| if x == 0.0 || y == 0.0 { 0.0 } else { x * y };
+            let products = [
+                mul(a.lo, b.lo),
+                mul(a.lo, b.hi),
+                mul(a.hi, b.lo),
+                mul(a.hi, b.hi),
+            ];
+            let lo = products.iter().copied().fold(f64::INFINITY, f64::min);
+            let hi = products.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            Self::new(lo, hi)
+        })
+    }
+}
+
+/// The quotients are the full interval if the divisor contains zero.
+impl std::ops::Div for Interval {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        self.map2(other, |a, b| {
+            if b.contains(0.0) {
+                Self::FULL
+            } else {
+                a * Self::new(1.0 / b.hi, 1.0 / b.lo)
+            }
+        })
+    }
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Debug for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            write!(f, "[]")
+        } else {
+            write!(f, "[{}, {}]", self.lo, self.hi)
+        }
+    }
+}
+
+impl Lattice for Interval {
+    fn bottom() -> Self {
+        Self::FULL
+    }
+
+    fn join(self, other: Self) -> Self {
+        self.intersect(&other)
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        // other is inside self
+        other.is_empty() || (self.lo <= other.lo && other.hi <= self.hi)
+    }
+}
+
+type IntervalFn<L> = Arc<dyn Fn(&L, &[Interval]) -> Interval + Send + Sync>;
+
+/**
+An [`Analysis`] that bounds the value of each e-class with an [`Interval`].
+
+The interval of an e-node is computed by a transfer function, which is
+given the e-node and the intervals of its children.
+When e-classes merge, their intervals are intersected, so facts learned
+about any term in an e-class hold for all of them.
+If that intersection is empty, the e-graph has equated terms that cannot
+be equal; these e-classes are listed by
+[`contradictions`](IntervalAnalysis::contradictions).
+
+Use [`ConditionInterval`] to check the intervals in a [`ConditionalApplier`].
+
+Note that intervals can keep shrinking through cycles in the e-graph,
+so the transfer function should not be too precise on cyclic terms.
+
+# Example
+
+```
+use egg::*;
+
+let analysis = IntervalAnalysis::new(|enode: &SymbolLang, args: &[Interval]| {
+    match (enode.op.as_str(), args) {
+        ("+", [a, b]) => *a + *b,
+        ("*", [a, b]) => *a * *b,
+        ("sq", [a]) => (*a * *a).intersect(&Interval::new(0.0, f64::INFINITY)),
+        (op, []) => op.parse().map_or(Interval::FULL, Interval::point),
+        _ => Interval::FULL,
+    }
+});
+
+let rules: &[Rewrite<SymbolLang, IntervalAnalysis<SymbolLang>>] = &[rewrite!("cancel";
+    "(* (/ ?a ?b) ?b)" => "?a" if ConditionInterval::nonzero("?b")
+)];
+
+let start = "(+ (* (/ x (+ (sq y) 1)) (+ (sq y) 1)) (* (/ x y) y))".parse().unwrap();
+let runner: Runner<_, _> = Runner::new(analysis).with_expr(&start).run(rules);
+let (_, best) = Extractor::new(&runner.egraph, AstSize).find_best(runner.roots[0]);
+assert_eq!(best.to_string(), "(+ x (* (/ x y) y))");
+assert!(runner.egraph.analysis.contradictions().is_empty());
+```
+**/
+pub struct IntervalAnalysis<L> {
+    eval: IntervalFn<L>,
+    contradictions: Vec<Id>,
+}
+
+impl<L> IntervalAnalysis<L> {
+    /// Creates an interval analysis with the given transfer function.
+    pub fn new(eval: impl Fn(&L, &[Interval]) -> Interval + Send + Sync + 'static) -> Self {
+        IntervalAnalysis {
+            eval: Arc::new(eval),
+            contradictions: vec![],
+        }
+    }
+
+    /// The e-classes whose interval became empty.
+    ///
+    /// These ids may not be canonical.
+    pub fn contradictions(&self) -> &[Id] {
+        &self.contradictions
+    }
+}
+
+impl<L> Clone for IntervalAnalysis<L> {
+    fn clone(&self) -> Self {
+        IntervalAnalysis {
+            eval: self.eval.clone(),
+            contradictions: self.contradictions.clone(),
+        }
+    }
+}
+
+impl<L> Debug for IntervalAnalysis<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntervalAnalysis")
+            .field("contradictions", &self.contradictions)
+            .finish()
+    }
+}
+
+impl<L: Language> Analysis<L> for IntervalAnalysis<L> {
+    type Data = Interval;
+
+    fn make(egraph: &mut EGraph<L, Self>, enode: &L, _id: Id) -> Self::Data {
+        let args: Vec<Interval> = enode
+            .children()
+            .iter()
+            .map(|&child| egraph[child].data)
+            .collect();
+        (egraph.analysis.eval)(enode, &args)
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        merge_lattice(a, b)
+    }
+
+    fn modify(egraph: &mut EGraph<L, Self>, id: Id) {
+        if egraph[id].data.is_empty() && !egraph.analysis.contradictions.contains(&id) {
+            egraph.analysis.contradictions.push(id);
+        }
+    }
+}
+
+/// A [`Condition`] that checks the [`Interval`] of a variable,
+/// for example that it is provably nonzero.
+///
+/// The data of the analysis must [`Borrow`] an [`Interval`], like
+/// that of [`IntervalAnalysis`].
+#[derive(Debug, Clone)]
+pub struct ConditionInterval {
+    var: Var,
+    check: fn(&Interval) -> bool,
+}
+
+impl ConditionInterval {
+    /// Checks the interval of `var` with `check`.
+    pub fn new(var: &str, check: fn(&Interval) -> bool) -> Self {
+        ConditionInterval {
+            var: var.parse().unwrap(),
+            check,
+        }
+    }
+
+    /// Checks that `var` cannot be zero.
+    pub fn nonzero(var: &str) -> Self {
+        Self::new(var, Interval::is_nonzero)
+    }
+
+    /// Checks that `var` is positive.
+    pub fn positive(var: &str) -> Self {
+        Self::new(var, Interval::is_positive)
+    }
+
+    /// Checks that `var` is negative.
+    pub fn negative(var: &str) -> Self {
+        Self::new(var, Interval::is_negative)
+    }
+
+    /// Checks that `var` is not negative.
+    pub fn nonnegative(var: &str) -> Self {
+        Self::new(var, Interval::is_nonnegative)
+    }
+}
+
+impl<L, N> Condition<L, N> for ConditionInterval
+where
+    L: Language,
+    N: Analysis<L>,
+    N::Data: Borrow<Interval>,
+{
+    fn check(&self, egraph: &mut EGraph<L, N>, _eclass: Id, subst: &Subst) -> bool {
+        (self.check)(egraph[subst[self.var]].data.borrow())
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        vec![self.var]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    #[test]
+    fn interval_arithmetic() {
+        let a = Interval::new(-1.0, 2.0);
+        let b = Interval::new(3.0, 4.0);
+        assert_eq!(a + b, Interval::new(2.0, 6.0));
+        assert_eq!(a - b, Interval::new(-5.0, -1.0));
+        assert_eq!(a * b, Interval::new(-4.0, 8.0));
+        assert_eq!(a / b, Interval::new(-1.0 / 3.0, 2.0 / 3.0));
+        assert_eq!(b / a, Interval::FULL);
+        assert_eq!(Interval::point(0.0) * Interval::FULL, Interval::point(0.0));
+        assert_eq!(a.intersect(&b), Interval::EMPTY);
+        assert_eq!(a.hull(&b), Interval::new(-1.0, 4.0));
+        assert_eq!(Interval::EMPTY + a, Interval::EMPTY);
+
+        assert_eq!(b.sign(), Some(std::cmp::Ordering::Greater));
+        assert_eq!(a.sign(), None);
+        assert!(b.is_nonzero() && !a.is_nonzero());
+
+        let mut c = a;
+        let did_merge = merge_lattice(&mut c, Interval::new(0.0, 10.0));
+        assert_eq!((did_merge.0, did_merge.1), (true, true));
+        assert_eq!(c, Interval::new(0.0, 2.0));
+    }
+
+    #[test]
+    fn interval_contradiction() {
+        crate::init_logger();
+        let analysis =
+            IntervalAnalysis::new(
+                |enode: &S, args: &[Interval]| match (enode.op.as_str(), args) {
+                    ("abs", [a]) => a.hull(&-*a).intersect(&Interval::new(0.0, f64::INFINITY)),
+                    (op, []) => op.parse().map_or(Interval::FULL, Interval::point),
+                    _ => Interval::FULL,
+                },
+            );
+        let mut egraph = EGraph::new(analysis).with_merge_checks();
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let abs = egraph.add_expr(&"(abs x)".parse().unwrap());
+        let two = egraph.add_expr(&"2".parse().unwrap());
+        egraph.union(x, two);
+        egraph.rebuild();
+        assert_eq!(egraph[abs].data, Interval::new(0.0, 2.0));
+        assert!(egraph.analysis.contradictions().is_empty());
+
+        let neg = egraph.add_expr(&"-1".parse().unwrap());
+        egraph.union(abs, neg);
+        egraph.rebuild();
+        assert!(egraph[abs].data.is_empty());
+        assert_eq!(egraph.analysis.contradictions(), &[egraph.find(abs)]);
+    }
+}
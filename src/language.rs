@@ -70,6 +70,37 @@ pub trait Language: Debug + Clone + Eq + Ord + Hash {
         self.fold(Ok(()), |res, id| res.and_then(|_| f(id)))
     }
 
+    /// Returns an e-node with the same operator (and data) as this one,
+    /// but with `arity` children, or `None` if the operator cannot have
+    /// that many children.
+    ///
+    /// The children of the result are unspecified and should be set
+    /// with [`children_mut`](Language::children_mut).
+    /// This is how sequence variables like `?xs...` in a [`Pattern`]
+    /// match and build e-nodes with a variable number of children.
+    ///
+    /// The default implementation only allows the current arity.
+    /// [`define_language!`] and [`derive(Language)`](derive@crate::Language)
+    /// implement this for all variants.
+    fn with_arity(&self, arity: usize) -> Option<Self> {
+        (arity == self.len()).then(|| self.clone())
+    }
+
+    /// Returns whether this operator is commutative,
+    /// or associative and commutative.
+    ///
+    /// E-matching treats binary e-nodes of these operators modulo
+    /// commutativity (or associativity and commutativity),
+    /// so rules like `(+ ?a ?b) => (+ ?b ?a)` are not needed.
+    /// [`Extractor::find_best_normalized`] puts extracted terms in a canonical form
+    /// with [`RecExpr::normalize_ac`].
+    /// Only e-nodes with exactly two children are affected.
+    ///
+    /// The default implementation returns [`AcKind::None`].
+    fn ac_kind(&self) -> AcKind {
+        AcKind::None
+    }
+
     /// Returns the number of the children this enode has.
     ///
     /// The default implementation uses `fold` to accumulate the number of
@@ -222,6 +253,30 @@ pub trait Language: Debug + Clone + Eq + Ord + Hash {
     }
 }
 
+/// Whether an operator is commutative, or associative and commutative.
+///
+/// See [`Language::ac_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AcKind {
+    /// No special treatment.
+    None,
+    /// Commutative: `(op a b) = (op b a)`.
+    C,
+    /// Associative and commutative: additionally, `(op (op a b) c) = (op a (op b c))`.
+    AC,
+}
+
+impl AcKind {
+    /// Returns the [`AcKind`] of an e-node, counting only binary e-nodes.
+    pub(crate) fn of<L: Language>(node: &L) -> AcKind {
+        if node.len() == 2 {
+            node.ac_kind()
+        } else {
+            AcKind::None
+        }
+    }
+}
+
 /// A trait for parsing e-nodes. This is implemented automatically by
 /// [`define_language!`].
 ///
@@ -512,6 +567,80 @@ impl<L: Language> RecExpr<L> {
     pub fn root(&self) -> Id {
         self.ids().last().unwrap()
     }
+
+    /// Puts the commutative and associative-commutative operators
+    /// (see [`Language::ac_kind`]) of this expression in a canonical form.
+    ///
+    /// Nested applications of an AC operator are flattened, their operands
+    /// are sorted, and then they are rebuilt as a right-nested chain.
+    /// The children of a commutative operator are sorted.
+    /// Operands are ordered structurally using the [`Ord`] of the language,
+    /// so two expressions that are equal modulo AC have the same canonical form.
+    /// Expressions without such operators are returned unchanged.
+    pub fn normalize_ac(&self) -> Self {
+        if self.iter().all(|n| AcKind::of(n) == AcKind::None) {
+            return self.clone();
+        }
+
+        let mut result = RecExpr::default();
+        let mut new_ids: Vec<Id> = Vec::with_capacity(self.len());
+        let mut operands = vec![];
+        for node in self {
+            let mut new_node = node.clone().map_children(|id| new_ids[usize::from(id)]);
+            let new_id = match AcKind::of(node) {
+                AcKind::None => result.add(new_node),
+                AcKind::C => {
+                    new_node
+                        .children_mut()
+                        .sort_by(|&a, &b| compare_exprs(&result, a, b));
+                    result.add(new_node)
+                }
+                AcKind::AC => {
+                    // collect the operands of the maximal chain rooted here
+                    operands.clear();
+                    let mut todo = node.children().to_vec();
+                    while let Some(id) = todo.pop() {
+                        let child = &self[id];
+                        if AcKind::of(child) == AcKind::AC && node.matches(child) {
+                            todo.extend(child.children());
+                        } else {
+                            operands.push(new_ids[usize::from(id)]);
+                        }
+                    }
+                    operands.sort_by(|&a, &b| compare_exprs(&result, a, b));
+                    let mut acc = operands.pop().unwrap();
+                    while let Some(operand) = operands.pop() {
+                        let children = new_node.children_mut();
+                        children[0] = operand;
+                        children[1] = acc;
+                        acc = result.add(new_node.clone());
+                    }
+                    acc
+                }
+            };
+            new_ids.push(new_id);
+        }
+        result.extract(*new_ids.last().unwrap())
+    }
+}
+
+/// Structurally compares two sub-expressions of `expr`.
+fn compare_exprs<L: Language>(expr: &RecExpr<L>, a: Id, b: Id) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (na, nb) = (&expr[a], &expr[b]);
+    let op = |n: &L| n.clone().map_children(|_| Id::from(0));
+    op(na)
+        .cmp(&op(nb))
+        .then_with(|| na.len().cmp(&nb.len()))
+        .then_with(|| {
+            let children = na.children().iter().zip(nb.children());
+            children
+                .map(|(&ca, &cb)| compare_exprs(expr, ca, cb))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
 }
 
 impl<L: Language> Index<Id> for RecExpr<L> {
@@ -960,6 +1089,10 @@ impl Language for SymbolLang {
         self.op == other.op && self.len() == other.len()
     }
 
+    fn with_arity(&self, arity: usize) -> Option<Self> {
+        Some(Self::new(self.op, vec![Id::from(0); arity]))
+    }
+
     fn children(&self) -> &[Id] {
         &self.children
     }
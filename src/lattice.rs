@@ -0,0 +1,292 @@
+use std::fmt::Debug;
+
+use crate::*;
+
+/**
+A join-semilattice, the usual kind of data for an [`Analysis`].
+
+An [`Analysis`] is sound when its [`merge`](Analysis::merge) is the join
+of a semilattice: it must be idempotent, commutative and associative,
+and the merged data must be above both inputs.
+Implementing [`Lattice`] and using [`merge_lattice`] (or [`LatticeAdapter`])
+gets the [`DidMerge`] flags right by construction.
+
+[`EGraph::with_merge_checks`] checks these laws for any analysis
+while the e-graph is used.
+**/
+pub trait Lattice: Clone + PartialEq + Debug {
+    /// The least element, below every other one.
+    fn bottom() -> Self;
+
+    /// The least upper bound of `self` and `other`.
+    fn join(self, other: Self) -> Self;
+
+    /// Whether `self` is below (or equal to) `other` in the partial order.
+    ///
+    /// By default, this checks if joining `self` into `other` leaves it unchanged.
+    fn leq(&self, other: &Self) -> bool {
+        self.clone().join(other.clone()) == *other
+    }
+}
+
+impl Lattice for () {
+    fn bottom() -> Self {}
+
+    fn join(self, _other: Self) -> Self {}
+}
+
+/// Booleans ordered by `false < true`, so join is logical or.
+impl Lattice for bool {
+    fn bottom() -> Self {
+        false
+    }
+
+    fn join(self, other: Self) -> Self {
+        self || other
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        *self <= *other
+    }
+}
+
+/// `None` is the bottom, below all `Some`s, which are ordered like `T`.
+impl<T: Lattice> Lattice for Option<T> {
+    fn bottom() -> Self {
+        None
+    }
+
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (Some(a), Some(b)) => Some(a.join(b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
+    fn leq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(a), Some(b)) => a.leq(b),
+        }
+    }
+}
+
+/// A utility for implementing [`Analysis::merge`]
+/// when the `Data` type is a [`Lattice`].
+/// This will take the join of the two values.
+pub fn merge_lattice<T: Lattice>(to: &mut T, from: T) -> DidMerge {
+    let did_merge = DidMerge(!from.leq(to), !to.leq(&from));
+    if did_merge.0 {
+        let old = std::mem::replace(to, T::bottom());
+        *to = old.join(from);
+    }
+    did_merge
+}
+
+/**
+An analysis whose data is a [`Lattice`].
+
+Wrap it in a [`LatticeAdapter`] to get an [`Analysis`] (and an
+[`AnalysisComponent`], so it can be part of a product analysis).
+
+# Example
+
+```
+use egg::*;
+
+/// Whether each e-class is known to be nonzero.
+/// Merged e-classes are equal, so if one was known to be nonzero, both are,
+/// which is the join of `bool`s.
+struct NonZero;
+impl LatticeAnalysis<SymbolLang> for NonZero {
+    type Value = bool;
+    fn make(enode: &SymbolLang, data: &dyn Fn(Id) -> bool) -> bool {
+        match (enode.op.as_str(), enode.children.as_slice()) {
+            ("*", [a, b]) => data(*a) && data(*b),
+            (op, []) => op.parse::<i32>().map_or(false, |n| n != 0),
+            _ => false,
+        }
+    }
+}
+
+let mut egraph = EGraph::new(LatticeAdapter(NonZero)).with_merge_checks();
+let a = egraph.add_expr(&"(* -2 (* x 3))".parse().unwrap());
+let x = egraph.add_expr(&"x".parse().unwrap());
+assert!(!egraph[a].data);
+let five = egraph.add_expr(&"5".parse().unwrap());
+egraph.union(x, five);
+egraph.rebuild();
+assert!(egraph[a].data);
+```
+**/
+pub trait LatticeAnalysis<L: Language> {
+    /// The data of each e-class.
+    type Value: Lattice;
+
+    /// Makes the data for a given e-node, given the data of its children.
+    fn make(enode: &L, data: &dyn Fn(Id) -> Self::Value) -> Self::Value;
+
+    /// Whether or not e-matching should allow finding cycles,
+    /// see [`Analysis::allow_ematching_cycles`].
+    fn allow_ematching_cycles(&self) -> bool {
+        true
+    }
+}
+
+/// Turns a [`LatticeAnalysis`] into an [`Analysis`], merging with
+/// [`Lattice::join`].
+#[derive(Debug, Default, Clone)]
+pub struct LatticeAdapter<A>(pub A);
+
+impl<L: Language, A: LatticeAnalysis<L>> Analysis<L> for LatticeAdapter<A> {
+    type Data = A::Value;
+
+    fn make(egraph: &mut EGraph<L, Self>, enode: &L, id: Id) -> Self::Data {
+        Self::make_component(egraph, enode, id, &|d| d)
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        merge_lattice(a, b)
+    }
+
+    fn allow_ematching_cycles(&self) -> bool {
+        self.0.allow_ematching_cycles()
+    }
+}
+
+impl<L: Language, A: LatticeAnalysis<L>> AnalysisComponent<L> for LatticeAdapter<A> {
+    fn make_component<N: Analysis<L>>(
+        egraph: &mut EGraph<L, N>,
+        enode: &L,
+        _id: Id,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) -> Self::Data {
+        A::make(enode, &|id| get(&egraph[id].data).clone())
+    }
+}
+
+/// Checks that merging `b` into `a` behaves like a semilattice join,
+/// see [`EGraph::with_merge_checks`].
+pub(crate) fn check_merge<L, N>(analysis: &mut N, a: &N::Data, b: &N::Data) -> Result<(), String>
+where
+    L: Language,
+    N: Analysis<L>,
+    N::Data: Clone + PartialEq,
+{
+    let mut merge = |x: &N::Data, y: &N::Data| {
+        let mut x = x.clone();
+        let did_merge = analysis.merge(&mut x, y.clone());
+        (x, did_merge)
+    };
+
+    let (ab, did_merge) = merge(a, b);
+    if ab != *a && !did_merge.0 {
+        return Err(format!(
+            "merging {:?} into {:?} gave {:?}, but DidMerge says the first was unchanged",
+            b, a, ab
+        ));
+    }
+    if ab != *b && !did_merge.1 {
+        return Err(format!(
+            "merging {:?} into {:?} gave {:?}, but DidMerge says it equals the second",
+            b, a, ab
+        ));
+    }
+    let (ba, _) = merge(b, a);
+    if ab != ba {
+        return Err(format!(
+            "merge is not commutative: {:?} and {:?} give {:?} one way and {:?} the other",
+            a, b, ab, ba
+        ));
+    }
+    for x in [a, b] {
+        let (xx, _) = merge(x, x);
+        if xx != *x {
+            return Err(format!(
+                "merge is not idempotent: merging {:?} with itself gave {:?}",
+                x, xx
+            ));
+        }
+        let (abx, _) = merge(&ab, x);
+        if abx != ab {
+            return Err(format!(
+                "merge is not monotone: {:?} is not above {:?}, merging them gave {:?}",
+                ab, x, abx
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    #[test]
+    fn merge_lattice_flags() {
+        let flags = |d: DidMerge| (d.0, d.1);
+        let mut a = Some(false);
+        assert_eq!(flags(merge_lattice(&mut a, None)), (false, true));
+        assert_eq!(flags(merge_lattice(&mut a, Some(false))), (false, false));
+        assert_eq!(flags(merge_lattice(&mut a, Some(true))), (true, false));
+        assert_eq!(a, Some(true));
+
+        let mut b = None;
+        assert_eq!(flags(merge_lattice(&mut b, Some(true))), (true, false));
+        assert_eq!(b, Some(true));
+    }
+
+    #[test]
+    fn merge_checks_pass() {
+        crate::init_logger();
+        let mut egraph = EGraph::new(LatticeAdapter(Even)).with_merge_checks();
+        let a = egraph.add_expr(&"(+ x 2)".parse().unwrap());
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let four = egraph.add_expr(&"4".parse().unwrap());
+        egraph.union(x, four);
+        egraph.rebuild();
+        assert!(egraph[a].data);
+    }
+
+    // whether each e-class is known to be even
+    struct Even;
+    impl LatticeAnalysis<S> for Even {
+        type Value = bool;
+        fn make(enode: &S, data: &dyn Fn(Id) -> bool) -> bool {
+            match (enode.op.as_str(), enode.children.as_slice()) {
+                ("+", [a, b]) => data(*a) && data(*b),
+                (op, []) => op.parse::<i32>().map_or(false, |n| n % 2 == 0),
+                _ => false,
+            }
+        }
+    }
+
+    // keeps the first value it sees, which is not commutative
+    struct First;
+    impl Analysis<S> for First {
+        type Data = Option<Symbol>;
+        fn make(_egraph: &mut EGraph<S, Self>, enode: &S, _id: Id) -> Self::Data {
+            Some(enode.op)
+        }
+        fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+            if a.is_none() {
+                *a = b;
+                return DidMerge(true, false);
+            }
+            DidMerge(false, a != &b)
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "merge is not commutative")]
+    fn merge_checks_fail() {
+        crate::init_logger();
+        let mut egraph = EGraph::new(First).with_merge_checks();
+        let x = egraph.add(S::leaf("x"));
+        let y = egraph.add(S::leaf("y"));
+        egraph.union(x, y);
+        egraph.rebuild();
+    }
+}
@@ -30,6 +30,8 @@ for less or more logging.
 #![doc = include_str!("../tests/simple.rs")]
 #![doc = "\n```"]
 
+extern crate self as egg;
+
 mod macros;
 
 #[doc(hidden)]
@@ -37,19 +39,30 @@ pub mod test;
 
 pub mod tutorials;
 
+mod binder;
 mod dot;
 mod eclass;
+mod egglog;
 mod egraph;
 mod explain;
 mod extract;
+mod fold;
+mod guard;
+mod interval;
 mod language;
+mod lattice;
 #[cfg(feature = "lp")]
 mod lp_extract;
 mod machine;
+mod metadata;
 mod multipattern;
 mod pattern;
+mod product;
+mod provenance;
 mod rewrite;
 mod run;
+mod snapshot;
+mod sort;
 mod subst;
 mod unionfind;
 mod util;
@@ -85,26 +98,49 @@ impl std::fmt::Display for Id {
     }
 }
 
-pub(crate) use {explain::Explain, unionfind::UnionFind};
+pub(crate) use {
+    explain::Explain,
+    metadata::MetadataTables,
+    provenance::ProvenanceTable,
+    snapshot::{SnapshotReader, SnapshotWriter},
+    unionfind::UnionFind,
+};
 
 pub use {
+    binder::{BinderLanguage, DeBruijnIndex, DeBruijnIndexParseError, Substitute},
     dot::Dot,
     eclass::EClass,
+    egglog::{parse_egglog_rules, Egglog, EgglogParseError},
     egraph::{EGraph, LanguageMapper, SimpleLanguageMapper},
     explain::{
-        Explanation, FlatExplanation, FlatTerm, Justification, TreeExplanation, TreeTerm,
-        UnionEqualities,
+        AnalysisJustification, Explanation, ExplanationStats, FlatExplanation, FlatTerm,
+        Justification, RuleUses, TreeExplanation, TreeTerm, UnionEqualities,
     },
     extract::*,
+    fold::ConstantFolding,
+    guard::{Guard, GuardParseError, GuardPredicate, GuardPredicates, GuardedPattern},
+    interval::{ConditionInterval, Interval, IntervalAnalysis},
     language::*,
+    lattice::{merge_lattice, Lattice, LatticeAdapter, LatticeAnalysis},
+    machine::{PatternTrie, Program},
+    metadata::MetadataMerge,
     multipattern::*,
     pattern::{ENodeOrVar, Pattern, PatternAst, SearchMatches},
-    rewrite::{Applier, Condition, ConditionEqual, ConditionalApplier, Rewrite, Searcher},
+    product::{AnalysisComponent, ProductAnalysis},
+    provenance::Provenance,
+    rewrite::{
+        Applier, Condition, ConditionEqual, ConditionNotExists, ConditionNotMatch,
+        ConditionalApplier, Rewrite, Searcher,
+    },
     run::*,
+    snapshot::{SnapshotCodec, SnapshotError, SNAPSHOT_VERSION},
+    sort::{Signature, SortError, TypedLanguage, TypedParseError},
     subst::{Subst, Var},
     util::*,
 };
 
+pub use egg_derive::Language;
+
 #[cfg(feature = "lp")]
 pub use lp_extract::*;
 
@@ -1,4 +1,8 @@
+use crate::pattern::sequence_child;
 use crate::*;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::fmt::{self, Display, Formatter};
 use std::result;
 
 type Result = result::Result<(), ()>;
@@ -6,6 +10,8 @@ type Result = result::Result<(), ()>;
 #[derive(Default)]
 struct Machine {
     reg: Vec<Id>,
+    // the lists of ids bound by sequence variables
+    seqs: Vec<Box<[Id]>>,
     // a buffer to re-use for lookups
     lookup: Vec<Id>,
 }
@@ -13,18 +19,84 @@ struct Machine {
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Reg(u32);
 
+/// A compiled [`Pattern`] or [`MultiPattern`], as run by the
+/// e-matching virtual machine.
+///
+/// A [`Program`] prints as a listing of its instructions, one per line,
+/// which shows the order in which a pattern is matched.
+/// Registers are written `$0`, `$1`, and so on, where `$0` holds the
+/// e-class being searched, and the children bound by
+/// [sequence variables](Var::is_sequence) are written `#0`, `#1`, and so on.
+///
+/// Patterns are compiled without knowing anything about the e-graph
+/// they will search, so the compiler can only guess which e-nodes
+/// are best matched first. [`Pattern::recompile`] compiles the pattern
+/// again, matching the operators that appear in the fewest e-classes first.
+///
+/// ```
+/// use egg::*;
+/// let mut pattern: Pattern<SymbolLang> = "(f (g ?x) (h ?x))".parse().unwrap();
+/// assert_eq!(
+///     pattern.program().to_string(),
+///     "bind $0 f -> $1 $2\n\
+///      bind $2 h -> $3\n\
+///      lookup $1 (g $3)\n\
+///      yield ?x = $3\n"
+/// );
+///
+/// // once `g` is known to be rarer than `h`, it is matched first
+/// let mut egraph = EGraph::<SymbolLang, ()>::default();
+/// egraph.add_expr(&"(f (h a) (h b) (h c) (g a))".parse().unwrap());
+/// egraph.rebuild();
+/// pattern.recompile(&egraph);
+/// assert_eq!(
+///     pattern.program().to_string(),
+///     "bind $0 f -> $1 $2\n\
+///      bind $1 g -> $3\n\
+///      lookup $2 (h $3)\n\
+///      yield ?x = $3\n"
+/// );
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Program<L> {
     instructions: Vec<Instruction<L>>,
     subst: Subst,
+    seqs: Vec<(Var, usize)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Instruction<L> {
-    Bind { node: L, i: Reg, out: Reg },
-    Compare { i: Reg, j: Reg },
-    Lookup { term: Vec<ENodeOrReg<L>>, i: Reg },
-    Scan { out: Reg },
+    Bind {
+        node: L,
+        i: Reg,
+        out: Reg,
+    },
+    BindSeq {
+        node: L,
+        i: Reg,
+        out: Reg,
+        seq: usize,
+        out_seq: usize,
+    },
+    Compare {
+        i: Reg,
+        j: Reg,
+    },
+    CompareSeq {
+        i: usize,
+        j: usize,
+    },
+    CheckData {
+        i: Reg,
+        predicate: Symbol,
+    },
+    Lookup {
+        term: Vec<ENodeOrReg<L>>,
+        i: Reg,
+    },
+    Scan {
+        out: Reg,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,6 +105,99 @@ enum ENodeOrReg<L> {
     Reg(Reg),
 }
 
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+/// Writes the `n` registers starting at `out`.
+fn fmt_regs(f: &mut Formatter<'_>, out: Reg, n: usize) -> fmt::Result {
+    for i in 0..n as u32 {
+        write!(f, " {}", Reg(out.0 + i))?;
+    }
+    Ok(())
+}
+
+/// Writes the term at index `i` of a [`Instruction::Lookup`] as an s-expression.
+fn fmt_term<L: Language + Display>(
+    f: &mut Formatter<'_>,
+    term: &[ENodeOrReg<L>],
+    i: usize,
+) -> fmt::Result {
+    match &term[i] {
+        ENodeOrReg::Reg(r) => write!(f, "{}", r),
+        ENodeOrReg::ENode(node) if node.is_leaf() => write!(f, "{}", node),
+        ENodeOrReg::ENode(node) => {
+            write!(f, "({}", node)?;
+            for &child in node.children() {
+                write!(f, " ")?;
+                fmt_term(f, term, usize::from(child))?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+impl<L: Language + Display> Display for Instruction<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Bind { node, i, out } => {
+                write!(f, "bind {} {} ->", i, node)?;
+                fmt_regs(f, *out, node.len())
+            }
+            Instruction::BindSeq {
+                node,
+                i,
+                out,
+                seq,
+                out_seq,
+            } => {
+                write!(f, "bind {} {} ->", i, node)?;
+                fmt_regs(f, *out, *seq)?;
+                write!(f, " #{}", out_seq)?;
+                fmt_regs(f, Reg(out.0 + *seq as u32), node.len() - 1 - seq)
+            }
+            Instruction::Compare { i, j } => write!(f, "compare {} {}", i, j),
+            Instruction::CompareSeq { i, j } => write!(f, "compare #{} #{}", i, j),
+            Instruction::CheckData { i, predicate } => write!(f, "check {} :{}", i, predicate),
+            Instruction::Lookup { term, i } => {
+                write!(f, "lookup {} ", i)?;
+                fmt_term(f, term, term.len() - 1)
+            }
+            Instruction::Scan { out } => write!(f, "scan -> {}", out),
+        }
+    }
+}
+
+impl<L: Language + Display> Display for Program<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        write!(f, "yield")?;
+        let vars = self
+            .subst
+            .vec
+            .iter()
+            .map(|(v, r)| format!("{} = ${}", v, r));
+        let seqs = self.seqs.iter().map(|(v, i)| format!("{} = #{}", v, i));
+        for (i, binding) in vars.chain(seqs).enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, binding)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Checks the data predicate with the given name on an e-class,
+/// for [`Instruction::CheckData`].
+pub(crate) type CheckData<'a> = &'a dyn Fn(Symbol, Id) -> bool;
+
+/// The [`CheckData`] for programs compiled without data predicates.
+pub(crate) fn no_data_predicates(predicate: Symbol, _: Id) -> bool {
+    unreachable!("data predicate {} outside of a guarded pattern", predicate)
+}
+
 impl Machine {
     #[inline(always)]
     fn reg(&self, reg: Reg) -> Id {
@@ -43,8 +208,8 @@ impl Machine {
         &mut self,
         egraph: &EGraph<L, N>,
         instructions: &[Instruction<L>],
-        subst: &Subst,
-        yield_fn: &mut impl FnMut(&Self, &Subst) -> Result,
+        check_data: CheckData,
+        yield_fn: &mut impl FnMut(&mut Self) -> Result,
     ) -> Result
     where
         L: Language,
@@ -53,21 +218,58 @@ impl Machine {
         let mut instructions = instructions.iter();
         while let Some(instruction) = instructions.next() {
             match instruction {
+                Instruction::Bind { i, out, node } if AcKind::of(node) != AcKind::None => {
+                    let remaining_instructions = instructions.as_slice();
+                    for [a, b] in ac_views(egraph, self.reg(*i), node) {
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.push(a);
+                        self.reg.push(b);
+                        self.run(egraph, remaining_instructions, check_data, yield_fn)?
+                    }
+                    return Ok(());
+                }
                 Instruction::Bind { i, out, node } => {
                     let remaining_instructions = instructions.as_slice();
                     let eclass = &egraph[self.reg(*i)];
                     return eclass.for_each_matching_node(node, |matched| {
                         self.reg.truncate(out.0 as usize);
                         matched.for_each(|id| self.reg.push(id));
-                        self.run(egraph, remaining_instructions, subst, yield_fn)
+                        self.run(egraph, remaining_instructions, check_data, yield_fn)
                     });
                 }
+                Instruction::BindSeq {
+                    i,
+                    out,
+                    node,
+                    seq,
+                    out_seq,
+                } => {
+                    let remaining_instructions = instructions.as_slice();
+                    // the children before and after the sequence variable
+                    let (prefix, suffix) = (*seq, node.len() - 1 - seq);
+                    for matched in egraph[self.reg(*i)].iter() {
+                        let n = matched.len();
+                        if n < prefix + suffix
+                            || !node.with_arity(n).map_or(false, |op| op.matches(matched))
+                        {
+                            continue;
+                        }
+                        let children = matched.children();
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.extend(&children[..prefix]);
+                        self.reg.extend(&children[n - suffix..]);
+                        self.seqs.truncate(*out_seq);
+                        self.seqs.push(children[prefix..n - suffix].into());
+                        self.run(egraph, remaining_instructions, check_data, yield_fn)?
+                    }
+                    return Ok(());
+                }
                 Instruction::Scan { out } => {
                     let remaining_instructions = instructions.as_slice();
                     for class in egraph.classes() {
                         self.reg.truncate(out.0 as usize);
                         self.reg.push(class.id);
-                        self.run(egraph, remaining_instructions, subst, yield_fn)?
+                        self.run(egraph, remaining_instructions, check_data, yield_fn)?
                     }
                     return Ok(());
                 }
@@ -76,6 +278,21 @@ impl Machine {
                         return Ok(());
                     }
                 }
+                Instruction::CompareSeq { i, j } => {
+                    let (a, b) = (&self.seqs[*i], &self.seqs[*j]);
+                    if a.len() != b.len()
+                        || a.iter()
+                            .zip(b.iter())
+                            .any(|(&a, &b)| egraph.find(a) != egraph.find(b))
+                    {
+                        return Ok(());
+                    }
+                }
+                Instruction::CheckData { i, predicate } => {
+                    if !check_data(*predicate, self.reg(*i)) {
+                        return Ok(());
+                    }
+                }
                 Instruction::Lookup { term, i } => {
                     self.lookup.clear();
                     for node in term {
@@ -101,38 +318,517 @@ impl Machine {
             }
         }
 
-        yield_fn(self, subst)
+        yield_fn(self)
+    }
+}
+
+/// The default for [`EGraph::with_max_ac_operands`].
+pub(crate) const DEFAULT_MAX_AC_OPERANDS: usize = 6;
+
+/// Returns the pairs of e-classes `[a, b]` such that `(op a b)` is in `eclass`
+/// modulo the commutativity (or associativity and commutativity) of `op`.
+///
+/// For AC operators, `eclass` is flattened into multisets of operands
+/// through nested applications of `op`, and each multiset is split in two.
+/// A split is only used if both halves are represented by existing e-classes,
+/// since pattern variables can only be bound to e-classes.
+/// Flattenings with more than [`EGraph::with_max_ac_operands`] operands
+/// are dropped, with a warning.
+fn ac_views<L, N>(egraph: &EGraph<L, N>, eclass: Id, op: &L) -> Vec<[Id; 2]>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    let mut views = vec![];
+    if AcKind::of(op) == AcKind::C {
+        let _ = egraph[eclass].for_each_matching_node::<()>(op, |node| {
+            let (a, b) = (node.children()[0], node.children()[1]);
+            views.push([a, b]);
+            views.push([b, a]);
+            Ok(())
+        });
+    } else {
+        let mut flattenings = vec![];
+        let mut stack = vec![];
+        let mut truncated = false;
+        flatten_ac(
+            egraph,
+            op,
+            eclass,
+            &mut stack,
+            &mut flattenings,
+            &mut truncated,
+        );
+        if truncated {
+            log::warn!(
+                "Dropped AC matches of e-class {} with more than {} operands, \
+                 see EGraph::with_max_ac_operands",
+                eclass,
+                egraph.max_ac_operands
+            );
+        }
+        let mut memo = HashMap::default();
+        for operands in flattenings {
+            let n = operands.len();
+            for mask in 1..(1u64 << n) - 1 {
+                let (left, right): (Vec<_>, Vec<_>) = (0..n).partition(|i| mask & (1 << i) != 0);
+                let left: Vec<Id> = left.into_iter().map(|i| operands[i]).collect();
+                let right: Vec<Id> = right.into_iter().map(|i| operands[i]).collect();
+                if let Some(a) = ac_class(egraph, op, &left, &mut memo) {
+                    if let Some(b) = ac_class(egraph, op, &right, &mut memo) {
+                        views.push([a, b]);
+                    }
+                }
+            }
+        }
+    }
+    for view in &mut views {
+        *view = view.map(|id| egraph.find(id));
+    }
+    views.sort_unstable();
+    views.dedup();
+    views
+}
+
+/// Collects the ways to flatten `eclass` into at least two operands of `op`,
+/// each as a sorted list of canonical e-class ids.
+///
+/// Sets `truncated` if a flattening was dropped for having too many operands.
+fn flatten_ac<L, N>(
+    egraph: &EGraph<L, N>,
+    op: &L,
+    eclass: Id,
+    stack: &mut Vec<Id>,
+    flattenings: &mut Vec<Vec<Id>>,
+    truncated: &mut bool,
+) where
+    L: Language,
+    N: Analysis<L>,
+{
+    let eclass = egraph.find(eclass);
+    // don't go around cycles
+    if stack.contains(&eclass) {
+        return;
+    }
+    stack.push(eclass);
+    let start = flattenings.len();
+    let _ = egraph[eclass].for_each_matching_node::<()>(op, |node| {
+        let mut expand = |child: Id, stack: &mut Vec<Id>| {
+            let child = egraph.find(child);
+            let mut options = vec![vec![child]];
+            flatten_ac(egraph, op, child, stack, &mut options, truncated);
+            options
+        };
+        let lefts = expand(node.children()[0], stack);
+        let rights = expand(node.children()[1], stack);
+        for left in &lefts {
+            for right in &rights {
+                if left.len() + right.len() > egraph.max_ac_operands {
+                    *truncated = true;
+                } else {
+                    let mut operands = [left.as_slice(), right.as_slice()].concat();
+                    operands.sort_unstable();
+                    flattenings.push(operands);
+                }
+            }
+        }
+        Ok(())
+    });
+    stack.pop();
+    let mut new = flattenings.split_off(start);
+    new.sort_unstable();
+    new.dedup();
+    flattenings.extend(new);
+}
+
+/// Finds an e-class representing `op` applied to the sorted `operands`,
+/// associated in any way.
+fn ac_class<L, N>(
+    egraph: &EGraph<L, N>,
+    op: &L,
+    operands: &[Id],
+    memo: &mut HashMap<Vec<Id>, Option<Id>>,
+) -> Option<Id>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    if let [operand] = operands {
+        return Some(*operand);
+    }
+    if let Some(&class) = memo.get(operands) {
+        return class;
+    }
+    // break symmetry by keeping the first operand on the left
+    let (first, rest) = operands.split_first().unwrap();
+    let n = rest.len();
+    let mut found = None;
+    for mask in 0..(1u64 << n) - 1 {
+        let mut left = vec![*first];
+        let mut right = vec![];
+        for (i, &id) in rest.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                left.push(id);
+            } else {
+                right.push(id);
+            }
+        }
+        let a = ac_class(egraph, op, &left, memo);
+        let b = a.and_then(|_| ac_class(egraph, op, &right, memo));
+        if let (Some(a), Some(b)) = (a, b) {
+            let mut node = op.clone();
+            node.children_mut().copy_from_slice(&[a, b]);
+            let mut swapped = node.clone();
+            swapped.children_mut().reverse();
+            found = egraph.lookup(node).or_else(|| egraph.lookup(swapped));
+            if found.is_some() {
+                break;
+            }
+        }
+    }
+    memo.insert(operands.to_vec(), found);
+    found
+}
+
+/// A term made of e-nodes of an e-graph, whose leaves are e-classes.
+pub(crate) enum AcTerm<L> {
+    Class(Id),
+    Node(L, Box<[AcTerm<L>; 2]>),
+}
+
+impl<L: Language> AcTerm<L> {
+    fn node(op: &L, left: Self, right: Self) -> Self {
+        AcTerm::Node(op.clone(), Box::new([left, right]))
+    }
+}
+
+/// Finds the reorderings modulo AC that a match of `pat` in `eclass`
+/// with `subst` relies on.
+///
+/// Each AC operator of `pat` is matched against a view of an e-class
+/// (see [`ac_views`]) that may not be an e-node of the e-graph.
+/// For each such view, this returns a term of e-nodes in that e-class and
+/// the term for the view, which are equal modulo AC as they are written.
+/// Once they are unioned, the instantiation of `pat` is in `eclass` by
+/// congruence, so explanations can justify the match step by step.
+///
+/// Returns `None` if the match cannot be found again, which can happen
+/// if the e-graph changed since it was searched.
+pub(crate) fn ac_witnesses<L, N>(
+    egraph: &EGraph<L, N>,
+    pat: &PatternAst<L>,
+    eclass: Id,
+    subst: &Subst,
+) -> Option<Vec<[AcTerm<L>; 2]>>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    let mut steps = vec![];
+    let pat = pat.as_ref();
+    let root = Id::from(pat.len() - 1);
+    if witness(egraph, pat, root, eclass, subst, &mut steps) {
+        Some(steps)
+    } else {
+        None
+    }
+}
+
+/// Matches the pattern node `i` of `pat` in `eclass` with `subst`,
+/// pushing the steps modulo AC that the match needs to `steps`.
+fn witness<L, N>(
+    egraph: &EGraph<L, N>,
+    pat: &[ENodeOrVar<L>],
+    i: Id,
+    eclass: Id,
+    subst: &Subst,
+    steps: &mut Vec<[AcTerm<L>; 2]>,
+) -> bool
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    let eclass = egraph.find(eclass);
+    let node = match &pat[usize::from(i)] {
+        ENodeOrVar::Var(v) => return egraph.find(subst[*v]) == eclass,
+        ENodeOrVar::ENode(node) => node,
+    };
+    let start = steps.len();
+    if AcKind::of(node) != AcKind::None {
+        for [a, b] in ac_views(egraph, eclass, node) {
+            steps.truncate(start);
+            if !witness_all(egraph, pat, node.children(), &[a, b], subst, steps) {
+                continue;
+            }
+            let mut viewed = node.clone();
+            viewed.children_mut().copy_from_slice(&[a, b]);
+            if egraph.lookup(viewed) == Some(eclass) {
+                return true;
+            }
+            if let Some(step) = ac_step(egraph, node, eclass, a, b) {
+                steps.push(step);
+                return true;
+            }
+        }
+    } else {
+        let seq = sequence_child(pat, node);
+        for matched in egraph[eclass].iter() {
+            steps.truncate(start);
+            let children = node.children();
+            let ids = matched.children();
+            let found = match seq {
+                None => {
+                    node.matches(matched) && witness_all(egraph, pat, children, ids, subst, steps)
+                }
+                Some(seq) => {
+                    let (prefix, suffix) = (seq, children.len() - 1 - seq);
+                    let n = ids.len();
+                    let same_seq = |v: &ENodeOrVar<L>| match v {
+                        ENodeOrVar::Var(v) => subst.get_seq(*v).map_or(false, |bound| {
+                            let spliced = &ids[prefix..n - suffix];
+                            bound.len() == spliced.len()
+                                && bound
+                                    .iter()
+                                    .zip(spliced)
+                                    .all(|(&a, &b)| egraph.find(a) == egraph.find(b))
+                        }),
+                        ENodeOrVar::ENode(_) => false,
+                    };
+                    n >= prefix + suffix
+                        && node.with_arity(n).map_or(false, |op| op.matches(matched))
+                        && same_seq(&pat[usize::from(children[seq])])
+                        && witness_all(
+                            egraph,
+                            pat,
+                            &children[..prefix],
+                            &ids[..prefix],
+                            subst,
+                            steps,
+                        )
+                        && witness_all(
+                            egraph,
+                            pat,
+                            &children[seq + 1..],
+                            &ids[n - suffix..],
+                            subst,
+                            steps,
+                        )
+                }
+            };
+            if found {
+                return true;
+            }
+        }
+    }
+    steps.truncate(start);
+    false
+}
+
+fn witness_all<L, N>(
+    egraph: &EGraph<L, N>,
+    pat: &[ENodeOrVar<L>],
+    children: &[Id],
+    eclasses: &[Id],
+    subst: &Subst,
+    steps: &mut Vec<[AcTerm<L>; 2]>,
+) -> bool
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    children
+        .iter()
+        .zip(eclasses)
+        .all(|(&child, &eclass)| witness(egraph, pat, child, eclass, subst, steps))
+}
+
+/// Finds a term of e-nodes in `eclass` and the term `op(a, b)`,
+/// where `[a, b]` is one of the [`ac_views`] of `eclass`,
+/// that are equal modulo AC as they are written.
+fn ac_step<L, N>(egraph: &EGraph<L, N>, op: &L, eclass: Id, a: Id, b: Id) -> Option<[AcTerm<L>; 2]>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    if AcKind::of(op) == AcKind::C {
+        // `op(a, b)` is not in `eclass`, so `op(b, a)` is
+        return Some([
+            AcTerm::node(op, AcTerm::Class(b), AcTerm::Class(a)),
+            AcTerm::node(op, AcTerm::Class(a), AcTerm::Class(b)),
+        ]);
+    }
+    let mut flattenings = vec![];
+    flatten_ac(
+        egraph,
+        op,
+        eclass,
+        &mut vec![],
+        &mut flattenings,
+        &mut false,
+    );
+    let mut memo = HashMap::default();
+    for operands in flattenings {
+        let n = operands.len();
+        for mask in 1..(1u64 << n) - 1 {
+            let (left, right): (Vec<_>, Vec<_>) = (0..n).partition(|i| mask & (1 << i) != 0);
+            let left: Vec<Id> = left.into_iter().map(|i| operands[i]).collect();
+            let right: Vec<Id> = right.into_iter().map(|i| operands[i]).collect();
+            let mut class =
+                |operands| ac_class(egraph, op, operands, &mut memo).map(|id| egraph.find(id));
+            if class(&left) != Some(a) || class(&right) != Some(b) {
+                continue;
+            }
+            let from = flattened_term(egraph, op, eclass, &operands, &mut vec![]);
+            let left = ac_term(egraph, op, &left, &mut memo);
+            let right = ac_term(egraph, op, &right, &mut memo);
+            if let (Some(from), Some(left), Some(right)) = (from, left, right) {
+                return Some([from, AcTerm::node(op, left, right)]);
+            }
+        }
+    }
+    None
+}
+
+/// Finds a term of nested applications of `op` in `eclass`
+/// whose operands are the sorted `operands`.
+fn flattened_term<L, N>(
+    egraph: &EGraph<L, N>,
+    op: &L,
+    eclass: Id,
+    operands: &[Id],
+    stack: &mut Vec<Id>,
+) -> Option<AcTerm<L>>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    let eclass = egraph.find(eclass);
+    // don't go around cycles
+    if stack.contains(&eclass) {
+        return None;
+    }
+    stack.push(eclass);
+    let mut found = None;
+    let n = operands.len();
+    'nodes: for node in egraph[eclass].iter().filter(|node| op.matches(node)) {
+        for mask in 1..(1u64 << n) - 1 {
+            let (left, right): (Vec<_>, Vec<_>) = (0..n).partition(|i| mask & (1 << i) != 0);
+            let side = |child: Id, part: Vec<usize>, stack: &mut Vec<Id>| {
+                let part: Vec<Id> = part.into_iter().map(|i| operands[i]).collect();
+                match part.as_slice() {
+                    [operand] if *operand == egraph.find(child) => Some(AcTerm::Class(*operand)),
+                    [_] => None,
+                    _ => flattened_term(egraph, op, child, &part, stack),
+                }
+            };
+            let left = side(node.children()[0], left, stack);
+            let right = left
+                .as_ref()
+                .and_then(|_| side(node.children()[1], right, stack));
+            if let (Some(left), Some(right)) = (left, right) {
+                found = Some(AcTerm::node(op, left, right));
+                break 'nodes;
+            }
+        }
+    }
+    stack.pop();
+    found
+}
+
+/// Like [`ac_class`], but returns the term of e-nodes that it finds.
+fn ac_term<L, N>(
+    egraph: &EGraph<L, N>,
+    op: &L,
+    operands: &[Id],
+    memo: &mut HashMap<Vec<Id>, Option<Id>>,
+) -> Option<AcTerm<L>>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    if let [operand] = operands {
+        return Some(AcTerm::Class(*operand));
     }
+    // the same search as `ac_class`, so the term is in the class it finds
+    let (first, rest) = operands.split_first().unwrap();
+    let n = rest.len();
+    for mask in 0..(1u64 << n) - 1 {
+        let mut left = vec![*first];
+        let mut right = vec![];
+        for (i, &id) in rest.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                left.push(id);
+            } else {
+                right.push(id);
+            }
+        }
+        let a = ac_class(egraph, op, &left, memo);
+        let b = a.and_then(|_| ac_class(egraph, op, &right, memo));
+        if let (Some(a), Some(b)) = (a, b) {
+            let mut node = op.clone();
+            node.children_mut().copy_from_slice(&[a, b]);
+            let mut swapped = node.clone();
+            swapped.children_mut().reverse();
+            if egraph.lookup(node).is_some() {
+                let left = ac_term(egraph, op, &left, memo)?;
+                return Some(AcTerm::node(op, left, ac_term(egraph, op, &right, memo)?));
+            } else if egraph.lookup(swapped).is_some() {
+                let right = ac_term(egraph, op, &right, memo)?;
+                return Some(AcTerm::node(op, right, ac_term(egraph, op, &left, memo)?));
+            }
+        }
+    }
+    None
 }
 
-struct Compiler<L> {
+struct Compiler<L: Language> {
     v2r: IndexMap<Var, Reg>,
+    // sequence variables are bound to indices into `Machine::seqs`
+    seq_v2s: IndexMap<Var, usize>,
     free_vars: Vec<HashSet<Var>>,
     subtree_size: Vec<usize>,
+    has_ac: Vec<bool>,
+    // how many e-classes contain each operator, if known
+    class_counts: HashMap<L::Discriminant, usize>,
+    data_predicates: Vec<(Var, Symbol)>,
     todo_nodes: HashMap<(Id, Reg), L>,
     instructions: Vec<Instruction<L>>,
     next_reg: Reg,
+    next_seq: usize,
 }
 
 impl<L: Language> Compiler<L> {
-    fn new() -> Self {
+    fn new(
+        class_counts: HashMap<L::Discriminant, usize>,
+        data_predicates: &[(Var, Symbol)],
+    ) -> Self {
         Self {
             free_vars: Default::default(),
             subtree_size: Default::default(),
+            has_ac: Default::default(),
+            class_counts,
+            data_predicates: data_predicates.to_vec(),
             v2r: Default::default(),
+            seq_v2s: Default::default(),
             todo_nodes: Default::default(),
             instructions: Default::default(),
             next_reg: Reg(0),
+            next_seq: 0,
         }
     }
 
     fn add_todo(&mut self, pattern: &PatternAst<L>, id: Id, reg: Reg) {
         match &pattern[id] {
             ENodeOrVar::Var(v) => {
+                assert!(
+                    !v.is_sequence(),
+                    "sequence variable {} must be a child of an e-node",
+                    v
+                );
                 if let Some(&j) = self.v2r.get(v) {
                     self.instructions.push(Instruction::Compare { i: reg, j })
                 } else {
-                    self.v2r.insert(*v, reg);
+                    self.bind_var(*v, reg);
                 }
             }
             ENodeOrVar::ENode(pat) => {
@@ -141,20 +837,36 @@ impl<L: Language> Compiler<L> {
         }
     }
 
+    /// Binds a variable to a register, checking its data predicates (if any)
+    /// right away so failing candidates are pruned early.
+    fn bind_var(&mut self, v: Var, reg: Reg) {
+        self.v2r.insert(v, reg);
+        for &(var, predicate) in &self.data_predicates {
+            if var == v {
+                self.instructions
+                    .push(Instruction::CheckData { i: reg, predicate });
+            }
+        }
+    }
+
     fn load_pattern(&mut self, pattern: &PatternAst<L>) {
         let len = pattern.len();
         self.free_vars = Vec::with_capacity(len);
         self.subtree_size = Vec::with_capacity(len);
+        self.has_ac = Vec::with_capacity(len);
 
         for node in pattern {
             let mut free = HashSet::default();
             let mut size = 0;
+            let mut has_ac = false;
             match node {
                 ENodeOrVar::ENode(n) => {
                     size = 1;
+                    has_ac = AcKind::of(n) != AcKind::None;
                     for &child in n.children() {
                         free.extend(&self.free_vars[usize::from(child)]);
                         size += self.subtree_size[usize::from(child)];
+                        has_ac |= self.has_ac[usize::from(child)];
                     }
                 }
                 ENodeOrVar::Var(v) => {
@@ -163,23 +875,30 @@ impl<L: Language> Compiler<L> {
             }
             self.free_vars.push(free);
             self.subtree_size.push(size);
+            self.has_ac.push(has_ac);
         }
     }
 
     fn next(&mut self) -> Option<((Id, Reg), L)> {
         // we take the max todo according to this key
         // - prefer grounded
+        // - prefer operators in fewer e-classes, if we know how many
         // - prefer more free variables
         // - prefer smaller term
-        let key = |(id, _): &&(Id, Reg)| {
-            let i = usize::from(*id);
+        let key = |todo: &&(Id, Reg)| {
+            let i = usize::from(todo.0);
+            let count = self
+                .class_counts
+                .get(&self.todo_nodes[*todo].discriminant())
+                .copied()
+                .unwrap_or(0);
             let n_bound = self.free_vars[i]
                 .iter()
                 .filter(|v| self.v2r.contains_key(*v))
                 .count();
             let n_free = self.free_vars[i].len() - n_bound;
             let size = self.subtree_size[i] as isize;
-            (n_free == 0, n_free, -size)
+            (n_free == 0, Reverse(count), n_free, -size)
         };
 
         self.todo_nodes
@@ -222,7 +941,7 @@ impl<L: Language> Compiler<L> {
                 // patternbinder is new variable
                 next_out.0 += 1;
                 add_new_pattern(self);
-                self.v2r.insert(v, self.next_reg); //add to known variables.
+                self.bind_var(v, self.next_reg); //add to known variables.
             }
         } else {
             // No pattern binder
@@ -231,7 +950,8 @@ impl<L: Language> Compiler<L> {
         }
 
         while let Some(((id, reg), node)) = self.next() {
-            if self.is_ground_now(id) && !node.is_leaf() {
+            // ground terms are looked up directly, unless they must be matched modulo AC
+            if self.is_ground_now(id) && !node.is_leaf() && !self.has_ac[usize::from(id)] {
                 let extracted = pattern.extract(id);
                 self.instructions.push(Instruction::Lookup {
                     i: reg,
@@ -243,6 +963,41 @@ impl<L: Language> Compiler<L> {
                         })
                         .collect(),
                 });
+            } else if let Some(seq) = sequence_child(pattern, &node) {
+                let out = next_out;
+                next_out.0 += node.len() as u32 - 1;
+                let out_seq = self.next_seq;
+                self.next_seq += 1;
+
+                let op = node.clone().map_children(|_| Id::from(0));
+                self.instructions.push(Instruction::BindSeq {
+                    i: reg,
+                    node: op,
+                    out,
+                    seq,
+                    out_seq,
+                });
+
+                for (i, &child) in node.children().iter().enumerate() {
+                    match i.cmp(&seq) {
+                        Ordering::Less => self.add_todo(pattern, child, Reg(out.0 + i as u32)),
+                        Ordering::Greater => {
+                            self.add_todo(pattern, child, Reg(out.0 + i as u32 - 1))
+                        }
+                        Ordering::Equal => {
+                            let var = match &pattern[child] {
+                                ENodeOrVar::Var(v) => *v,
+                                ENodeOrVar::ENode(_) => unreachable!(),
+                            };
+                            if let Some(&j) = self.seq_v2s.get(&var) {
+                                self.instructions
+                                    .push(Instruction::CompareSeq { i: j, j: out_seq });
+                            } else {
+                                self.seq_v2s.insert(var, out_seq);
+                            }
+                        }
+                    }
+                }
             } else {
                 let out = next_out;
                 next_out.0 += node.len() as u32;
@@ -271,13 +1026,46 @@ impl<L: Language> Compiler<L> {
         Program {
             instructions: self.instructions,
             subst,
+            seqs: self.seq_v2s.into_iter().collect(),
         }
     }
 }
 
+/// Counts the e-classes containing each operator of the patterns, which
+/// tells the compiler how selective matching each e-node is.
+pub(crate) fn class_counts<'a, L, N>(
+    egraph: &EGraph<L, N>,
+    patterns: impl IntoIterator<Item = &'a PatternAst<L>>,
+) -> HashMap<L::Discriminant, usize>
+where
+    L: Language + 'a,
+    N: Analysis<L>,
+{
+    let mut counts = HashMap::default();
+    for node in patterns.into_iter().flat_map(|p| p.as_ref()) {
+        if let ENodeOrVar::ENode(node) = node {
+            let key = node.discriminant();
+            let count = egraph.classes_for_op(&key).map_or(0, |ids| ids.len());
+            counts.insert(key, count);
+        }
+    }
+    counts
+}
+
 impl<L: Language> Program<L> {
-    pub(crate) fn compile_from_pat(pattern: &PatternAst<L>) -> Self {
-        let mut compiler = Compiler::new();
+    pub(crate) fn compile_from_pat(
+        pattern: &PatternAst<L>,
+        data_predicates: &[(Var, Symbol)],
+    ) -> Self {
+        Self::compile_from_pat_with_counts(pattern, data_predicates, Default::default())
+    }
+
+    pub(crate) fn compile_from_pat_with_counts(
+        pattern: &PatternAst<L>,
+        data_predicates: &[(Var, Symbol)],
+        class_counts: HashMap<L::Discriminant, usize>,
+    ) -> Self {
+        let mut compiler = Compiler::new(class_counts, data_predicates);
         compiler.compile(None, pattern);
         let program = compiler.extract();
         log::debug!("Compiled {:?} to {:?}", pattern.as_ref(), program);
@@ -285,24 +1073,73 @@ impl<L: Language> Program<L> {
     }
 
     pub(crate) fn compile_from_multi_pat(patterns: &[(Var, PatternAst<L>)]) -> Self {
-        let mut compiler = Compiler::new();
+        Self::compile_from_multi_pat_with_counts(patterns, Default::default())
+    }
+
+    pub(crate) fn compile_from_multi_pat_with_counts(
+        patterns: &[(Var, PatternAst<L>)],
+        class_counts: HashMap<L::Discriminant, usize>,
+    ) -> Self {
+        let mut compiler = Compiler::new(class_counts, &[]);
         for (var, pattern) in patterns {
             compiler.compile(Some(*var), pattern);
         }
         compiler.extract()
     }
 
-    pub fn run_with_limit<A>(
+    /// Returns the number of instructions in this program.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Returns true if this program has no instructions,
+    /// so it matches any e-class.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Runs this program on `eclass`, returning at most `limit` substitutions.
+    pub fn run_with_limit<A>(&self, egraph: &EGraph<L, A>, eclass: Id, limit: usize) -> Vec<Subst>
+    where
+        A: Analysis<L>,
+    {
+        self.run_with_limit_filtered(egraph, eclass, limit, &no_data_predicates, |_| true)
+    }
+
+    /// Like [`run_with_limit`](Program::run_with_limit), but only keeps
+    /// (and counts towards `limit`) the substitutions that pass `keep`.
+    /// The data predicates of the program are checked with `check_data`.
+    pub(crate) fn run_with_limit_filtered<A>(
         &self,
         egraph: &EGraph<L, A>,
         eclass: Id,
-        mut limit: usize,
+        limit: usize,
+        check_data: CheckData,
+        keep: impl FnMut(&Subst) -> bool,
     ) -> Vec<Subst>
     where
         A: Analysis<L>,
     {
         assert!(egraph.clean, "Tried to search a dirty e-graph!");
+        self.run_on_dirty(egraph, eclass, limit, check_data, keep)
+    }
 
+    /// Like [`run_with_limit_filtered`](Program::run_with_limit_filtered),
+    /// but also runs on an e-graph that is not rebuilt, as conditions are
+    /// checked while rules are being applied.
+    /// Classes are still compared with `find`, but a [`Instruction::Lookup`]
+    /// may miss enodes whose children are not canonical yet.
+    pub(crate) fn run_on_dirty<A>(
+        &self,
+        egraph: &EGraph<L, A>,
+        eclass: Id,
+        mut limit: usize,
+        check_data: CheckData,
+        mut keep: impl FnMut(&Subst) -> bool,
+    ) -> Vec<Subst>
+    where
+        A: Analysis<L>,
+    {
         if limit == 0 {
             return vec![];
         }
@@ -313,37 +1150,311 @@ impl<L: Language> Program<L> {
 
         let mut matches = Vec::new();
         machine
-            .run(
-                egraph,
-                &self.instructions,
-                &self.subst,
-                &mut |machine, subst| {
-                    if !egraph.analysis.allow_ematching_cycles() {
-                        if let Some((first, rest)) = machine.reg.split_first() {
-                            if rest.contains(first) {
-                                return Ok(());
-                            }
-                        }
-                    }
-
-                    let subst_vec = subst
-                        .vec
-                        .iter()
-                        // HACK we are reusing Ids here, this is bad
-                        .map(|(v, reg_id)| (*v, machine.reg(Reg(usize::from(*reg_id) as u32))))
-                        .collect();
-                    matches.push(Subst { vec: subst_vec });
-                    limit -= 1;
-                    if limit != 0 {
-                        Ok(())
-                    } else {
-                        Err(())
-                    }
-                },
-            )
+            .run(egraph, &self.instructions, check_data, &mut |machine| {
+                let subst = match self.make_subst(egraph, machine) {
+                    Some(subst) if keep(&subst) => subst,
+                    _ => return Ok(()),
+                };
+                matches.push(subst);
+                limit -= 1;
+                if limit != 0 {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            })
             .unwrap_or_default();
 
         log::trace!("Ran program, found {:?}", matches);
         matches
     }
+
+    /// Reads the substitution out of the registers of a machine that has
+    /// finished running this program, or `None` if the match is cyclic
+    /// and the analysis does not allow that.
+    fn make_subst<A: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, A>,
+        machine: &Machine,
+    ) -> Option<Subst> {
+        if !egraph.analysis.allow_ematching_cycles() {
+            if let Some((first, rest)) = machine.reg.split_first() {
+                if rest.contains(first) {
+                    return None;
+                }
+            }
+        }
+
+        let subst_vec = self
+            .subst
+            .vec
+            .iter()
+            // HACK we are reusing Ids here, this is bad
+            .map(|(v, reg_id)| (*v, machine.reg(Reg(usize::from(*reg_id) as u32))))
+            .collect();
+        // only programs with `BindSeq` bind sequence variables
+        let seqs = (!self.seqs.is_empty()).then(|| {
+            let seqs = self.seqs.iter();
+            Box::new(seqs.map(|(v, i)| (*v, machine.seqs[*i].clone())).collect())
+        });
+        Some(Subst {
+            vec: subst_vec,
+            seqs,
+        })
+    }
+}
+
+/// A node of a [`PatternTrie`]: a run of instructions shared by all the
+/// programs below it.
+#[derive(Debug, Clone)]
+struct TrieNode<L> {
+    instructions: Vec<Instruction<L>>,
+    // the programs that end at this node
+    programs: Vec<usize>,
+    children: Vec<TrieNode<L>>,
+}
+
+impl<L: Language> TrieNode<L> {
+    fn insert(&mut self, instructions: &[Instruction<L>], program: usize) {
+        let common = self
+            .instructions
+            .iter()
+            .zip(instructions)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < self.instructions.len() {
+            // split this node where the instructions diverge
+            let suffix = TrieNode {
+                instructions: self.instructions.split_off(common),
+                programs: std::mem::take(&mut self.programs),
+                children: std::mem::take(&mut self.children),
+            };
+            self.children.push(suffix);
+        }
+
+        let rest = &instructions[common..];
+        match rest.first() {
+            None => self.programs.push(program),
+            Some(first) => match self
+                .children
+                .iter_mut()
+                .find(|child| child.instructions.first() == Some(first))
+            {
+                Some(child) => child.insert(rest, program),
+                None => self.children.push(TrieNode {
+                    instructions: rest.to_vec(),
+                    programs: vec![program],
+                    children: vec![],
+                }),
+            },
+        }
+    }
+}
+
+impl Machine {
+    fn run_trie<L, N>(
+        &mut self,
+        egraph: &EGraph<L, N>,
+        node: &TrieNode<L>,
+        yield_fn: &mut impl FnMut(&Self, usize) -> Result,
+    ) -> Result
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        // the trie only holds plain patterns, which have no data predicates
+        self.run(
+            egraph,
+            &node.instructions,
+            &no_data_predicates,
+            &mut |machine| {
+                for &program in &node.programs {
+                    yield_fn(machine, program)?;
+                }
+                // each child starts from the registers bound so far
+                let (regs, seqs) = (machine.reg.len(), machine.seqs.len());
+                for child in &node.children {
+                    machine.reg.truncate(regs);
+                    machine.seqs.truncate(seqs);
+                    machine.run_trie(egraph, child, yield_fn)?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Many [`Pattern`]s merged into one matcher, so that a single traversal
+/// of the e-graph finds the matches of all of them.
+///
+/// The [`Program`]s of the patterns are stored in a trie, so instructions
+/// that patterns share (like binding the `+` at the root of many arithmetic
+/// rules) are run once for all of them.
+/// Patterns are grouped by their root operator, so each e-class is only
+/// matched against the patterns that can match it.
+///
+/// The matches are the same as searching each pattern on its own,
+/// and in the same order.
+/// [`SharedScheduler`] uses a [`PatternTrie`] to search the rules of a [`Runner`].
+///
+/// ```
+/// use egg::*;
+/// let patterns: Vec<Pattern<SymbolLang>> = vec![
+///     "(+ ?a ?b)".parse().unwrap(),
+///     "(+ ?a (* ?b ?c))".parse().unwrap(),
+///     "(* ?a 2)".parse().unwrap(),
+/// ];
+/// let trie = PatternTrie::new(patterns.clone());
+///
+/// let mut egraph = EGraph::<SymbolLang, ()>::default();
+/// egraph.add_expr(&"(+ x (* y 2))".parse().unwrap());
+/// egraph.rebuild();
+///
+/// let matches = trie.search(&egraph);
+/// for (pattern, matches) in patterns.iter().zip(&matches) {
+///     let substs = |ms: &[SearchMatches<SymbolLang>]| -> Vec<(Id, Vec<Subst>)> {
+///         ms.iter().map(|m| (m.eclass, m.substs.clone())).collect()
+///     };
+///     assert_eq!(substs(matches), substs(&pattern.search(&egraph)));
+/// }
+/// assert_eq!(matches[1].len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatternTrie<L: Language> {
+    patterns: Vec<Pattern<L>>,
+    roots: Vec<TrieRoot<L>>,
+}
+
+/// The patterns of a [`PatternTrie`] rooted at the same operator,
+/// or at a variable if `op` is `None`.
+#[derive(Debug, Clone)]
+struct TrieRoot<L: Language> {
+    op: Option<L::Discriminant>,
+    patterns: Vec<usize>,
+    node: TrieNode<L>,
+}
+
+impl<L: Language> PatternTrie<L> {
+    /// Merges the given patterns into a [`PatternTrie`].
+    pub fn new(patterns: impl IntoIterator<Item = Pattern<L>>) -> Self {
+        let patterns: Vec<Pattern<L>> = patterns.into_iter().collect();
+        let mut roots: Vec<TrieRoot<L>> = vec![];
+        for (i, pattern) in patterns.iter().enumerate() {
+            let op = match pattern.ast.last().unwrap() {
+                ENodeOrVar::ENode(node) => Some(node.discriminant()),
+                ENodeOrVar::Var(_) => None,
+            };
+            let index = match roots.iter().position(|root| root.op == op) {
+                Some(index) => index,
+                None => {
+                    let node = TrieNode {
+                        instructions: vec![],
+                        programs: vec![],
+                        children: vec![],
+                    };
+                    roots.push(TrieRoot {
+                        op,
+                        patterns: vec![],
+                        node,
+                    });
+                    roots.len() - 1
+                }
+            };
+            roots[index].patterns.push(i);
+            roots[index].node.insert(&pattern.program.instructions, i);
+        }
+        Self { patterns, roots }
+    }
+
+    /// Returns the patterns in this trie, in the order they were given.
+    pub fn patterns(&self) -> &[Pattern<L>] {
+        &self.patterns
+    }
+
+    /// Searches the e-graph for all the patterns at once, returning
+    /// the matches of each pattern in the order they were given.
+    pub fn search<N: Analysis<L>>(&self, egraph: &EGraph<L, N>) -> Vec<Vec<SearchMatches<L>>> {
+        self.search_with_limits(egraph, &vec![usize::MAX; self.patterns.len()])
+    }
+
+    /// Like [`search`](PatternTrie::search), but finds at most `limits[i]`
+    /// substitutions for the `i`th pattern,
+    /// like [`Searcher::search_with_limit`].
+    pub fn search_with_limits<N: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, N>,
+        limits: &[usize],
+    ) -> Vec<Vec<SearchMatches<L>>> {
+        let substs = self.search_substs(egraph, limits);
+        substs
+            .into_iter()
+            .zip(&self.patterns)
+            .map(|(matches, pattern)| {
+                matches
+                    .into_iter()
+                    .map(|(eclass, substs)| SearchMatches {
+                        eclass,
+                        substs,
+                        ast: Some(Cow::Borrowed(&pattern.ast)),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Searches for all the patterns, returning the e-classes and
+    /// substitutions matched by each pattern.
+    pub(crate) fn search_substs<N: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, N>,
+        limits: &[usize],
+    ) -> Vec<Vec<(Id, Vec<Subst>)>> {
+        assert!(egraph.clean, "Tried to search a dirty e-graph!");
+        assert_eq!(limits.len(), self.patterns.len(), "one limit per pattern");
+
+        let mut limits = limits.to_vec();
+        let mut results: Vec<Vec<(Id, Vec<Subst>)>> = vec![vec![]; self.patterns.len()];
+        let mut found: Vec<Vec<Subst>> = vec![vec![]; self.patterns.len()];
+        let mut machine = Machine::default();
+        for root in &self.roots {
+            let classes: Vec<Id> = match &root.op {
+                Some(op) => match egraph.classes_for_op(op) {
+                    Some(ids) => ids.collect(),
+                    None => continue,
+                },
+                None => egraph.classes().map(|c| c.id).collect(),
+            };
+            let mut remaining = root.patterns.iter().filter(|&&i| limits[i] > 0).count();
+            for eclass in classes {
+                if remaining == 0 {
+                    break;
+                }
+                machine.reg.clear();
+                machine.seqs.clear();
+                machine.reg.push(eclass);
+                let _ = machine.run_trie(egraph, &root.node, &mut |machine, i| {
+                    if limits[i] == 0 {
+                        return Ok(());
+                    }
+                    if let Some(subst) = self.patterns[i].program.make_subst(egraph, machine) {
+                        found[i].push(subst);
+                        limits[i] -= 1;
+                        if limits[i] == 0 {
+                            remaining -= 1;
+                            if remaining == 0 {
+                                return Err(());
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+                for &i in &root.patterns {
+                    if !found[i].is_empty() {
+                        results[i].push((eclass, std::mem::take(&mut found[i])));
+                    }
+                }
+            }
+        }
+        results
+    }
 }
@@ -17,6 +17,8 @@ See [`LanguageChildren`] for acceptable types of children `Id`s.
 
 Note that you can always implement [`Language`] yourself by just not using this
 macro.
+You can also write the `enum` yourself and use [`derive(Language)`](derive@Language),
+which supports the same forms of variants using `#[op = "+"]` attributes.
 
 Presently, the macro does not support data variant with children, but that may
 be added later.
@@ -102,7 +104,7 @@ macro_rules! define_language {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($($gen),*)?] { $($variants)* }
             [$($($where)*)?]
-            -> {} {} {} {} {} {}
+            -> {} {} {} {} {} {} {}
         );
     };
 }
@@ -114,7 +116,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      $decl:tt {$($matches:tt)*} $children:tt $children_mut:tt
-     $display:tt {$($from_op:tt)*}
+     $display:tt {$($from_op:tt)*} {$($with_arity:tt)*}
     ) => {
         $(#[$meta])*
         #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -136,6 +138,10 @@ macro_rules! __define_language {
 
             fn children(&self) -> &[$crate::Id] { match self $children }
             fn children_mut(&mut self) -> &mut [$crate::Id] { match self $children_mut }
+
+            fn with_arity(&self, arity: usize) -> ::std::option::Option<Self> {
+                match (self, arity) { $($with_arity)* }
+            }
         }
 
         impl<$($gen),*> ::std::fmt::Display for $name <$($gen),*> where $($where)* {
@@ -166,7 +172,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_arity:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -179,6 +185,7 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant => &mut [], }
             { $($display)*       ($name::$variant, f) => f.write_str($string), }
             { $($from_op)*       ($string, children) if children.is_empty() => Ok($name::$variant), }
+            { $($with_arity)*    ($name::$variant, arity) => (arity == 0).then(|| $name::$variant), }
         );
     };
 
@@ -190,7 +197,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_arity:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -207,6 +214,8 @@ macro_rules! __define_language {
                   Ok($name::$variant(children))
               },
             }
+            { $($with_arity)*    ($name::$variant(_), arity) => <$ids as $crate::LanguageChildren>::can_be_length(arity)
+                  .then(|| $name::$variant(<$ids as $crate::LanguageChildren>::from_vec(vec![$crate::Id::from(0); arity]))), }
         );
     };
 
@@ -218,7 +227,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_arity:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -231,6 +240,7 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant(_data) => &mut [], }
             { $($display)*       ($name::$variant(data), f) => ::std::fmt::Display::fmt(data, f), }
             { $($from_op)*       (op, children) if op.parse::<$data>().is_ok() && children.is_empty() => Ok($name::$variant(op.parse().unwrap())), }
+            { $($with_arity)*    ($name::$variant(data), arity) => (arity == 0).then(|| $name::$variant(data.clone())), }
         );
     };
 
@@ -242,7 +252,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_arity:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen)*]
@@ -260,6 +270,8 @@ macro_rules! __define_language {
                   Ok($name::$variant(data, children))
               },
             }
+            { $($with_arity)*    ($name::$variant(data, _), arity) => <$ids as $crate::LanguageChildren>::can_be_length(arity)
+                  .then(|| $name::$variant(data.clone(), <$ids as $crate::LanguageChildren>::from_vec(vec![$crate::Id::from(0); arity]))), }
         );
     };
 }
@@ -287,6 +299,9 @@ where the given expression should implement [`Condition`].
 For each of these, the macro will wrap the given applier in a
 [`ConditionalApplier`] with the given condition, with the first condition being
 the outermost, and the last condition being the innermost.
+Besides closures, conditions include [`ConditionEqual`] and the negative
+[`ConditionNotExists`] and [`ConditionNotMatch`], which check that a
+term or pattern is _not_ already in the e-graph.
 
 # Example
 ```
@@ -458,4 +473,94 @@ mod tests {
             "bad"; "?a" => "?a" if ConditionEqual::new(x.clone(), x)
         );
     }
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+    enum Derived<
+        T: std::fmt::Debug + std::fmt::Display + std::str::FromStr + Ord + std::hash::Hash + Clone,
+    > {
+        #[op = "+"]
+        Add([Id; 2]),
+        #[op = "-"]
+        Sub([Id; 2]),
+        #[op = "-"]
+        Neg(Id),
+        #[op = "list"]
+        List(Box<[Id]>),
+        #[op = "pi"]
+        Pi,
+        Num(T),
+        Call(Symbol, Vec<Id>),
+    }
+
+    #[test]
+    fn derive_language() {
+        let expr: RecExpr<Derived<i32>> = "(+ (- pi) (list 1 (- 2 3) (f x)))".parse().unwrap();
+        assert_eq!(expr.to_string(), "(+ (- pi) (list 1 (- 2 3) (f x)))");
+        let nodes = expr.as_ref();
+        assert!(nodes.iter().any(|n| matches!(n, Derived::Neg(_))));
+        assert!(nodes.iter().any(|n| matches!(n, Derived::Sub(_))));
+        assert!(nodes
+            .iter()
+            .any(|n| matches!(n, Derived::Call(_, ids) if ids.is_empty())));
+
+        assert!(Derived::<i32>::Num(1).matches(&Derived::Num(1)));
+        assert!(!Derived::<i32>::Num(1).matches(&Derived::Num(2)));
+        let list = |n: usize| Derived::<i32>::List(vec![Id::from(0); n].into());
+        assert!(list(2).matches(&list(2)));
+        assert!(!list(2).matches(&list(3)));
+
+        // operators with the wrong number of children fall back to `Call`
+        let pi = Derived::<i32>::from_op("pi", vec![]).unwrap();
+        assert_eq!(pi, Derived::Pi);
+        let call = Derived::<i32>::from_op("pi", vec![Id::from(0)]).unwrap();
+        assert_eq!(call, Derived::Call("pi".into(), vec![Id::from(0)]));
+        let err = DerivedArity::from_op("f", vec![Id::from(0); 2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "could not parse an e-node with operator \"f\" and children [0, 0]"
+        );
+
+        let rules: Vec<Rewrite<Derived<i32>, ()>> =
+            vec![rewrite!("neg"; "(- ?a ?b)" => "(+ ?a (- ?b))")];
+        let runner = Runner::default()
+            .with_expr(&"(- 1 2)".parse().unwrap())
+            .run(&rules);
+        let goal = "(+ 1 (- 2))".parse().unwrap();
+        assert_eq!(
+            runner.egraph.lookup_expr(&goal),
+            Some(runner.egraph.find(runner.roots[0]))
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Arity(usize);
+
+    impl From<&DerivedArity> for Arity {
+        fn from(node: &DerivedArity) -> Self {
+            Arity(node.len())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+    #[language(discriminant = Arity)]
+    enum DerivedArity {
+        #[op = "f"]
+        F(Id),
+        #[op = "g"]
+        G(Id),
+        Var(Symbol),
+    }
+
+    #[test]
+    fn derive_language_discriminant() {
+        let mut egraph = EGraph::<DerivedArity, ()>::default();
+        egraph.add_expr(&"(f (g x))".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph.classes_for_op(&Arity(1)).unwrap().count(), 2);
+        assert_eq!(egraph.classes_for_op(&Arity(0)).unwrap().count(), 1);
+
+        // both unary operators share a discriminant, but only `f` matches
+        let pattern: Pattern<DerivedArity> = "(f ?x)".parse().unwrap();
+        assert_eq!(pattern.search(&egraph).len(), 1);
+    }
 }
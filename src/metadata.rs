@@ -0,0 +1,233 @@
+use std::any::{Any, TypeId};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use crate::*;
+
+type MergeFn<T> = Arc<dyn Fn(&mut T, T) + Send + Sync>;
+
+/**
+How to combine the metadata of two e-classes when they are unioned,
+see [`EGraph::with_metadata`].
+
+The merge function is given the metadata of the e-class of the first
+argument of the union, and that of the second one.
+For congruence unions found by [`rebuild`](EGraph::rebuild), the order
+is unspecified.
+If only one of the e-classes has metadata, it is kept as is.
+**/
+pub struct MetadataMerge<T>(MergeFn<T>);
+
+impl<T> MetadataMerge<T> {
+    /// Merges the metadata with a custom function, that updates the metadata
+    /// of the first e-class with that of the second one.
+    pub fn new(merge: impl Fn(&mut T, T) + Send + Sync + 'static) -> Self {
+        MetadataMerge(Arc::new(merge))
+    }
+
+    /// Keeps the metadata of the first e-class.
+    pub fn keep_first() -> Self {
+        Self::new(|_, _| {})
+    }
+}
+
+impl<T, I> MetadataMerge<T>
+where
+    T: Extend<I> + IntoIterator<Item = I>,
+{
+    /// Appends the metadata of the second e-class to that of the first one.
+    pub fn concat() -> Self {
+        Self::new(|a, b| a.extend(b))
+    }
+}
+
+impl<T> Clone for MetadataMerge<T> {
+    fn clone(&self) -> Self {
+        MetadataMerge(self.0.clone())
+    }
+}
+
+impl<T> Debug for MetadataMerge<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetadataMerge")
+    }
+}
+
+trait AnyTable: Send + Sync {
+    fn union(&mut self, root: Id, first: Id, second: Id);
+    fn clone_box(&self) -> Box<dyn AnyTable>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Table<T> {
+    values: HashMap<Id, T>,
+    merge: MetadataMerge<T>,
+}
+
+impl<T: Clone + Send + Sync + 'static> AnyTable for Table<T> {
+    fn union(&mut self, root: Id, first: Id, second: Id) {
+        let first = hashmap_remove(&mut self.values, &first);
+        let second = hashmap_remove(&mut self.values, &second);
+        let merged = match (first, second) {
+            (Some(mut a), Some(b)) => {
+                (self.merge.0)(&mut a, b);
+                a
+            }
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => return,
+        };
+        self.values.insert(root, merged);
+    }
+
+    fn clone_box(&self) -> Box<dyn AnyTable> {
+        Box::new(Table {
+            values: self.values.clone(),
+            merge: self.merge.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The metadata side tables of an [`EGraph`], one per type.
+#[derive(Default)]
+pub(crate) struct MetadataTables {
+    tables: HashMap<TypeId, Box<dyn AnyTable>>,
+}
+
+impl MetadataTables {
+    pub(crate) fn insert_table<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        merge: MetadataMerge<T>,
+    ) {
+        let table = Table {
+            values: HashMap::<Id, T>::default(),
+            merge,
+        };
+        self.tables.insert(TypeId::of::<T>(), Box::new(table));
+    }
+
+    fn table<T: 'static>(&self) -> &HashMap<Id, T> {
+        match self.tables.get(&TypeId::of::<T>()) {
+            Some(table) => &table.as_any().downcast_ref::<Table<T>>().unwrap().values,
+            None => panic!("no metadata of type {}", std::any::type_name::<T>()),
+        }
+    }
+
+    fn table_mut<T: 'static>(&mut self) -> &mut HashMap<Id, T> {
+        match self.tables.get_mut(&TypeId::of::<T>()) {
+            Some(table) => {
+                &mut table
+                    .as_any_mut()
+                    .downcast_mut::<Table<T>>()
+                    .unwrap()
+                    .values
+            }
+            None => panic!("no metadata of type {}", std::any::type_name::<T>()),
+        }
+    }
+
+    pub(crate) fn get<T: 'static>(&self, id: Id) -> Option<&T> {
+        self.table().get(&id)
+    }
+
+    pub(crate) fn get_mut<T: 'static>(&mut self, id: Id) -> Option<&mut T> {
+        self.table_mut().get_mut(&id)
+    }
+
+    pub(crate) fn insert<T: 'static>(&mut self, id: Id, value: T) -> Option<T> {
+        self.table_mut().insert(id, value)
+    }
+
+    pub(crate) fn remove<T: 'static>(&mut self, id: Id) -> Option<T> {
+        hashmap_remove(self.table_mut(), &id)
+    }
+
+    /// Moves the metadata of the e-classes `first` and `second`
+    /// to `root`, merging it.
+    pub(crate) fn union(&mut self, root: Id, first: Id, second: Id) {
+        for table in self.tables.values_mut() {
+            table.union(root, first, second);
+        }
+    }
+}
+
+impl Clone for MetadataTables {
+    fn clone(&self) -> Self {
+        MetadataTables {
+            tables: self
+                .tables
+                .iter()
+                .map(|(k, table)| (*k, table.clone_box()))
+                .collect(),
+        }
+    }
+}
+
+impl Debug for MetadataTables {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataTables")
+            .field("tables", &self.tables.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn metadata_across_rebuild() {
+        crate::init_logger();
+        let mut egraph = EGraph::<S, ()>::default()
+            .with_metadata(MetadataMerge::<Name>::keep_first())
+            .with_metadata(MetadataMerge::<usize>::new(|a, b| *a = (*a).min(b)));
+        let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+        let fy = egraph.add_expr(&"(f y)".parse().unwrap());
+        let x = egraph.add(S::leaf("x"));
+        let y = egraph.add(S::leaf("y"));
+        egraph.set_metadata(fx, Name("fx"));
+        egraph.set_metadata(fy, Name("fy"));
+        egraph.set_metadata(fy, 3usize);
+        egraph.set_metadata(x, 5usize);
+        egraph.set_metadata(y, 2usize);
+
+        // (f y) and (f x) are unioned by congruence
+        egraph.union(y, x);
+        egraph.rebuild();
+        assert_eq!(egraph.find(fx), egraph.find(fy));
+        assert_eq!(egraph.metadata::<usize>(fx), Some(&3));
+        assert_eq!(egraph.metadata::<usize>(x), Some(&2));
+
+        let z = egraph.add(S::leaf("z"));
+        egraph.set_metadata(z, Name("z"));
+        egraph.union(z, fy);
+        egraph.rebuild();
+        assert_eq!(egraph.metadata(fx), Some(&Name("z")));
+
+        let mut copy = egraph.clone();
+        *copy.metadata_mut::<usize>(x).unwrap() = 0;
+        assert_eq!(copy.remove_metadata::<usize>(y), Some(0));
+        assert_eq!(egraph.metadata::<usize>(x), Some(&2));
+        assert_eq!(copy.metadata::<usize>(x), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no metadata of type")]
+    fn metadata_without_table() {
+        let mut egraph = EGraph::<S, ()>::default();
+        let x = egraph.add(S::leaf("x"));
+        egraph.set_metadata(x, Name("x"));
+    }
+}
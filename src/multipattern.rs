@@ -55,6 +55,18 @@ impl<L: Language> MultiPattern<L> {
         let program = machine::Program::compile_from_multi_pat(&asts);
         Self { asts, program }
     }
+
+    /// Returns the compiled [`Program`](machine::Program) that searches for this multipattern.
+    pub fn program(&self) -> &machine::Program<L> {
+        &self.program
+    }
+
+    /// Compiles this multipattern again, using the statistics of `egraph`.
+    /// See [`Pattern::recompile`].
+    pub fn recompile<N: Analysis<L>>(&mut self, egraph: &EGraph<L, N>) {
+        let counts = machine::class_counts(egraph, self.asts.iter().map(|(_, p)| p));
+        self.program = machine::Program::compile_from_multi_pat_with_counts(&self.asts, counts);
+    }
 }
 
 #[derive(Debug, Error)]
@@ -144,6 +156,10 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
         vars.dedup();
         vars
     }
+
+    fn recompile(&mut self, egraph: &EGraph<L, A>) {
+        MultiPattern::recompile(self, egraph)
+    }
 }
 
 impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
@@ -167,13 +183,17 @@ impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
         // TODO explanations?
         // the ids returned are kinda garbage
         let mut added = vec![];
+        let seqs = self
+            .asts
+            .iter()
+            .any(|(_, p)| crate::pattern::has_sequence_vars(p.as_ref()));
         for mat in matches {
             for subst in &mat.substs {
                 let mut subst = subst.clone();
                 let mut id_buf = vec![];
                 for (i, (v, p)) in self.asts.iter().enumerate() {
                     id_buf.resize(p.len(), 0.into());
-                    let id1 = crate::pattern::apply_pat(&mut id_buf, p, egraph, &subst);
+                    let id1 = crate::pattern::apply_pat(&mut id_buf, p, seqs, egraph, &subst);
                     if let Some(id2) = subst.insert(*v, id1) {
                         egraph.union(id1, id2);
                     }
@@ -31,6 +31,12 @@ use crate::*;
 /// As an [`Applier`], a [`Pattern`] performs the given substitution
 /// and adds the result to the [`EGraph`].
 ///
+/// A [sequence variable](Var::is_sequence) like `?xs...` matches any
+/// number of consecutive children of an e-node with a variable number of
+/// children (like `Box<[Id]>`), so `(f ?a ?xs...)` matches `f` with one
+/// or more children.
+/// Applying a pattern splices the bound children back in.
+///
 /// Importantly, [`Pattern`] implements [`FromStr`] if the
 /// [`Language`] does.
 /// This is probably how you'll create most [`Pattern`]s.
@@ -66,7 +72,10 @@ use crate::*;
 pub struct Pattern<L> {
     /// The actual pattern as a [`RecExpr`]
     pub ast: PatternAst<L>,
-    program: machine::Program<L>,
+    pub(crate) program: machine::Program<L>,
+    /// Whether the pattern has [sequence variables](Var::is_sequence),
+    /// so instantiating it without them can skip looking for them.
+    pub(crate) has_sequence_vars: bool,
 }
 
 /// A [`RecExpr`] that represents a
@@ -79,11 +88,13 @@ impl<L: Language> PatternAst<L> {
         let mut vars = HashMap::<Var, Var>::default();
         let mut new = PatternAst::default();
 
-        fn mkvar(i: usize) -> Var {
+        // keep the suffixes of sequence variables
+        fn mkvar(i: usize, v: Var) -> Var {
             let vs = &["?x", "?y", "?z", "?w"];
+            let suffix = if v.is_sequence() { "..." } else { "" };
             match vs.get(i) {
-                Some(v) => v.parse().unwrap(),
-                None => format!("?v{}", i - vs.len()).parse().unwrap(),
+                Some(v) => format!("{}{}", v, suffix).parse().unwrap(),
+                None => format!("?v{}{}", i - vs.len(), suffix).parse().unwrap(),
             }
         }
 
@@ -92,7 +103,7 @@ impl<L: Language> PatternAst<L> {
                 ENodeOrVar::ENode(_) => n.clone(),
                 ENodeOrVar::Var(v) => {
                     let i = vars.len();
-                    ENodeOrVar::Var(*vars.entry(*v).or_insert_with(|| mkvar(i)))
+                    ENodeOrVar::Var(*vars.entry(*v).or_insert_with(|| mkvar(i, *v)))
                 }
             });
         }
@@ -105,8 +116,29 @@ impl<L: Language> Pattern<L> {
     /// Creates a new pattern from the given pattern ast.
     pub fn new(ast: PatternAst<L>) -> Self {
         let ast = ast.compact();
-        let program = machine::Program::compile_from_pat(&ast);
-        Pattern { ast, program }
+        let program = machine::Program::compile_from_pat(&ast, &[]);
+        let has_sequence_vars = has_sequence_vars(&ast);
+        Pattern {
+            ast,
+            program,
+            has_sequence_vars,
+        }
+    }
+
+    /// Returns the compiled [`Program`] that searches for this pattern.
+    pub fn program(&self) -> &machine::Program<L> {
+        &self.program
+    }
+
+    /// Compiles this pattern again, ordering the matching by how many
+    /// e-classes of `egraph` contain each operator, rarest first.
+    ///
+    /// The statistics of an e-graph change as it grows, so this can be
+    /// called between iterations of equality saturation, see
+    /// [`Rewrite::recompile`].
+    pub fn recompile<N: Analysis<L>>(&mut self, egraph: &EGraph<L, N>) {
+        let counts = machine::class_counts(egraph, [&self.ast]);
+        self.program = machine::Program::compile_from_pat_with_counts(&self.ast, &[], counts);
     }
 
     /// Returns a list of the [`Var`]s in this pattern.
@@ -337,6 +369,14 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for Pattern<L> {
     fn vars(&self) -> Vec<Var> {
         Pattern::vars(self)
     }
+
+    fn recompile(&mut self, egraph: &EGraph<L, A>) {
+        Pattern::recompile(self, egraph)
+    }
+
+    fn as_pattern(&self) -> Option<&Pattern<L>> {
+        Some(self)
+    }
 }
 
 impl<L, A> Applier<L, A> for Pattern<L>
@@ -362,12 +402,21 @@ where
                 let did_something;
                 let id;
                 if egraph.are_explanations_enabled() {
+                    // a match modulo AC may see terms that are new to the e-graph
+                    let did_ac = mentions_ac(sast.unwrap())
+                        && egraph.union_modulo_ac(sast.unwrap(), mat.eclass, subst);
                     let (id_temp, did_something_temp) =
                         egraph.union_instantiations(sast.unwrap(), &self.ast, subst, rule_name);
-                    did_something = did_something_temp;
+                    did_something = did_something_temp || did_ac;
                     id = id_temp;
                 } else {
-                    id = apply_pat(&mut id_buf, &self.ast, egraph, subst);
+                    id = apply_pat(
+                        &mut id_buf,
+                        &self.ast,
+                        self.has_sequence_vars,
+                        egraph,
+                        subst,
+                    );
                     did_something = egraph.union(id, mat.eclass);
                 }
 
@@ -388,12 +437,20 @@ where
         rule_name: Symbol,
     ) -> Vec<Id> {
         let mut id_buf = vec![0.into(); self.ast.len()];
-        let id = apply_pat(&mut id_buf, &self.ast, egraph, subst);
+        let id = apply_pat(
+            &mut id_buf,
+            &self.ast,
+            self.has_sequence_vars,
+            egraph,
+            subst,
+        );
 
         if let Some(ast) = searcher_ast {
+            // a match modulo AC may see terms that are new to the e-graph
+            let did_ac = mentions_ac(ast) && egraph.union_modulo_ac(ast, eclass, subst);
             let (from, did_something) =
                 egraph.union_instantiations(ast, &self.ast, subst, rule_name);
-            if did_something {
+            if did_something || did_ac {
                 vec![from]
             } else {
                 vec![]
@@ -410,9 +467,79 @@ where
     }
 }
 
+/// Whether the pattern has an operator that matches modulo AC,
+/// see [`Language::ac_kind`].
+pub(crate) fn mentions_ac<L: Language>(pat: &PatternAst<L>) -> bool {
+    pat.as_ref().iter().any(|node| match node {
+        ENodeOrVar::ENode(n) => AcKind::of(n) != AcKind::None,
+        ENodeOrVar::Var(_) => false,
+    })
+}
+
+/// Whether the pattern has a [sequence variable](Var::is_sequence).
+pub(crate) fn has_sequence_vars<L: Language>(pat: &[ENodeOrVar<L>]) -> bool {
+    pat.iter()
+        .any(|node| matches!(node, ENodeOrVar::Var(v) if v.is_sequence()))
+}
+
+/// Returns the position of the [sequence variable](Var::is_sequence)
+/// among the children of `node`, if there is one.
+pub(crate) fn sequence_child<L: Language>(pat: &[ENodeOrVar<L>], node: &L) -> Option<usize> {
+    let mut seqs = node.children().iter().enumerate().filter(
+        |(_, &child)| matches!(&pat[usize::from(child)], ENodeOrVar::Var(v) if v.is_sequence()),
+    );
+    let seq = seqs.next().map(|(i, _)| i);
+    assert!(
+        seqs.next().is_none(),
+        "at most one sequence variable can be a child of each e-node"
+    );
+    seq
+}
+
+/// Instantiates an e-node of a pattern, mapping its children with `ids`
+/// and splicing in the `Id`s bound to its sequence variable, if any.
+/// `seqs` is whether the pattern has sequence variables at all,
+/// see [`has_sequence_vars`].
+pub(crate) fn instantiate_node<L: Language>(
+    pat: &[ENodeOrVar<L>],
+    seqs: bool,
+    node: &L,
+    subst: &Subst,
+    ids: impl Fn(Id) -> Id,
+) -> L {
+    let seq = match seqs.then(|| sequence_child(pat, node)).flatten() {
+        Some(seq) => seq,
+        None => return node.clone().map_children(ids),
+    };
+    let var = match &pat[usize::from(node.children()[seq])] {
+        ENodeOrVar::Var(v) => *v,
+        ENodeOrVar::ENode(_) => unreachable!(),
+    };
+    let spliced = match subst.get_seq(var) {
+        Some(spliced) => spliced,
+        None => panic!("Sequence var '{}' not found in {:?}", var, subst),
+    };
+    let children = node.children();
+    let mut new_children = Vec::with_capacity(children.len() - 1 + spliced.len());
+    new_children.extend(children[..seq].iter().map(|&id| ids(id)));
+    new_children.extend_from_slice(spliced);
+    new_children.extend(children[seq + 1..].iter().map(|&id| ids(id)));
+    let mut new_node = node.with_arity(new_children.len()).unwrap_or_else(|| {
+        panic!(
+            "{:?} cannot have {} children after splicing {}",
+            node,
+            new_children.len(),
+            var
+        )
+    });
+    new_node.children_mut().copy_from_slice(&new_children);
+    new_node
+}
+
 pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
     ids: &mut [Id],
     pat: &[ENodeOrVar<L>],
+    seqs: bool,
     egraph: &mut EGraph<L, A>,
     subst: &Subst,
 ) -> Id {
@@ -421,9 +548,11 @@ pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
 
     for (i, pat_node) in pat.iter().enumerate() {
         let id = match pat_node {
+            // spliced into the parent by `instantiate_node`
+            ENodeOrVar::Var(w) if w.is_sequence() => Id::from(0),
             ENodeOrVar::Var(w) => subst[*w],
             ENodeOrVar::ENode(e) => {
-                let n = e.clone().map_children(|child| ids[usize::from(child)]);
+                let n = instantiate_node(pat, seqs, e, subst, |child| ids[usize::from(child)]);
                 trace!("adding: {:?}", n);
                 egraph.add(n)
             }
@@ -504,6 +633,94 @@ mod tests {
         assert_eq!(n_matches("(h ?x 0 0)"), 1);
     }
 
+    #[test]
+    fn sequence_patterns() {
+        crate::init_logger();
+        let mut egraph = EGraph::default();
+        egraph.add_expr(&"(f)".parse().unwrap());
+        egraph.add_expr(&"(f a)".parse().unwrap());
+        egraph.add_expr(&"(f a b)".parse().unwrap());
+        egraph.add_expr(&"(f a b c)".parse().unwrap());
+        egraph.add_expr(&"(k (h a b) (h a b))".parse().unwrap());
+        egraph.add_expr(&"(k (h a b) (h a))".parse().unwrap());
+        egraph.rebuild();
+
+        let n_matches = |s: &str| s.parse::<Pattern<S>>().unwrap().n_matches(&egraph);
+
+        assert_eq!(n_matches("(f ?xs...)"), 4);
+        assert_eq!(n_matches("(f ?x ?xs...)"), 3);
+        assert_eq!(n_matches("(f ?xs... c)"), 1);
+        assert_eq!(n_matches("(f a ?xs... c)"), 1);
+        assert_eq!(n_matches("(f a ?xs... ?y ?z)"), 1);
+        assert_eq!(n_matches("(f ?xs... ?y)"), 3);
+        assert_eq!(n_matches("(k (h ?xs...) (h ?ys...))"), 2);
+        assert_eq!(n_matches("(k (h ?xs...) (h ?xs...))"), 1);
+
+        let pattern: Pattern<S> = "(f a ?xs...)".parse().unwrap();
+        let matches = pattern.search(&egraph);
+        let mut lens: Vec<usize> = matches
+            .iter()
+            .flat_map(|m| &m.substs)
+            .map(|s| s.get_seq("?xs...".parse().unwrap()).unwrap().len())
+            .collect();
+        lens.sort_unstable();
+        assert_eq!(lens, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn sequence_rewrites() {
+        crate::init_logger();
+        let rules: Vec<Rewrite<S, ()>> = vec![
+            rewrite!("rotate"; "(f ?x ?xs...)" => "(f ?xs... ?x)"),
+            rewrite!("wrap"; "(g ?xs...)" => "(h (f ?xs...) ?xs...)"),
+        ];
+        let start = "(g (f a b c))".parse().unwrap();
+        let mut runner = Runner::default()
+            .with_explanations_enabled()
+            .with_expr(&start)
+            .run(&rules);
+
+        let goal = "(h (f (f b c a)) (f c a b))".parse().unwrap();
+        let mut explanation = runner.explain_equivalence(&start, &goal);
+        explanation.check_proof(&rules);
+    }
+
+    #[test]
+    #[should_panic(expected = "at most one sequence variable")]
+    fn two_sequence_vars() {
+        let _: Pattern<S> = "(f ?xs... ?ys...)".parse().unwrap();
+    }
+
+    #[test]
+    fn recompile_same_matches() {
+        crate::init_logger();
+        let init_expr = &"(* (+ 1 (* 2 x)) (+ (* 3 x) (* x 4)))".parse().unwrap();
+        let rules: Vec<Rewrite<_, ()>> = vec![
+            rewrite!("comm-add"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("comm-mul"; "(* ?x ?y)" => "(* ?y ?x)"),
+            rewrite!("distribute"; "(* ?x (+ ?y ?z))" => "(+ (* ?x ?y) (* ?x ?z))"),
+        ];
+        let runner = Runner::default()
+            .with_expr(init_expr)
+            .with_iter_limit(3)
+            .run(&rules);
+        let egraph = &runner.egraph;
+
+        let count =
+            |p: &Pattern<S>| -> usize { p.search(egraph).iter().map(|m| m.substs.len()).sum() };
+        for s in [
+            "(* ?x (+ ?y ?z))",
+            "(+ (* ?x ?y) (* ?x ?z))",
+            "(+ (* 2 ?x) (* ?x ?y))",
+            "(* (+ ?a ?b) (+ ?c (* ?d ?a)))",
+        ] {
+            let mut pat: Pattern<S> = s.parse().unwrap();
+            let before = count(&pat);
+            pat.recompile(egraph);
+            assert_eq!(count(&pat), before, "{}", pat.program());
+        }
+    }
+
     #[test]
     fn search_with_limit() {
         crate::init_logger();
@@ -0,0 +1,486 @@
+use std::any::{Any, TypeId};
+
+use crate::*;
+
+/**
+An [`Analysis`] that can be combined with others into a product analysis.
+
+Tuples of [`AnalysisComponent`]s (of up to six elements) are themselves
+[`Analysis`]s (and [`AnalysisComponent`]s, so products can be nested).
+The data of a product is the tuple of the data of its components,
+so the data of each component is reached by position, like
+`egraph[id].data.0` or `egraph[id].data.1`, or by the type of the component
+with [`EGraph::component_data`] (see [`ProductAnalysis`]).
+[`merge`](Analysis::merge) and [`allow_ematching_cycles`](Analysis::allow_ematching_cycles)
+delegate to each component in order, and the [`DidMerge`]s are combined.
+The data of each component must be `'static`.
+
+[`Analysis::make`], [`Analysis::pre_union`] and [`Analysis::modify`] take
+an [`EGraph`] of the analysis itself, which a component doesn't have,
+so components implement them generically over the e-graph's analysis instead.
+Each method is given `get`, a function to find the component's data
+in the data of the e-graph's analysis.
+The component's own [`Analysis`] implementation can just call these with
+the identity function, so the logic is only written once.
+
+# Why an [`Analysis`] is not a component by itself
+
+[`Analysis::make`] takes an `EGraph<L, Self>`, whose e-classes each hold
+exactly the analysis' own data.
+In a product the e-graph is an `EGraph<L, (A, B)>` whose e-classes hold
+`(A::Data, B::Data)`, and there is no `EGraph<L, A>` to hand to `A::make`
+short of copying the whole e-graph.
+So an existing analysis is made a component by moving the body of its `make`
+(and `pre_union` and `modify`, if it has them) into [`make_component`]
+and friends, reading data through `get` instead of `egraph[id].data`,
+as `ConstFold` and `Depth` do below.
+A [`LatticeAnalysis`] needs no changes, since its [`LatticeAdapter`]
+is already a component.
+
+[`make_component`]: AnalysisComponent::make_component
+
+# Example
+
+```
+use egg::*;
+
+#[derive(Default)]
+struct ConstFold;
+impl Analysis<SymbolLang> for ConstFold {
+    type Data = Option<i32>;
+    fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang, id: Id) -> Self::Data {
+        Self::make_component(egraph, enode, id, &|d| d)
+    }
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        merge_option(a, b, |a, b| {
+            assert_eq!(*a, b, "merged non-equal constants");
+            DidMerge(false, false)
+        })
+    }
+}
+impl AnalysisComponent<SymbolLang> for ConstFold {
+    fn make_component<N: Analysis<SymbolLang>>(
+        egraph: &mut EGraph<SymbolLang, N>,
+        enode: &SymbolLang,
+        _id: Id,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) -> Self::Data {
+        let x = |i: &Id| *get(&egraph[*i].data);
+        match (enode.op.as_str(), enode.children.as_slice()) {
+            ("+", [a, b]) => Some(x(a)? + x(b)?),
+            (op, []) => op.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Depth;
+impl Analysis<SymbolLang> for Depth {
+    type Data = usize;
+    fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang, id: Id) -> Self::Data {
+        Self::make_component(egraph, enode, id, &|d| d)
+    }
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        merge_min(a, b)
+    }
+}
+impl AnalysisComponent<SymbolLang> for Depth {
+    fn make_component<N: Analysis<SymbolLang>>(
+        egraph: &mut EGraph<SymbolLang, N>,
+        enode: &SymbolLang,
+        _id: Id,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) -> Self::Data {
+        let depths = enode.children.iter().map(|c| *get(&egraph[*c].data));
+        1 + depths.max().unwrap_or(0)
+    }
+}
+
+let mut egraph = EGraph::<SymbolLang, (ConstFold, Depth)>::default();
+let sum = egraph.add_expr(&"(+ 1 (+ 2 3))".parse().unwrap());
+let x = egraph.add_expr(&"(+ x 6)".parse().unwrap());
+egraph.rebuild();
+assert_eq!(egraph[sum].data, (Some(6), 3));
+assert_eq!(egraph[x].data.0, None);
+
+// merging takes the constant from one side and the smallest depth
+egraph.union(sum, x);
+egraph.rebuild();
+assert_eq!(egraph[x].data, (Some(6), 2));
+```
+**/
+pub trait AnalysisComponent<L: Language>: Analysis<L> {
+    /// [`Analysis::make`] for this analysis as part of the analysis `N`.
+    ///
+    /// `get` finds the data of this analysis in the data of `N`,
+    /// for example to look at the data of the children of `enode`.
+    fn make_component<N: Analysis<L>>(
+        egraph: &mut EGraph<L, N>,
+        enode: &L,
+        id: Id,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) -> Self::Data;
+
+    /// [`Analysis::pre_union`] for this analysis as part of the analysis `N`.
+    ///
+    /// By default it does nothing.
+    #[allow(unused_variables)]
+    fn pre_union_component<N: Analysis<L>>(
+        egraph: &EGraph<L, N>,
+        id1: Id,
+        id2: Id,
+        justification: &Option<Justification>,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) {
+    }
+
+    /// [`Analysis::modify`] for this analysis as part of the analysis `N`.
+    ///
+    /// By default it does nothing.
+    #[allow(unused_variables)]
+    fn modify_component<N: Analysis<L>>(
+        egraph: &mut EGraph<L, N>,
+        id: Id,
+        get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) {
+    }
+}
+
+/**
+A product of [`AnalysisComponent`]s, which can find the data of a component
+by the component's type.
+
+This is implemented by the tuples that are product analyses.
+Only the direct components of a product are found, not those nested in
+another product.
+See [`EGraph::component_data`] for an example.
+**/
+pub trait ProductAnalysis<L: Language>: Analysis<L> {
+    /// Returns the data of the component `C` in `data`,
+    /// or `None` if `C` is not a component of this product.
+    fn component<C>(data: &Self::Data) -> Option<&C::Data>
+    where
+        C: Analysis<L> + 'static,
+        C::Data: 'static;
+
+    /// Like [`component`](ProductAnalysis::component), but mutable.
+    fn component_mut<C>(data: &mut Self::Data) -> Option<&mut C::Data>
+    where
+        C: Analysis<L> + 'static,
+        C::Data: 'static;
+}
+
+impl<L: Language, N: ProductAnalysis<L>> EGraph<L, N> {
+    /// Returns the data of the component `C` of the product analysis
+    /// of this e-graph, in the e-class `id`.
+    ///
+    /// Panics if `C` is not a component of the product.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// #[derive(Default)]
+    /// struct Size;
+    /// impl Analysis<SymbolLang> for Size {
+    ///     type Data = usize;
+    ///     fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang, id: Id) -> usize {
+    ///         Self::make_component(egraph, enode, id, &|d| d)
+    ///     }
+    ///     fn merge(&mut self, a: &mut usize, b: usize) -> DidMerge {
+    ///         merge_min(a, b)
+    ///     }
+    /// }
+    /// impl AnalysisComponent<SymbolLang> for Size {
+    ///     fn make_component<N: Analysis<SymbolLang>>(
+    ///         egraph: &mut EGraph<SymbolLang, N>,
+    ///         enode: &SymbolLang,
+    ///         _id: Id,
+    ///         get: &dyn Fn(&N::Data) -> &usize,
+    ///     ) -> usize {
+    ///         1 + enode.children.iter().map(|c| *get(&egraph[*c].data)).sum::<usize>()
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ((), Size)>::default();
+    /// let id = egraph.add_expr(&"(f x y)".parse().unwrap());
+    /// egraph.rebuild();
+    /// assert_eq!(*egraph.component_data::<Size>(id), 3);
+    /// ```
+    pub fn component_data<C>(&self, id: Id) -> &C::Data
+    where
+        C: Analysis<L> + 'static,
+        C::Data: 'static,
+    {
+        N::component::<C>(&self[id].data).unwrap_or_else(|| {
+            panic!(
+                "{} is not a component of the analysis",
+                std::any::type_name::<C>()
+            )
+        })
+    }
+
+    /// Like [`component_data`](EGraph::component_data), but mutable.
+    pub fn component_data_mut<C>(&mut self, id: Id) -> &mut C::Data
+    where
+        C: Analysis<L> + 'static,
+        C::Data: 'static,
+    {
+        N::component_mut::<C>(&mut self[id].data).unwrap_or_else(|| {
+            panic!(
+                "{} is not a component of the analysis",
+                std::any::type_name::<C>()
+            )
+        })
+    }
+}
+
+impl<L: Language> AnalysisComponent<L> for () {
+    fn make_component<N: Analysis<L>>(
+        _egraph: &mut EGraph<L, N>,
+        _enode: &L,
+        _id: Id,
+        _get: &dyn Fn(&N::Data) -> &Self::Data,
+    ) -> Self::Data {
+    }
+}
+
+macro_rules! impl_product {
+    ($($C:ident $i:tt),*) => {
+        impl<L, $($C),*> Analysis<L> for ($($C,)*)
+        where
+            L: Language,
+            $($C: AnalysisComponent<L>, $C::Data: 'static,)*
+        {
+            type Data = ($($C::Data,)*);
+
+            fn make(egraph: &mut EGraph<L, Self>, enode: &L, id: Id) -> Self::Data {
+                Self::make_component(egraph, enode, id, &|d| d)
+            }
+
+            fn pre_union(
+                egraph: &EGraph<L, Self>,
+                id1: Id,
+                id2: Id,
+                justification: &Option<Justification>,
+            ) {
+                Self::pre_union_component(egraph, id1, id2, justification, &|d| d)
+            }
+
+            fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+                let mut did = DidMerge(false, false);
+                $(did = did | self.$i.merge(&mut a.$i, b.$i);)*
+                did
+            }
+
+            fn modify(egraph: &mut EGraph<L, Self>, id: Id) {
+                Self::modify_component(egraph, id, &|d| d)
+            }
+
+            fn allow_ematching_cycles(&self) -> bool {
+                $(self.$i.allow_ematching_cycles())&&*
+            }
+        }
+
+        impl<L, $($C),*> AnalysisComponent<L> for ($($C,)*)
+        where
+            L: Language,
+            $($C: AnalysisComponent<L>, $C::Data: 'static,)*
+        {
+            fn make_component<N: Analysis<L>>(
+                egraph: &mut EGraph<L, N>,
+                enode: &L,
+                id: Id,
+                get: &dyn Fn(&N::Data) -> &Self::Data,
+            ) -> Self::Data {
+                ($($C::make_component(egraph, enode, id, &|d| &get(d).$i),)*)
+            }
+
+            fn pre_union_component<N: Analysis<L>>(
+                egraph: &EGraph<L, N>,
+                id1: Id,
+                id2: Id,
+                justification: &Option<Justification>,
+                get: &dyn Fn(&N::Data) -> &Self::Data,
+            ) {
+                $($C::pre_union_component(egraph, id1, id2, justification, &|d| &get(d).$i);)*
+            }
+
+            fn modify_component<N: Analysis<L>>(
+                egraph: &mut EGraph<L, N>,
+                id: Id,
+                get: &dyn Fn(&N::Data) -> &Self::Data,
+            ) {
+                $($C::modify_component(egraph, id, &|d| &get(d).$i);)*
+            }
+        }
+
+        impl<L, $($C),*> ProductAnalysis<L> for ($($C,)*)
+        where
+            L: Language,
+            $($C: AnalysisComponent<L> + 'static, $C::Data: 'static,)*
+        {
+            fn component<T>(data: &Self::Data) -> Option<&T::Data>
+            where
+                T: Analysis<L> + 'static,
+                T::Data: 'static,
+            {
+                $(if TypeId::of::<T>() == TypeId::of::<$C>() {
+                    return (&data.$i as &dyn Any).downcast_ref();
+                })*
+                None
+            }
+
+            fn component_mut<T>(data: &mut Self::Data) -> Option<&mut T::Data>
+            where
+                T: Analysis<L> + 'static,
+                T::Data: 'static,
+            {
+                $(if TypeId::of::<T>() == TypeId::of::<$C>() {
+                    return (&mut data.$i as &mut dyn Any).downcast_mut();
+                })*
+                None
+            }
+        }
+    };
+}
+
+impl_product!(A 0, B 1);
+impl_product!(A 0, B 1, C 2);
+impl_product!(A 0, B 1, C 2, D 3);
+impl_product!(A 0, B 1, C 2, D 3, E 4);
+impl_product!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    // folds additions, adding the result to the e-graph in `modify`
+    #[derive(Default)]
+    struct Fold;
+    impl Analysis<S> for Fold {
+        type Data = Option<i32>;
+        fn make(egraph: &mut EGraph<S, Self>, enode: &S, id: Id) -> Self::Data {
+            Self::make_component(egraph, enode, id, &|d| d)
+        }
+        fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+            merge_option(a, b, |a, b| {
+                assert_eq!(*a, b);
+                DidMerge(false, false)
+            })
+        }
+        fn modify(egraph: &mut EGraph<S, Self>, id: Id) {
+            Self::modify_component(egraph, id, &|d| d)
+        }
+    }
+    impl AnalysisComponent<S> for Fold {
+        fn make_component<N: Analysis<S>>(
+            egraph: &mut EGraph<S, N>,
+            enode: &S,
+            _id: Id,
+            get: &dyn Fn(&N::Data) -> &Self::Data,
+        ) -> Self::Data {
+            let x = |i: &Id| *get(&egraph[*i].data);
+            match (enode.op.as_str(), enode.children.as_slice()) {
+                ("+", [a, b]) => Some(x(a)? + x(b)?),
+                (op, []) => op.parse().ok(),
+                _ => None,
+            }
+        }
+        fn modify_component<N: Analysis<S>>(
+            egraph: &mut EGraph<S, N>,
+            id: Id,
+            get: &dyn Fn(&N::Data) -> &Self::Data,
+        ) {
+            if let Some(c) = *get(&egraph[id].data) {
+                let added = egraph.add(S::leaf(c.to_string()));
+                egraph.union(id, added);
+            }
+        }
+    }
+
+    // the free variables of each e-class
+    #[derive(Default)]
+    struct Free;
+    impl Analysis<S> for Free {
+        type Data = Vec<Symbol>;
+        fn make(egraph: &mut EGraph<S, Self>, enode: &S, id: Id) -> Self::Data {
+            Self::make_component(egraph, enode, id, &|d| d)
+        }
+        fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+            // the intersection, since equal terms can only need the same variables
+            let len = (a.len(), b.len());
+            a.retain(|v| b.contains(v));
+            DidMerge(a.len() != len.0, a.len() != len.1)
+        }
+    }
+    impl AnalysisComponent<S> for Free {
+        fn make_component<N: Analysis<S>>(
+            egraph: &mut EGraph<S, N>,
+            enode: &S,
+            _id: Id,
+            get: &dyn Fn(&N::Data) -> &Self::Data,
+        ) -> Self::Data {
+            let mut free: Vec<Symbol> = enode
+                .children
+                .iter()
+                .flat_map(|c| get(&egraph[*c].data).clone())
+                .collect();
+            if enode.is_leaf() && enode.op.as_str().parse::<i32>().is_err() {
+                free.push(enode.op);
+            }
+            free.sort();
+            free.dedup();
+            free
+        }
+    }
+
+    #[test]
+    fn product_analysis() {
+        crate::init_logger();
+        let rules: Vec<Rewrite<S, (Fold, Free)>> = vec![
+            rewrite!("mul-zero"; "(* ?a 0)" => "0"),
+            rewrite!("comm-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+        ];
+        let start = "(+ (* x 0) (+ 1 2))".parse().unwrap();
+        let runner = Runner::<S, (Fold, Free)>::default()
+            .with_expr(&start)
+            .run(&rules);
+        let egraph = &runner.egraph;
+        let root = egraph.find(runner.roots[0]);
+
+        // `(* x 0)` was folded to 0, and the sum to 3 by `Fold::modify_component`
+        assert_eq!(egraph[root].data, (Some(3), vec![]));
+        let three = egraph.lookup(S::leaf("3")).unwrap();
+        assert_eq!(egraph.find(three), root);
+        let x_times_zero = egraph.lookup_expr(&"(* x 0)".parse().unwrap()).unwrap();
+        assert_eq!(egraph[x_times_zero].data.1, vec![]);
+        assert_eq!(*egraph.component_data::<Fold>(root), Some(3));
+        assert!(egraph.component_data::<Free>(x_times_zero).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a component of the analysis")]
+    fn missing_component() {
+        let mut egraph = EGraph::<S, ((Fold, ()), Free)>::default();
+        let a = egraph.add_expr(&"(+ 2 3)".parse().unwrap());
+        egraph.rebuild();
+        egraph.component_data_mut::<Free>(a).push(Symbol::from("z"));
+        assert_eq!(egraph[a].data.1, vec![Symbol::from("z")]);
+        // only direct components are found
+        egraph.component_data::<Fold>(a);
+    }
+
+    #[test]
+    fn nested_product_analysis() {
+        let mut egraph = EGraph::<S, ((Fold, ()), Free)>::default();
+        let a = egraph.add_expr(&"(+ y (+ 2 3))".parse().unwrap());
+        let b = egraph.add_expr(&"(+ y 5)".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(egraph[a].data, ((None, ()), vec![Symbol::from("y")]));
+        let sum = egraph.lookup_expr(&"(+ 2 3)".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(sum), egraph.lookup(S::leaf("5")).unwrap());
+        // hashconsing found `(+ y 5)` once `(+ 2 3)` was folded
+        assert_eq!(egraph.find(a), egraph.find(b));
+    }
+}
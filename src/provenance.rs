@@ -0,0 +1,69 @@
+use crate::*;
+
+/// Where an e-node came from: the rule that first added it to the
+/// [`EGraph`] and in which iteration.
+///
+/// See [`EGraph::with_provenance_enabled`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Provenance {
+    /// The name of the [`Rewrite`] that added the e-node,
+    /// or `None` if it was added some other way, like [`EGraph::add_expr`].
+    pub rule: Option<Symbol>,
+    /// The [`Runner`] iteration in which the e-node was added.
+    pub iteration: usize,
+}
+
+/// The provenance of every e-node, indexed like [`EGraph::nodes`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct ProvenanceTable {
+    pub(crate) current: Provenance,
+    pub(crate) nodes: Vec<Provenance>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rewrite as rw, SymbolLang as S, *};
+
+    #[test]
+    fn provenance() {
+        crate::init_logger();
+        let rules: &[Rewrite<S, ()>] = &[
+            rw!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rw!("zero"; "(+ ?x 0)" => "(f ?x)"),
+        ];
+        for explanations in [false, true] {
+            let mut egraph = EGraph::<S, ()>::default();
+            if explanations {
+                egraph = egraph.with_explanations_enabled();
+            }
+            let a = egraph.add_expr(&"a".parse().unwrap());
+            let mut egraph = egraph.with_provenance_enabled();
+            let sum = egraph.add_expr(&"(+ 0 a)".parse().unwrap());
+            let runner = Runner::default()
+                .with_egraph(egraph)
+                .with_iter_limit(2)
+                .run(rules);
+            let egraph = runner.egraph;
+
+            assert_eq!(egraph.node_provenance(a), Some(Provenance::default()));
+            assert_eq!(egraph.node_provenance(sum), Some(Provenance::default()));
+            let f = S::new("f", vec![egraph.find(a)]);
+            let expected = Provenance {
+                rule: Some("zero".into()),
+                iteration: 1,
+            };
+            assert_eq!(egraph.enode_provenance(&f), Some(expected));
+            assert_eq!(egraph.provenance_by_enode()[&f], expected);
+            assert_eq!(egraph.provenance_counts()[&Some("comm".into())], 1);
+
+            let dot = egraph.dot().with_provenance_colors(true).to_string();
+            assert!(dot.contains(
+                "[label = \"f\", tooltip = \"zero @ 1\", style = filled, fillcolor = lightpink]"
+            ));
+            assert!(dot.contains("[label = \"a\", tooltip = \"none @ 0\"]"));
+            assert!(!egraph.dot().to_string().contains("tooltip"));
+        }
+    }
+}
@@ -91,6 +91,46 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
         self.searcher.search_with_limit(egraph, limit)
     }
 
+    /// Recompiles the searcher of this rewrite for `egraph`,
+    /// see [`Searcher::recompile`] and [`Pattern::recompile`].
+    ///
+    /// This is useful between runs of a [`Runner`],
+    /// since the best order to match a pattern in depends on the e-graph.
+    /// [`Runner::with_recompile_every`] does this during a run.
+    ///
+    /// The searcher is behind an [`Arc`], so this returns `false` (and does
+    /// nothing) if it is shared with another rewrite, for example because
+    /// the rewrite was cloned.
+    ///
+    /// ```
+    /// use egg::{*, rewrite as rw};
+    /// let mut rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+    ///     rw!("fold"; "(+ (f ?x) (g ?x))" => "(h ?x)"),
+    /// ];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(+ (f a) (g a))".parse().unwrap())
+    ///     .with_iter_limit(1)
+    ///     .run(&rules);
+    /// for rule in &mut rules {
+    ///     assert!(rule.recompile(&runner.egraph));
+    /// }
+    /// let runner = Runner::default().with_egraph(runner.egraph).run(&rules);
+    /// assert!(runner.egraph.lookup_expr(&"(h a)".parse().unwrap()).is_some());
+    ///
+    /// let shared = rules[0].clone();
+    /// assert!(!rules[0].recompile(&runner.egraph));
+    /// # drop(shared);
+    /// ```
+    pub fn recompile(&mut self, egraph: &EGraph<L, N>) -> bool {
+        match Arc::get_mut(&mut self.searcher) {
+            Some(searcher) => {
+                searcher.recompile(egraph);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Call [`apply_matches`] on the [`Applier`].
     ///
     /// [`apply_matches`]: Applier::apply_matches()
@@ -210,6 +250,24 @@ where
 
     /// Returns a list of the variables bound by this Searcher
     fn vars(&self) -> Vec<Var>;
+
+    /// Prepares this searcher to search `egraph`, for example by
+    /// compiling its patterns again using the statistics of `egraph`.
+    ///
+    /// This is called by [`Rewrite::recompile`].
+    /// The default implementation does nothing.
+    fn recompile(&mut self, egraph: &EGraph<L, N>) {
+        let _ = egraph;
+    }
+
+    /// Returns this searcher as a plain [`Pattern`], if it is one.
+    ///
+    /// Plain patterns can be searched together in a [`PatternTrie`],
+    /// see [`SharedScheduler`].
+    /// The default implementation returns `None`.
+    fn as_pattern(&self) -> Option<&Pattern<L>> {
+        None
+    }
 }
 
 /// The righthand side of a [`Rewrite`].
@@ -526,8 +584,9 @@ where
     fn check(&self, egraph: &mut EGraph<L, N>, _eclass: Id, subst: &Subst) -> bool {
         let mut id_buf_1 = vec![0.into(); self.p1.ast.len()];
         let mut id_buf_2 = vec![0.into(); self.p2.ast.len()];
-        let a1 = apply_pat(&mut id_buf_1, &self.p1.ast, egraph, subst);
-        let a2 = apply_pat(&mut id_buf_2, &self.p2.ast, egraph, subst);
+        let (p1, p2) = (&self.p1, &self.p2);
+        let a1 = apply_pat(&mut id_buf_1, &p1.ast, p1.has_sequence_vars, egraph, subst);
+        let a2 = apply_pat(&mut id_buf_2, &p2.ast, p2.has_sequence_vars, egraph, subst);
         a1 == a2
     }
 
@@ -538,6 +597,132 @@ where
     }
 }
 
+/// A [`Condition`] that passes if a term is _not_ in the egraph.
+///
+/// The [`Pattern`] is instantiated with the substitution and looked up
+/// with [`EGraph::lookup`], so the condition never adds anything to the
+/// egraph, with or without explanations.
+/// All of the variables of the pattern must be bound by the searcher.
+///
+/// Like any negative condition, this is not monotonic: a term missing now
+/// may be added (or merged in) later, so results can depend on the order
+/// rules are run in.
+///
+/// ```
+/// use egg::*;
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+///     // expand `?x` once, unless it is already known to be in normal form
+///     rewrite!("expand"; "(g ?x)" => "(h ?x)" if ConditionNotExists::parse("(done ?x)")),
+/// ];
+/// let runner = Runner::default()
+///     .with_expr(&"(g a)".parse().unwrap())
+///     .with_expr(&"(+ (g b) (done b))".parse().unwrap())
+///     .run(&rules);
+/// let egraph = &runner.egraph;
+/// assert!(egraph.lookup_expr(&"(h a)".parse().unwrap()).is_some());
+/// assert!(egraph.lookup_expr(&"(h b)".parse().unwrap()).is_none());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionNotExists<L> {
+    pattern: Pattern<L>,
+}
+
+impl<L: Language> ConditionNotExists<L> {
+    /// Create a new [`ConditionNotExists`] condition given a pattern.
+    pub fn new(pattern: Pattern<L>) -> Self {
+        ConditionNotExists { pattern }
+    }
+}
+
+impl<L: FromOp> ConditionNotExists<L> {
+    /// Create a [`ConditionNotExists`] by parsing a pattern string.
+    ///
+    /// This panics if the parsing fails.
+    pub fn parse(s: &str) -> Self {
+        Self {
+            pattern: s.parse().unwrap(),
+        }
+    }
+}
+
+impl<L, N> Condition<L, N> for ConditionNotExists<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn check(&self, egraph: &mut EGraph<L, N>, _eclass: Id, subst: &Subst) -> bool {
+        crate::guard::lookup_term(egraph, &self.pattern.ast, subst).is_none()
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.pattern.vars()
+    }
+}
+
+/// A [`Condition`] that passes if a [`Pattern`] does _not_ match in the
+/// eclass where the searcher matched.
+///
+/// Every variable of the pattern must be bound by the searcher, and a match
+/// only counts if it binds each variable to the eclass in the substitution.
+/// For example, `ConditionNotMatch::parse("(nf ?x)")` passes unless the
+/// eclass contains `nf` applied to the eclass of `?x`.
+///
+/// The pattern is matched like [`Searcher::search_eclass_with_limit`] with a
+/// limit of 1, so the check stops at the first match.
+/// Conditions are checked while rules are being applied, so the egraph may
+/// not be rebuilt; like [`ConditionNotExists`], the match may then miss
+/// enodes whose children are not canonical yet.
+/// It does not support sequence variables.
+/// Like [`ConditionNotExists`], it never adds to the egraph and is not monotonic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionNotMatch<L> {
+    pattern: Pattern<L>,
+}
+
+impl<L: Language> ConditionNotMatch<L> {
+    /// Create a new [`ConditionNotMatch`] condition given a pattern.
+    ///
+    /// This panics if the pattern has sequence variables.
+    pub fn new(pattern: Pattern<L>) -> Self {
+        assert!(
+            pattern.vars().iter().all(|v| !v.is_sequence()),
+            "ConditionNotMatch does not support sequence variables"
+        );
+        ConditionNotMatch { pattern }
+    }
+}
+
+impl<L: FromOp> ConditionNotMatch<L> {
+    /// Create a [`ConditionNotMatch`] by parsing a pattern string.
+    ///
+    /// This panics if the parsing fails.
+    pub fn parse(s: &str) -> Self {
+        Self::new(s.parse().unwrap())
+    }
+}
+
+impl<L, N> Condition<L, N> for ConditionNotMatch<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn check(&self, egraph: &mut EGraph<L, N>, eclass: Id, subst: &Subst) -> bool {
+        let agrees = |s: &Subst| {
+            s.vec
+                .iter()
+                .all(|&(v, id)| subst.get(v).map(|&b| egraph.find(b)) == Some(egraph.find(id)))
+        };
+        self.pattern
+            .program
+            .run_on_dirty(egraph, eclass, 1, &machine::no_data_predicates, agrees)
+            .is_empty()
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.pattern.vars()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -648,4 +833,64 @@ mod tests {
         fold_add.run(&mut egraph);
         assert_eq!(egraph.equivs(&start, &goal), vec![egraph.find(root)]);
     }
+
+    #[test]
+    fn negative_conditions() {
+        crate::init_logger();
+        let start = RecExpr::from_str("(+ (f a) (f b))").unwrap();
+
+        let mut egraph = EGraph::default();
+        egraph.add_expr(&start);
+        let fb = egraph.add_expr(&"(f b)".parse().unwrap());
+        let nf = egraph.add_expr(&"(nf c)".parse().unwrap());
+        egraph.union(fb, nf);
+        egraph.add_expr(&"(p a)".parse().unwrap());
+        egraph.rebuild();
+
+        let ground_nf =
+            rewrite!("ground-nf"; "(f ?x)" => "(k ?x)" if ConditionNotMatch::parse("(nf c)"));
+        let bound_nf =
+            rewrite!("bound-nf"; "(f ?x)" => "(j ?x)" if ConditionNotMatch::parse("(nf ?x)"));
+        let exists =
+            rewrite!("exists"; "(f ?x)" => "(g ?x)" if ConditionNotExists::parse("(p ?x)"));
+        for rw in [&ground_nf, &bound_nf, &exists] {
+            rw.run(&mut egraph);
+        }
+        egraph.rebuild();
+
+        let has = |egraph: &EGraph, s: &str| egraph.lookup_expr(&s.parse().unwrap());
+        assert_eq!(has(&egraph, "(k a)"), has(&egraph, "(f a)"));
+        assert_eq!(has(&egraph, "(k b)"), None);
+        // only `(nf c)` is in the eclass, and `c` is not `b`
+        assert_eq!(has(&egraph, "(j b)"), has(&egraph, "(f b)"));
+        assert_eq!(has(&egraph, "(g a)"), None);
+        assert_eq!(has(&egraph, "(g b)"), has(&egraph, "(f b)"));
+
+        let unbound = ConditionalApplier {
+            condition: ConditionNotMatch::parse("(nf ?y)"),
+            applier: Pattern::from_str("(k ?x)").unwrap(),
+        };
+        let searcher = Pattern::from_str("(f ?x)").unwrap();
+        assert!(Rewrite::<S, ()>::new("unbound-nf", searcher, unbound).is_err());
+    }
+
+    #[test]
+    fn negative_conditions_explanations() {
+        crate::init_logger();
+        let rules: Vec<Rewrite<S, ()>> = vec![
+            rewrite!("expand"; "(g ?x)" => "(h ?x)" if ConditionNotExists::parse("(done ?x)")),
+            rewrite!("finish"; "(h ?x)" => "(done ?x)" if ConditionNotMatch::parse("(done ?x)")),
+        ];
+        let start = "(+ (g a) (g b))".parse().unwrap();
+        let mut runner = Runner::default()
+            .with_explanations_enabled()
+            .with_expr(&start)
+            .run(&rules);
+
+        let goal = "(+ (done a) (done b))".parse().unwrap();
+        assert_eq!(runner.egraph.equivs(&start, &goal).len(), 1);
+        runner
+            .explain_equivalence(&start, &goal)
+            .check_proof(&rules);
+    }
 }
@@ -1,4 +1,6 @@
+use std::borrow::Cow;
 use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
 
 use log::*;
 
@@ -153,6 +155,30 @@ pub struct Runner<L: Language, N: Analysis<L>, IterData = ()> {
 
     limits: RunnerLimits,
     scheduler: Box<dyn RewriteScheduler<L, N>>,
+    recompile: Option<(usize, RecompileFn<L, N>)>,
+}
+
+type RecompileFn<L, N> = fn(&Rewrite<L, N>, &EGraph<L, N>) -> Rewrite<L, N>;
+
+/// Copies `rw`, recompiling its searcher for `egraph` if it is a plain [`Pattern`].
+fn recompiled<L, N>(rw: &Rewrite<L, N>, egraph: &EGraph<L, N>) -> Rewrite<L, N>
+where
+    L: Language + Send + Sync + 'static,
+    N: Analysis<L> + 'static,
+{
+    let searcher: Arc<dyn Searcher<L, N> + Sync + Send> = match rw.searcher.as_pattern() {
+        Some(pattern) => {
+            let mut pattern = pattern.clone();
+            pattern.recompile(egraph);
+            Arc::new(pattern)
+        }
+        None => rw.searcher.clone(),
+    };
+    Rewrite {
+        name: rw.name,
+        searcher,
+        applier: rw.applier.clone(),
+    }
 }
 
 /// Describes the limits that would stop a [`Runner`].
@@ -215,6 +241,7 @@ where
             hooks,
             limits,
             scheduler: _,
+            recompile,
         } = self;
 
         f.debug_struct("Runner")
@@ -225,6 +252,7 @@ where
             .field("hooks", &vec![format_args!("<dyn FnMut ..>"); hooks.len()])
             .field("limits", limits)
             .field("scheduler", &format_args!("<dyn RewriteScheduler ..>"))
+            .field("recompile_every", &recompile.map(|(every, _)| every))
             .finish()
     }
 }
@@ -350,6 +378,7 @@ where
             stop_reason: None,
             hooks: vec![],
             scheduler: Box::new(BackoffScheduler::default()),
+            recompile: None,
         }
     }
 
@@ -402,6 +431,38 @@ where
         self
     }
 
+    /// Recompiles the searchers of the rules for the current e-graph before
+    /// the search of every `every`th iteration, starting with the first.
+    /// Default: never
+    ///
+    /// The best order to match a pattern in depends on the e-graph,
+    /// see [`Pattern::recompile`].
+    /// Only searchers that are plain [`Pattern`]s (see [`Searcher::as_pattern`])
+    /// are recompiled. The runner searches with recompiled copies,
+    /// so the rules passed to [`run`](Runner::run) are left as they are.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("fold"; "(+ (f ?x) (g ?x))" => "(h ?x)"),
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ (g a) (f a))".parse().unwrap())
+    ///     .with_recompile_every(2)
+    ///     .run(rules);
+    /// assert!(runner.egraph.lookup_expr(&"(h a)".parse().unwrap()).is_some());
+    /// ```
+    pub fn with_recompile_every(mut self, every: usize) -> Self
+    where
+        L: Send + Sync + 'static,
+        N: 'static,
+    {
+        assert!(every > 0, "cannot recompile every 0 iterations");
+        self.recompile = Some((every, recompiled));
+        self
+    }
+
     /// Change out the [`RewriteScheduler`] used by this [`Runner`].
     /// The default one is [`BackoffScheduler`].
     ///
@@ -439,8 +500,18 @@ where
         let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
         check_rules(&rules);
         self.egraph.rebuild();
+        // copies of the rules with recompiled searchers, see `with_recompile_every`
+        let mut recompiled_rules: Vec<Rewrite<L, N>> = vec![];
         loop {
-            let iter = self.run_one(&rules);
+            let iter = if let Some((every, recompile)) = self.recompile {
+                if recompiled_rules.is_empty() || self.iterations.len() % every == 0 {
+                    recompiled_rules = rules.iter().map(|rw| recompile(rw, &self.egraph)).collect();
+                }
+                let rules: Vec<&Rewrite<L, N>> = recompiled_rules.iter().collect();
+                self.run_one(&rules)
+            } else {
+                self.run_one(&rules)
+            };
             self.iterations.push(iter);
             let stop_reason = self.iterations.last().unwrap().stop_reason.clone();
             // we need to check_limits after the iteration is complete to check for iter_limit
@@ -464,6 +535,13 @@ where
         self
     }
 
+    /// Enable compact explanations for this runner's egraph.
+    /// See [`EGraph::with_compact_explanations_enabled`].
+    pub fn with_compact_explanations_enabled(mut self) -> Self {
+        self.egraph = self.egraph.with_compact_explanations_enabled();
+        self
+    }
+
     /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
     /// Use this function to turn this algorithm off.
     pub fn without_explanation_length_optimization(mut self) -> Self {
@@ -573,6 +651,7 @@ where
                 let total_matches: usize = ms.iter().map(|m| m.substs.len()).sum();
                 debug!("Applying {} {} times", rw.name, total_matches);
 
+                self.egraph.set_current_provenance(Some(rw.name), i);
                 let actually_matched = self.scheduler.apply_rewrite(i, &mut self.egraph, rw, ms);
                 if actually_matched > 0 {
                     if let Some(count) = applied.get_mut(&rw.name) {
@@ -586,6 +665,8 @@ where
             })
         });
 
+        self.egraph.set_current_provenance(None, i);
+
         let apply_time = apply_time.elapsed().as_secs_f64();
         info!("Apply time: {}", apply_time);
 
@@ -785,6 +866,107 @@ where
 {
 }
 
+/// A [`RewriteScheduler`] that searches for all the rules at once.
+///
+/// The rules whose searchers are plain [`Pattern`]s
+/// (see [`Searcher::as_pattern`]) are merged into a [`PatternTrie`],
+/// so a single traversal of the e-graph finds the matches of all of them,
+/// sharing the work for the parts of the patterns they have in common.
+/// Other rules are searched one by one.
+/// The trie is kept between iterations as long as the rules do not change.
+///
+/// Like [`SimpleScheduler`], this does not ban any rules.
+/// Choose it with the [`with_scheduler`](Runner::with_scheduler())
+/// method.
+///
+/// ```
+/// use egg::{*, rewrite as rw};
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+///     rw!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rw!("distribute"; "(+ (* ?a ?b) (* ?a ?c))" => "(* ?a (+ ?b ?c))"),
+///     rw!("add-zero"; "(+ ?a 0)" => "?a"),
+/// ];
+/// let start = "(+ 0 (+ (* x y) (* x z)))".parse().unwrap();
+/// let runner = Runner::default()
+///     .with_scheduler(SharedScheduler::default())
+///     .with_expr(&start)
+///     .run(&rules);
+/// let goal = "(* x (+ z y))".parse().unwrap();
+/// assert_eq!(runner.egraph.equivs(&start, &goal).len(), 1);
+/// ```
+pub struct SharedScheduler<L: Language> {
+    trie: Option<PatternTrie<L>>,
+}
+
+impl<L: Language> Default for SharedScheduler<L> {
+    fn default() -> Self {
+        Self { trie: None }
+    }
+}
+
+impl<L: Language> Debug for SharedScheduler<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let n = self.trie.as_ref().map_or(0, |trie| trie.patterns().len());
+        f.debug_struct("SharedScheduler")
+            .field("patterns", &n)
+            .finish()
+    }
+}
+
+impl<L, N> RewriteScheduler<L, N> for SharedScheduler<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn search_rewrites<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrites: &[&'a Rewrite<L, N>],
+        limits: &RunnerLimits,
+    ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
+        let patterns: Vec<Option<&'a Pattern<L>>> = rewrites
+            .iter()
+            .map(|rw| {
+                let rw: &'a Rewrite<L, N> = rw;
+                rw.searcher.as_pattern()
+            })
+            .collect();
+        let shared: Vec<&Pattern<L>> = patterns.iter().flatten().copied().collect();
+
+        let stale = match &self.trie {
+            Some(trie) => !trie.patterns().iter().eq(shared.iter().copied()),
+            None => true,
+        };
+        if stale {
+            self.trie = Some(PatternTrie::new(shared.iter().map(|&p| p.clone())));
+        }
+        let trie = self.trie.as_ref().unwrap();
+
+        let mut shared_matches = trie
+            .search_substs(egraph, &vec![usize::MAX; shared.len()])
+            .into_iter();
+        let mut matches = Vec::with_capacity(rewrites.len());
+        for (rw, pattern) in rewrites.iter().zip(patterns) {
+            matches.push(match pattern {
+                Some(pattern) => shared_matches
+                    .next()
+                    .unwrap()
+                    .into_iter()
+                    .map(|(eclass, substs)| SearchMatches {
+                        eclass,
+                        substs,
+                        ast: Some(Cow::Borrowed(&pattern.ast)),
+                    })
+                    .collect(),
+                None => rw.search(egraph),
+            });
+        }
+        limits.check_limits(iteration, egraph)?;
+        Ok(matches)
+    }
+}
+
 /// A [`RewriteScheduler`] that implements exponentional rule backoff.
 ///
 /// For each rewrite, there exists a configurable initial match limit.
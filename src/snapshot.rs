@@ -0,0 +1,456 @@
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+
+use thiserror::Error;
+
+use crate::*;
+
+const MAGIC: &[u8; 8] = b"EGGSNAP\0";
+
+/// The version of the snapshot format written by [`EGraph::save_snapshot`].
+///
+/// [`EGraph::load_snapshot`] reads snapshots up to this version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// inputs claiming more elements than this don't get preallocated,
+// so corrupt lengths fail when reading instead of when allocating
+const MAX_PREALLOCATE: usize = 1 << 16;
+
+/**
+Encodes and decodes the [`Analysis::Data`] of e-classes in snapshots,
+see [`EGraph::save_snapshot`].
+
+The unit codec `()` handles `()` data, and a pair of closures
+`(encode, decode)` can be used as a codec for any data.
+**/
+pub trait SnapshotCodec<D> {
+    /// Appends the encoding of `data` to `out`.
+    fn encode(&self, data: &D, out: &mut Vec<u8>);
+
+    /// Decodes data from exactly the bytes written by [`encode`](SnapshotCodec::encode).
+    fn decode(&self, bytes: &[u8]) -> Result<D, String>;
+}
+
+impl SnapshotCodec<()> for () {
+    fn encode(&self, _data: &(), _out: &mut Vec<u8>) {}
+
+    fn decode(&self, _bytes: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<D, E, F> SnapshotCodec<D> for (E, F)
+where
+    E: Fn(&D, &mut Vec<u8>),
+    F: Fn(&[u8]) -> Result<D, String>,
+{
+    fn encode(&self, data: &D, out: &mut Vec<u8>) {
+        (self.0)(data, out)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<D, String> {
+        (self.1)(bytes)
+    }
+}
+
+/// An error from saving or loading an [`EGraph`] snapshot.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    /// Reading or writing failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The input does not start like a snapshot.
+    #[error("not an egg snapshot")]
+    BadMagic,
+    /// The snapshot was written by a newer version of the format.
+    #[error("unsupported snapshot version {0}, expected at most {SNAPSHOT_VERSION}")]
+    UnsupportedVersion(u32),
+    /// Only rebuilt e-graphs can be saved.
+    #[error("the e-graph must be rebuilt before saving a snapshot")]
+    NotClean,
+    /// The snapshot is malformed.
+    #[error("corrupt snapshot: {0}")]
+    Corrupt(String),
+    /// The [`SnapshotCodec`] could not decode some analysis data.
+    #[error("could not decode analysis data: {0}")]
+    Data(String),
+}
+
+fn corrupt(message: impl Into<String>) -> SnapshotError {
+    SnapshotError::Corrupt(message.into())
+}
+
+pub(crate) struct SnapshotWriter<W> {
+    writer: W,
+    strings: HashMap<String, u64>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub(crate) fn new(mut writer: W) -> Result<Self, SnapshotError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        Ok(SnapshotWriter {
+            writer,
+            strings: Default::default(),
+            buf: vec![],
+        })
+    }
+
+    // LEB128
+    pub(crate) fn u64(&mut self, mut n: u64) -> Result<(), SnapshotError> {
+        let mut bytes = [0; 10];
+        let mut len = 0;
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                bytes[len] = byte;
+                len += 1;
+                break;
+            }
+            bytes[len] = byte | 0x80;
+            len += 1;
+        }
+        self.writer.write_all(&bytes[..len])?;
+        Ok(())
+    }
+
+    pub(crate) fn usize(&mut self, n: usize) -> Result<(), SnapshotError> {
+        self.u64(n as u64)
+    }
+
+    pub(crate) fn id(&mut self, id: Id) -> Result<(), SnapshotError> {
+        self.usize(usize::from(id))
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        self.usize(bytes.len())?;
+        self.writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    // strings are interned: the first occurrence is written in full
+    // right after its new index, later ones are just the index
+    pub(crate) fn string(&mut self, s: &str) -> Result<(), SnapshotError> {
+        if let Some(&i) = self.strings.get(s) {
+            return self.u64(i);
+        }
+        let i = self.strings.len() as u64;
+        self.strings.insert(s.to_owned(), i);
+        self.u64(i)?;
+        self.bytes(s.as_bytes())
+    }
+
+    pub(crate) fn node<L: Language + Display>(&mut self, node: &L) -> Result<(), SnapshotError> {
+        self.string(&node.to_string())?;
+        self.usize(node.len())?;
+        node.try_for_each(|child| self.id(child))
+    }
+
+    pub(crate) fn data<D>(
+        &mut self,
+        codec: &impl SnapshotCodec<D>,
+        data: &D,
+    ) -> Result<(), SnapshotError> {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        codec.encode(data, &mut buf);
+        let result = self.bytes(&buf);
+        self.buf = buf;
+        result
+    }
+
+    pub(crate) fn provenance(&mut self, provenance: Provenance) -> Result<(), SnapshotError> {
+        match provenance.rule {
+            None => self.u64(0)?,
+            Some(rule) => {
+                self.u64(1)?;
+                self.string(rule.as_str())?
+            }
+        }
+        self.usize(provenance.iteration)
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), SnapshotError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub(crate) struct SnapshotReader<R> {
+    reader: R,
+    strings: Vec<String>,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    pub(crate) fn new(mut reader: R) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => SnapshotError::BadMagic,
+            _ => e.into(),
+        })?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        Ok(SnapshotReader {
+            reader,
+            strings: vec![],
+            buf: vec![],
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(corrupt("integer too large"))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, SnapshotError> {
+        let n = self.u64()?;
+        usize::try_from(n).map_err(|_| corrupt(format!("{} is too large", n)))
+    }
+
+    /// Reads a length, to preallocate a `Vec`.
+    pub(crate) fn len(&mut self) -> Result<(usize, usize), SnapshotError> {
+        let len = self.usize()?;
+        Ok((len, len.min(MAX_PREALLOCATE)))
+    }
+
+    /// Reads an id, which must be less than `bound`.
+    pub(crate) fn id(&mut self, bound: usize) -> Result<Id, SnapshotError> {
+        let id = self.usize()?;
+        if id >= bound {
+            return Err(corrupt(format!("id {} out of bounds", id)));
+        }
+        Ok(Id::from(id))
+    }
+
+    fn bytes(&mut self) -> Result<&[u8], SnapshotError> {
+        let len = self.usize()?;
+        self.buf.clear();
+        let read = (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut self.buf)?;
+        if read < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(&self.buf)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<&str, SnapshotError> {
+        let i = self.usize()?;
+        if i == self.strings.len() {
+            let s = std::str::from_utf8(self.bytes()?)
+                .map_err(|e| corrupt(e.to_string()))?
+                .to_owned();
+            self.strings.push(s);
+        }
+        match self.strings.get(i) {
+            Some(s) => Ok(s),
+            None => Err(corrupt(format!("string {} out of bounds", i))),
+        }
+    }
+
+    /// Reads an e-node, whose children must be less than `bound`.
+    pub(crate) fn node<L: FromOp>(&mut self, bound: usize) -> Result<L, SnapshotError> {
+        let op = self.string()?.to_owned();
+        let (len, capacity) = self.len()?;
+        let mut children = Vec::with_capacity(capacity);
+        for _ in 0..len {
+            children.push(self.id(bound)?);
+        }
+        L::from_op(&op, children).map_err(|e| corrupt(format!("{:?}", e)))
+    }
+
+    pub(crate) fn data<D>(&mut self, codec: &impl SnapshotCodec<D>) -> Result<D, SnapshotError> {
+        let bytes = self.bytes()?;
+        codec.decode(bytes).map_err(SnapshotError::Data)
+    }
+
+    pub(crate) fn provenance(&mut self) -> Result<Provenance, SnapshotError> {
+        let rule = match self.u64()? {
+            0 => None,
+            1 => Some(Symbol::from(self.string()?)),
+            n => return Err(corrupt(format!("bad rule tag {}", n))),
+        };
+        let iteration = self.usize()?;
+        Ok(Provenance { rule, iteration })
+    }
+
+    /// Checks that the whole input was read.
+    pub(crate) fn finish(mut self) -> Result<(), SnapshotError> {
+        match self.byte() {
+            Ok(_) => Err(corrupt("trailing data")),
+            Err(SnapshotError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rewrite as rw, SymbolLang as S, *};
+
+    fn rules() -> Vec<Rewrite<S, ()>> {
+        vec![
+            rw!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rw!("assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        ]
+    }
+
+    fn canonical_nodes(egraph: &EGraph<S, ()>) -> Vec<(Id, Vec<S>)> {
+        let mut classes: Vec<_> = egraph
+            .classes()
+            .map(|class| (class.id, class.nodes.clone()))
+            .collect();
+        classes.sort();
+        classes
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        crate::init_logger();
+        let mut egraph = EGraph::<S, ()>::default().with_provenance_enabled();
+        egraph.add_expr(&"(+ a (+ b (+ c d)))".parse().unwrap());
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(2)
+            .run(&rules());
+        let egraph = runner.egraph;
+
+        let mut snapshot = vec![];
+        egraph.save_snapshot(&(), &mut snapshot).unwrap();
+        let loaded = EGraph::<S, ()>::load_snapshot((), &(), &snapshot[..]).unwrap();
+        assert_eq!(canonical_nodes(&loaded), canonical_nodes(&egraph));
+        // including the order of the e-classes, which only the deterministic
+        // maps keep exactly
+        #[cfg(feature = "deterministic")]
+        {
+            let ids = |egraph: &EGraph<S, ()>| egraph.classes().map(|c| c.id).collect::<Vec<_>>();
+            assert_eq!(ids(&loaded), ids(&egraph));
+        }
+        assert_eq!(loaded.nodes(), egraph.nodes());
+        assert_eq!(loaded.provenance_counts(), egraph.provenance_counts());
+        for i in 0..egraph.nodes().len() {
+            assert_eq!(loaded.find(Id::from(i)), egraph.find(Id::from(i)));
+        }
+
+        // both can keep going and stay the same
+        let run = |egraph| {
+            Runner::default()
+                .with_egraph(egraph)
+                .with_iter_limit(2)
+                .run(&rules())
+                .egraph
+        };
+        let egraph = run(egraph);
+        let loaded = run(loaded);
+        assert_eq!(canonical_nodes(&loaded), canonical_nodes(&egraph));
+    }
+
+    #[test]
+    fn snapshot_errors() {
+        let mut egraph = EGraph::<S, ()>::default();
+        egraph.add_expr(&"(f x)".parse().unwrap());
+        let load = |bytes: &[u8]| EGraph::<S, ()>::load_snapshot((), &(), bytes).map(|_| ());
+
+        let mut snapshot = vec![];
+        let err = egraph.save_snapshot(&(), &mut snapshot).unwrap_err();
+        assert!(matches!(err, SnapshotError::NotClean));
+        egraph.rebuild();
+        egraph.save_snapshot(&(), &mut snapshot).unwrap();
+        assert!(load(&snapshot).is_ok());
+
+        assert!(matches!(load(b"EGG"), Err(SnapshotError::BadMagic)));
+        assert!(matches!(
+            load(b"not a snapshot"),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let mut newer = snapshot.clone();
+        newer[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            load(&newer),
+            Err(SnapshotError::UnsupportedVersion(v)) if v == SNAPSHOT_VERSION + 1
+        ));
+
+        let truncated = &snapshot[..snapshot.len() - 2];
+        assert!(matches!(load(truncated), Err(SnapshotError::Io(_))));
+
+        let mut trailing = snapshot.clone();
+        trailing.push(0);
+        assert!(matches!(load(&trailing), Err(SnapshotError::Corrupt(_))));
+
+        let failing = (
+            |_: &(), _: &mut Vec<u8>| {},
+            |_: &[u8]| Err("nope".to_string()),
+        );
+        let result = EGraph::<S, ()>::load_snapshot((), &failing, &snapshot[..]);
+        assert!(matches!(result, Err(SnapshotError::Data(e)) if e == "nope"));
+    }
+
+    #[test]
+    fn snapshot_bad_unionfind() {
+        // a snapshot of the leaves `x` and `y` with the given union-find and e-classes
+        let snapshot = |parents: &[usize], classes: &[usize]| {
+            let nodes = [S::leaf("x"), S::leaf("y")];
+            let mut bytes = vec![];
+            let mut w = super::SnapshotWriter::new(&mut bytes).unwrap();
+            w.usize(nodes.len()).unwrap();
+            for node in &nodes {
+                w.node(node).unwrap();
+            }
+            for &parent in parents {
+                w.usize(parent).unwrap();
+            }
+            w.usize(classes.len()).unwrap();
+            for &class in classes {
+                w.usize(class).unwrap();
+                w.usize(1).unwrap();
+                w.node(&nodes[class]).unwrap();
+                w.usize(0).unwrap();
+                w.data(&(), &()).unwrap();
+            }
+            // no memo or operator index
+            w.usize(0).unwrap();
+            w.usize(0).unwrap();
+            w.u64(0).unwrap();
+            w.finish().unwrap();
+            bytes
+        };
+        let load = |bytes: Vec<u8>| EGraph::<S, ()>::load_snapshot((), &(), &bytes[..]);
+
+        assert!(load(snapshot(&[0, 1], &[0, 1])).is_ok());
+        let corrupt = |parents: &[usize], classes: &[usize]| {
+            matches!(
+                load(snapshot(parents, classes)),
+                Err(SnapshotError::Corrupt(_))
+            )
+        };
+        assert!(corrupt(&[1, 0], &[0, 1]));
+        assert!(corrupt(&[0, 1], &[0]));
+        assert!(corrupt(&[0, 1], &[0, 0]));
+        assert!(corrupt(&[0, 0], &[0, 1]));
+    }
+}
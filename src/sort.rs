@@ -0,0 +1,421 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+use crate::*;
+use thiserror::Error;
+
+/// The signature of an e-node in a [`TypedLanguage`]:
+/// the sorts of its children and the sort of the node itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature<S> {
+    /// The sorts of the children, in order.
+    pub inputs: Vec<S>,
+    /// The sort of the e-node.
+    pub output: S,
+}
+
+impl<S> Signature<S> {
+    /// Create a new [`Signature`].
+    pub fn new(inputs: impl IntoIterator<Item = S>, output: S) -> Self {
+        Signature {
+            inputs: inputs.into_iter().collect(),
+            output,
+        }
+    }
+}
+
+/// An error found while checking the sorts of a term, pattern, or rewrite.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SortError<S> {
+    /// An e-node has a different number of children than its signature.
+    #[error("operator {op} expects {expected} children, but was given {found}")]
+    Arity {
+        /// The operator of the e-node.
+        op: String,
+        /// The number of inputs in the signature.
+        expected: usize,
+        /// The number of children of the e-node.
+        found: usize,
+    },
+
+    /// A child of an e-node does not have the sort its signature expects.
+    #[error("argument {index} of {op} should have sort {expected}, but has sort {found}")]
+    Mismatch {
+        /// The operator of the e-node.
+        op: String,
+        /// The position of the ill-sorted child.
+        index: usize,
+        /// The sort the signature expects.
+        expected: S,
+        /// The sort of the child.
+        found: S,
+    },
+
+    /// A pattern variable is used at two different sorts.
+    #[error("variable {var} is used with sort {first} and with sort {second}")]
+    Var {
+        /// The variable.
+        var: Var,
+        /// The first sort the variable was used at.
+        first: S,
+        /// The conflicting sort.
+        second: S,
+    },
+
+    /// The two sides of a rewrite have different sorts.
+    #[error("rewrite turns a term of sort {searcher} into a term of sort {applier}")]
+    Rewrite {
+        /// The sort of the searcher pattern.
+        searcher: S,
+        /// The sort of the applier pattern.
+        applier: S,
+    },
+}
+
+/// An error type for failures when parsing a [`RecExpr`] with
+/// [`TypedLanguage::parse_expr`].
+#[derive(Debug, Error)]
+pub enum TypedParseError<E, S> {
+    /// The input could not be parsed as a [`RecExpr`].
+    #[error(transparent)]
+    Parse(RecExprParseError<E>),
+
+    /// The input parsed, but is not well-sorted.
+    #[error("ill-sorted expression: {0}")]
+    Sort(SortError<S>),
+}
+
+/** A [`Language`] whose e-nodes have sorts.
+
+A [`TypedLanguage`] gives each e-node a [`Signature`].
+This lets `egg` infer the sorts of pattern variables, reject rewrites
+that would build ill-sorted terms, and report sort errors when parsing.
+Sorts are checked by equality, so polymorphic operators
+should be split into one variant per sort.
+
+Create the rewrites of a typed language with [`Rewrite::new_typed`],
+which rejects ill-sorted rewrites however the language was defined.
+[`Rewrite::new`] and [`rewrite!`] know nothing about sorts.
+
+# Example
+```
+use egg::*;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+enum Lang {
+    #[op = "+"]
+    Add([Id; 2]),
+    #[op = "<"]
+    Lt([Id; 2]),
+    #[op = "if"]
+    If([Id; 3]),
+    Bool(bool),
+    Num(i32),
+}
+
+impl TypedLanguage for Lang {
+    type Sort = &'static str;
+
+    fn signature(&self) -> Signature<Self::Sort> {
+        match self {
+            Lang::Add(_) => Signature::new(["int", "int"], "int"),
+            Lang::Lt(_) => Signature::new(["int", "int"], "bool"),
+            Lang::If(_) => Signature::new(["bool", "int", "int"], "int"),
+            Lang::Bool(_) => Signature::new([], "bool"),
+            Lang::Num(_) => Signature::new([], "int"),
+        }
+    }
+}
+
+let pattern: Pattern<Lang> = "(if (< ?a ?b) ?b ?a)".parse().unwrap();
+let sorts = Lang::infer_sorts(&pattern.ast).unwrap();
+let a: Var = "?a".parse().unwrap();
+assert_eq!(sorts[&a], "int");
+
+let expr = Lang::parse_expr("(+ 1 (if true 2 3))").unwrap();
+assert_eq!(Lang::check_expr(&expr), Ok("int"));
+let err = Lang::parse_expr("(+ 1 (< 2 3))").unwrap_err();
+assert_eq!(
+    err.to_string(),
+    "ill-sorted expression: argument 1 of + should have sort int, but has sort bool"
+);
+
+let searcher: Pattern<Lang> = "(+ ?a ?b)".parse().unwrap();
+let applier: Pattern<Lang> = "(< ?b ?a)".parse().unwrap();
+assert!(Rewrite::<Lang, ()>::new_typed("bad", searcher, applier).is_err());
+```
+**/
+pub trait TypedLanguage: Language + Display {
+    /// The type of sorts in this language.
+    type Sort: Debug + Display + Clone + Eq + Hash;
+
+    /// Returns the [`Signature`] of this e-node.
+    fn signature(&self) -> Signature<Self::Sort>;
+
+    /// Infers the sort of every variable in a pattern from the positions it
+    /// is used in, checking that the pattern is well-sorted.
+    ///
+    /// Variables that only appear as the root of the pattern are left out.
+    fn infer_sorts(
+        ast: &PatternAst<Self>,
+    ) -> Result<HashMap<Var, Self::Sort>, SortError<Self::Sort>> {
+        let mut vars = HashMap::default();
+        pattern_sort(ast, &mut vars)?;
+        Ok(vars)
+    }
+
+    /// Checks that an expression is well-sorted, returning the sort of its root.
+    fn check_expr(expr: &RecExpr<Self>) -> Result<Self::Sort, SortError<Self::Sort>> {
+        let mut sorts: Vec<Self::Sort> = Vec::with_capacity(expr.len());
+        for node in expr.as_ref() {
+            let sort = check_node(node, |index, child, expected| {
+                let found = &sorts[usize::from(child)];
+                if found == expected {
+                    Ok(())
+                } else {
+                    Err(SortError::Mismatch {
+                        op: node.to_string(),
+                        index,
+                        expected: expected.clone(),
+                        found: found.clone(),
+                    })
+                }
+            })?;
+            sorts.push(sort);
+        }
+        Ok(sorts.pop().expect("empty expression"))
+    }
+
+    /// Checks that the searcher and applier patterns of a rewrite are
+    /// well-sorted, agree on the sorts of their variables, and have the
+    /// same sort.
+    ///
+    /// Either side may be missing if it is not a pattern,
+    /// in which case only the other side is checked.
+    fn check_rewrite(
+        searcher: Option<&PatternAst<Self>>,
+        applier: Option<&PatternAst<Self>>,
+    ) -> Result<(), SortError<Self::Sort>> {
+        let mut vars = HashMap::default();
+        if let Some(ast) = searcher {
+            pattern_sort(ast, &mut vars)?;
+        }
+        if let Some(ast) = applier {
+            pattern_sort(ast, &mut vars)?;
+        }
+        // check the roots once the sorts of all the variables are known
+        let root_sort = |ast: &PatternAst<Self>| match &ast[ast.root()] {
+            ENodeOrVar::ENode(node) => Some(node.signature().output),
+            ENodeOrVar::Var(v) => vars.get(v).cloned(),
+        };
+        match (searcher.and_then(root_sort), applier.and_then(root_sort)) {
+            (Some(searcher), Some(applier)) if searcher != applier => {
+                Err(SortError::Rewrite { searcher, applier })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses a [`RecExpr`] and checks that it is well-sorted.
+    fn parse_expr(s: &str) -> Result<RecExpr<Self>, TypedParseError<Self::Error, Self::Sort>>
+    where
+        Self: FromOp,
+    {
+        let expr: RecExpr<Self> = s.parse().map_err(TypedParseError::Parse)?;
+        Self::check_expr(&expr).map_err(TypedParseError::Sort)?;
+        Ok(expr)
+    }
+}
+
+impl<L: TypedLanguage, N: Analysis<L>> Rewrite<L, N> {
+    /// Like [`Rewrite::new`], but also rejects the rewrite if its patterns
+    /// are ill-sorted, see [`TypedLanguage::check_rewrite`].
+    pub fn new_typed(
+        name: impl Into<Symbol>,
+        searcher: impl Searcher<L, N> + Send + Sync + 'static,
+        applier: impl Applier<L, N> + Send + Sync + 'static,
+    ) -> Result<Self, String> {
+        let rewrite = Self::new(name, searcher, applier)?;
+        let searcher = rewrite.searcher.get_pattern_ast();
+        L::check_rewrite(searcher, rewrite.applier.get_pattern_ast())
+            .map_err(|e| format!("Rewrite {} is ill-sorted: {}", rewrite.name, e))?;
+        Ok(rewrite)
+    }
+}
+
+/// Checks the arity of `node` against its signature and calls `check_child`
+/// with the index, id and expected sort of each child.
+/// Returns the sort of `node`.
+fn check_node<L, F>(node: &L, mut check_child: F) -> Result<L::Sort, SortError<L::Sort>>
+where
+    L: TypedLanguage,
+    F: FnMut(usize, Id, &L::Sort) -> Result<(), SortError<L::Sort>>,
+{
+    let sig = node.signature();
+    if sig.inputs.len() != node.len() {
+        return Err(SortError::Arity {
+            op: node.to_string(),
+            expected: sig.inputs.len(),
+            found: node.len(),
+        });
+    }
+    for (index, (&child, expected)) in node.children().iter().zip(&sig.inputs).enumerate() {
+        check_child(index, child, expected)?;
+    }
+    Ok(sig.output)
+}
+
+/// Checks a pattern, adding the sorts of its variables to `vars`.
+fn pattern_sort<L: TypedLanguage>(
+    ast: &PatternAst<L>,
+    vars: &mut HashMap<Var, L::Sort>,
+) -> Result<(), SortError<L::Sort>> {
+    // the sort of each e-node in the pattern, None for variables
+    let mut sorts: Vec<Option<L::Sort>> = Vec::with_capacity(ast.len());
+    for node in ast.as_ref() {
+        let node = match node {
+            ENodeOrVar::ENode(node) => node,
+            ENodeOrVar::Var(_) => {
+                sorts.push(None);
+                continue;
+            }
+        };
+        let sort = check_node(node, |index, child, expected| {
+            match (&ast[child], &sorts[usize::from(child)]) {
+                (ENodeOrVar::Var(var), _) => match vars.get(var) {
+                    Some(first) if first != expected => Err(SortError::Var {
+                        var: *var,
+                        first: first.clone(),
+                        second: expected.clone(),
+                    }),
+                    Some(_) => Ok(()),
+                    None => {
+                        vars.insert(*var, expected.clone());
+                        Ok(())
+                    }
+                },
+                (ENodeOrVar::ENode(_), Some(found)) if found != expected => {
+                    Err(SortError::Mismatch {
+                        op: node.to_string(),
+                        index,
+                        expected: expected.clone(),
+                        found: found.clone(),
+                    })
+                }
+                (ENodeOrVar::ENode(_), _) => Ok(()),
+            }
+        })?;
+        sorts.push(Some(sort));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+    enum Vector {
+        #[op = "+"]
+        Add([Id; 2]),
+        #[op = "="]
+        Eq([Id; 2]),
+        #[op = "not"]
+        Not(Id),
+        #[op = "get"]
+        Get([Id; 2]),
+        #[op = "vec"]
+        Vec(Box<[Id]>),
+        Num(i32),
+    }
+
+    impl TypedLanguage for Vector {
+        type Sort = Symbol;
+
+        fn signature(&self) -> Signature<Symbol> {
+            let [int, boolean, vector] = ["int", "bool", "vector"].map(Symbol::from);
+            match self {
+                Vector::Add(_) => Signature::new([int, int], int),
+                Vector::Eq(_) => Signature::new([int, int], boolean),
+                Vector::Not(_) => Signature::new([boolean], boolean),
+                Vector::Get(_) => Signature::new([vector, int], int),
+                Vector::Vec(ids) => Signature::new(ids.iter().map(|_| int), vector),
+                Vector::Num(_) => Signature::new([], int),
+            }
+        }
+    }
+
+    fn pat(s: &str) -> Pattern<Vector> {
+        s.parse().unwrap()
+    }
+
+    fn var(s: &str) -> Var {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn infer_pattern_sorts() {
+        let sorts = Vector::infer_sorts(&pat("(not (= (get ?v ?i) ?x))").ast).unwrap();
+        assert_eq!(sorts.len(), 3);
+        assert_eq!(sorts[&var("?v")], Symbol::from("vector"));
+        assert_eq!(sorts[&var("?i")], Symbol::from("int"));
+        assert_eq!(sorts[&var("?x")], Symbol::from("int"));
+
+        assert!(Vector::infer_sorts(&pat("?x").ast).unwrap().is_empty());
+
+        let err = Vector::infer_sorts(&pat("(get ?v ?v)").ast).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "variable ?v is used with sort vector and with sort int"
+        );
+
+        let err = Vector::infer_sorts(&pat("(+ ?a (= ?a 1))").ast).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "argument 1 of + should have sort int, but has sort bool"
+        );
+    }
+
+    #[test]
+    fn check_rewrite_sorts() {
+        let check = |lhs: &str, rhs: &str| {
+            Rewrite::<Vector, ()>::new_typed("rule", pat(lhs), pat(rhs)).map(|_| ())
+        };
+        check("(+ ?a ?b)", "(+ ?b ?a)").unwrap();
+        check("(get (vec ?a ?b) 1)", "?b").unwrap();
+        check("(not (not ?b))", "?b").unwrap();
+
+        assert_eq!(
+            check("(+ ?a 0)", "(= ?a ?a)").unwrap_err(),
+            "Rewrite rule is ill-sorted: rewrite turns a term of sort int into a term of sort bool"
+        );
+        assert_eq!(
+            check("(get ?v 0)", "(+ ?v 0)").unwrap_err(),
+            "Rewrite rule is ill-sorted: variable ?v is used with sort vector and with sort int"
+        );
+        // a bare variable takes its sort from the other side
+        check("?x", "(+ ?x 0)").unwrap();
+
+        // sorts are only checked by `new_typed`
+        assert!(Rewrite::<Vector, ()>::new("rule", pat("(+ ?a 0)"), pat("(= ?a ?a)")).is_ok());
+    }
+
+    #[test]
+    fn parse_typed_expr() {
+        let expr = Vector::parse_expr("(not (= (get (vec 1 2 3) 0) 1))").unwrap();
+        assert_eq!(Vector::check_expr(&expr), Ok(Symbol::from("bool")));
+
+        let err = Vector::parse_expr("(get 1 (vec 2))").unwrap_err();
+        assert!(matches!(
+            err,
+            TypedParseError::Sort(SortError::Mismatch { index: 0, .. })
+        ));
+        let err = Vector::parse_expr("(vec 1 (not 2))").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ill-sorted expression: argument 0 of not should have sort bool, but has sort int"
+        );
+        let err = Vector::parse_expr("(+ 1").unwrap_err();
+        assert!(matches!(err, TypedParseError::Parse(_)));
+    }
+}
@@ -9,6 +9,9 @@ use thiserror::Error;
 ///
 /// This implements [`FromStr`], and will only parse if it has a
 /// leading `?`.
+/// A trailing `...`, as in `?xs...`, makes it a
+/// [sequence variable](Var::is_sequence); this is decided when the
+/// variable is parsed.
 ///
 /// [`FromStr`]: std::str::FromStr
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -37,11 +40,30 @@ impl Var {
             _ => None,
         }
     }
+
+    /// Returns whether this is a sequence variable, written with a
+    /// trailing `...` like `?xs...`.
+    ///
+    /// A sequence variable stands for any number of consecutive children
+    /// of an e-node, so `(f ?a ?rest...)` matches `(f x)`, `(f x y)`, and so on.
+    /// It is bound with [`Subst::insert_seq`] rather than [`Subst::insert`].
+    /// At most one sequence variable may appear among the children of
+    /// each e-node in a [`Pattern`].
+    ///
+    /// ```
+    /// # use egg::*;
+    /// assert!("?xs...".parse::<Var>().unwrap().is_sequence());
+    /// assert!(!"?x".parse::<Var>().unwrap().is_sequence());
+    /// ```
+    pub fn is_sequence(&self) -> bool {
+        matches!(self.0, VarInner::Seq(_))
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum VarInner {
     Sym(Symbol),
+    Seq(Symbol),
     Num(u32),
 }
 
@@ -64,6 +86,7 @@ impl FromStr for Var {
                 .parse()
                 .map(|num| Var(VarInner::Num(num)))
                 .map_err(|_| BadNumber(s.to_owned())),
+            [b'?', .., b'.', b'.', b'.'] => Ok(Var(VarInner::Seq(Symbol::from(s)))),
             [b'?', ..] if s.len() > 1 => Ok(Var(VarInner::Sym(Symbol::from(s)))),
             _ => Err(MissingQuestionMark(s.to_owned())),
         }
@@ -73,7 +96,7 @@ impl FromStr for Var {
 impl Display for Var {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self.0 {
-            VarInner::Sym(sym) => write!(f, "{}", sym),
+            VarInner::Sym(sym) | VarInner::Seq(sym) => write!(f, "{}", sym),
             VarInner::Num(num) => write!(f, "?#{}", num),
         }
     }
@@ -82,7 +105,7 @@ impl Display for Var {
 impl Debug for Var {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self.0 {
-            VarInner::Sym(sym) => write!(f, "{:?}", sym),
+            VarInner::Sym(sym) | VarInner::Seq(sym) => write!(f, "{:?}", sym),
             VarInner::Num(num) => write!(f, "?#{}", num),
         }
     }
@@ -96,16 +119,24 @@ impl From<u32> for Var {
 
 /// A substitution mapping [`Var`]s to eclass [`Id`]s.
 ///
+/// [Sequence variables](Var::is_sequence) are mapped to lists of
+/// eclass [`Id`]s instead.
 #[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Subst {
     pub(crate) vec: smallvec::SmallVec<[(Var, Id); 3]>,
+    // boxed so substitutions without sequence variables, the common case,
+    // only pay for a null pointer when they are cloned, hashed and compared
+    pub(crate) seqs: Option<Box<SeqBindings>>,
 }
 
+type SeqBindings = Vec<(Var, Box<[Id]>)>;
+
 impl Subst {
     /// Create a `Subst` with the given initial capacity
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             vec: smallvec::SmallVec::with_capacity(capacity),
+            seqs: None,
         }
     }
 
@@ -127,6 +158,29 @@ impl Subst {
             .iter()
             .find_map(|(v, id)| if *v == var { Some(id) } else { None })
     }
+
+    /// Insert a list of `Id`s for a [sequence variable](Var::is_sequence),
+    /// returning the old list if present.
+    pub fn insert_seq(&mut self, var: Var, ids: impl Into<Box<[Id]>>) -> Option<Box<[Id]>> {
+        let ids = ids.into();
+        let seqs = self.seqs.get_or_insert_with(Default::default);
+        for pair in seqs.iter_mut() {
+            if pair.0 == var {
+                return Some(std::mem::replace(&mut pair.1, ids));
+            }
+        }
+        seqs.push((var, ids));
+        None
+    }
+
+    /// Retrieve the `Id`s of a [sequence variable](Var::is_sequence),
+    /// returning `None` if not present.
+    pub fn get_seq(&self, var: Var) -> Option<&[Id]> {
+        self.seqs
+            .as_ref()?
+            .iter()
+            .find_map(|(v, ids)| if *v == var { Some(&ids[..]) } else { None })
+    }
 }
 
 impl std::ops::Index<Var> for Subst {
@@ -151,6 +205,12 @@ impl Debug for Subst {
                 write!(f, ", ")?;
             }
         }
+        for (i, (var, ids)) in self.seqs.iter().flat_map(|s| s.iter()).enumerate() {
+            if len > 0 || i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {:?}", var, ids)?;
+        }
         write!(f, "}}")
     }
 }
@@ -159,6 +219,14 @@ impl Debug for Subst {
 mod tests {
     use super::*;
 
+    #[test]
+    fn subst_size() {
+        // without sequence variables, a substitution only pays for a pointer
+        let vec = std::mem::size_of::<smallvec::SmallVec<[(Var, Id); 3]>>();
+        let size = std::mem::size_of::<Subst>();
+        assert_eq!(size, vec + std::mem::size_of::<usize>());
+    }
+
     #[test]
     fn var_parse() {
         assert_eq!(Var::from_str("?a").unwrap().to_string(), "?a");
@@ -177,5 +245,15 @@ mod tests {
             Var::from_str("?#0010").unwrap()
         );
         assert_eq!(Var::from_str("?#010").unwrap(), Var(VarInner::Num(10)));
+
+        // sequence vars
+        assert!(Var::from_str("?xs...").unwrap().is_sequence());
+        assert_eq!(Var::from_str("?xs...").unwrap().to_string(), "?xs...");
+        assert!(!Var::from_str("?xs").unwrap().is_sequence());
+        assert!(!Var::from_str("?#1").unwrap().is_sequence());
+        assert_ne!(
+            Var::from_str("?xs...").unwrap(),
+            Var::from_str("?xs").unwrap()
+        );
     }
 }
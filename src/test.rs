@@ -96,6 +96,12 @@ pub fn test_runner<L, A>(
             for goal in goals {
                 let matches = goal.search_eclass(&runner.egraph, id).unwrap();
                 let subst = matches.substs[0].clone();
+                // a goal matched modulo AC may not be in the e-graph as it is written,
+                // so add it like a rule that matched it would
+                if crate::pattern::mentions_ac(&goal.ast) {
+                    runner.egraph.union_modulo_ac(&goal.ast, id, &subst);
+                    runner.egraph.rebuild();
+                }
                 // don't optimize the length for the first egraph
                 runner = runner.without_explanation_length_optimization();
                 let mut explained = runner.explain_matches(&start, &goal.ast, &subst);
@@ -129,6 +135,13 @@ fn percentile(k: f64, data: &[u128]) -> u128 {
     data[i]
 }
 
+// the peak resident memory of this process, on Linux
+fn peak_memory() -> Option<String> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    Some(line["VmHWM:".len()..].trim().to_string())
+}
+
 pub fn bench_egraph<L, N>(
     _name: &str,
     rules: Vec<Rewrite<L, N>>,
@@ -185,13 +198,39 @@ where
         .with_node_limit(node_limit)
         .with_time_limit(Duration::from_secs(time_limit));
 
+    // measure the overhead of explanations with EGG_BENCH_EXPLANATIONS=none, full or compact
+    let explanations = env_var::<String>("EGG_BENCH_EXPLANATIONS");
+    match explanations.as_deref() {
+        None | Some("none") => {}
+        Some("full") => runner = runner.with_explanations_enabled(),
+        Some("compact") => runner = runner.with_compact_explanations_enabled(),
+        Some(other) => panic!(
+            "Unknown EGG_BENCH_EXPLANATIONS={}, use none, full or compact",
+            other
+        ),
+    }
+
     for expr in exprs {
         runner = runner.with_expr(&expr.parse().unwrap());
     }
 
     let runner = runner.run(&rules);
     eprintln!("{}", runner.report());
-    let egraph = runner.egraph;
+    let mut egraph = runner.egraph;
+    if egraph.are_explanations_enabled() {
+        let size = egraph.explain.as_ref().unwrap().approximate_size();
+        eprintln!(
+            "Explanations: {} enodes, ~{} bytes",
+            egraph.get_explanation_num_nodes(),
+            size
+        );
+    }
+    if explanations.is_some() {
+        // only meaningful when this is the only test run by the process
+        if let Some(peak) = peak_memory() {
+            eprintln!("Peak memory: {}", peak);
+        }
+    }
 
     let get_len = |pat: &Pattern<L>| pat.to_string().len();
     let max_width = patterns.iter().map(get_len).max().unwrap_or(0);
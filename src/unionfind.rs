@@ -18,6 +18,38 @@ impl UnionFind {
         self.parents.len()
     }
 
+    pub fn parents(&self) -> &[Id] {
+        &self.parents
+    }
+
+    /// Makes a union-find from its parents, or returns `None` if they do
+    /// not form a forest, so following the parents from some id never
+    /// reaches a root.
+    pub fn from_parents(parents: Vec<Id>) -> Option<Self> {
+        // 0: not visited yet, 1: on the current path, 2: reaches a root
+        let mut state = vec![0u8; parents.len()];
+        let mut path = vec![];
+        for start in 0..parents.len() {
+            let mut current = start;
+            while state[current] == 0 {
+                state[current] = 1;
+                path.push(current);
+                let parent = usize::from(parents[current]);
+                if parent == current {
+                    break;
+                }
+                current = parent;
+            }
+            if state[current] == 1 && usize::from(parents[current]) != current {
+                return None;
+            }
+            for i in path.drain(..) {
+                state[i] = 2;
+            }
+        }
+        Some(UnionFind { parents })
+    }
+
     fn parent(&self, query: Id) -> Id {
         self.parents[usize::from(query)]
     }
@@ -89,4 +121,14 @@ mod tests {
         let expected = vec![0, 0, 0, 0, 4, 5, 6, 6, 6, 6];
         assert_eq!(uf.parents, ids(expected));
     }
+
+    #[test]
+    fn from_parents() {
+        let uf = UnionFind::from_parents(ids([0, 0, 1, 3, 3])).unwrap();
+        assert_eq!(uf.find(Id::from(2)), Id::from(0));
+        assert_eq!(uf.find(Id::from(4)), Id::from(3));
+
+        assert!(UnionFind::from_parents(ids([1, 0])).is_none());
+        assert!(UnionFind::from_parents(ids([0, 2, 3, 1])).is_none());
+    }
 }
@@ -65,6 +65,28 @@ pub(crate) fn hashmap_with_capacity<K, V>(cap: usize) -> hashmap::HashMap<K, V>
     hashmap::HashMap::with_capacity_and_hasher(cap, <_>::default())
 }
 
+/// Removes a key from a map, without keeping the order of a deterministic map.
+pub(crate) fn hashmap_remove<K: std::hash::Hash + Eq, V>(
+    map: &mut hashmap::HashMap<K, V>,
+    key: &K,
+) -> Option<V> {
+    #[cfg(feature = "deterministic")]
+    return map.swap_remove(key);
+    #[cfg(not(feature = "deterministic"))]
+    return map.remove(key);
+}
+
+/// Removes a value from a set, without keeping the order of a deterministic set.
+pub(crate) fn hashset_remove<K: std::hash::Hash + Eq>(
+    set: &mut hashmap::HashSet<K>,
+    key: &K,
+) -> bool {
+    #[cfg(feature = "deterministic")]
+    return set.swap_remove(key);
+    #[cfg(not(feature = "deterministic"))]
+    return set.remove(key);
+}
+
 pub(crate) type IndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasher>;
 pub(crate) type IndexSet<K> = indexmap::IndexSet<K, BuildHasher>;
 
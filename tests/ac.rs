@@ -0,0 +1,157 @@
+use egg::{rewrite as rw, *};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Language)]
+enum Arith {
+    #[op = "+"]
+    #[ac]
+    Add([Id; 2]),
+    #[op = "*"]
+    #[ac]
+    Mul([Id; 2]),
+    #[op = "-"]
+    Sub([Id; 2]),
+    #[op = "="]
+    #[commutative]
+    Eq([Id; 2]),
+    Num(i32),
+    Symbol(Symbol),
+}
+
+// none of these rules mention commutativity or associativity
+fn rules() -> Vec<Rewrite<Arith, ()>> {
+    vec![
+        rw!("distribute"; "(* ?a (+ ?b ?c))" => "(+ (* ?a ?b) (* ?a ?c))"),
+        rw!("double"; "(+ ?a (+ ?a ?b))" => "(+ (* 2 ?a) ?b)"),
+        rw!("sub-self"; "(- ?a ?a)" => "0"),
+        rw!("eq-zero"; "(= 0 (- ?a ?b))" => "(= ?a ?b)"),
+    ]
+}
+
+egg::test_fn! {
+    ac_distribute, rules(),
+    "(* (+ c b) a)" => "(+ (* b a) (* a c))"
+}
+
+egg::test_fn! {
+    ac_flatten, rules(),
+    "(+ x (+ y x))" => "(+ y (* x 2))"
+}
+
+egg::test_fn! {
+    ac_commutative, rules(),
+    "(= (- x x) (- y z))" => "(= z y)"
+}
+
+egg::test_fn! {
+    #[should_panic(expected = "Could not prove goal 0")]
+    ac_not_commutative, rules(),
+    "(- a b)" => "(- b a)"
+}
+
+#[test]
+fn ac_extract_normalized() {
+    let a: RecExpr<Arith> = "(+ c (+ (* b a) (+ a b)))".parse().unwrap();
+    let b: RecExpr<Arith> = "(+ (+ b (* a b)) (+ a c))".parse().unwrap();
+    assert_eq!(a.normalize_ac(), b.normalize_ac());
+
+    // operands are ordered by `Arith`'s `Ord`, so products come before numbers
+    let c: RecExpr<Arith> = "(+ 3 (+ (* 2 1) (+ 1 2)))".parse().unwrap();
+    assert_eq!(c.normalize_ac().to_string(), "(+ (* 1 2) (+ 1 (+ 2 3)))");
+
+    let d: RecExpr<Arith> = "(= 3 (- 2 1))".parse().unwrap();
+    assert_eq!(d.normalize_ac().to_string(), "(= (- 2 1) 3)");
+
+    let runner = Runner::default()
+        .with_expr(&"(* (+ c b) a)".parse().unwrap())
+        .run(&rules());
+    let extractor = Extractor::new(&runner.egraph, AstSize);
+    let (_, best) = extractor.find_best(runner.roots[0]);
+    // the extracted term is in the e-graph as it is
+    let root = runner.egraph.find(runner.roots[0]);
+    assert_eq!(runner.egraph.lookup_expr(&best), Some(root));
+    let (_, normalized) = extractor.find_best_normalized(runner.roots[0]);
+    assert_eq!(normalized, best.normalize_ac());
+}
+
+#[test]
+fn ac_explanations() {
+    let start = "(* (+ c b) a)".parse().unwrap();
+    let goal = "(+ (* a b) (* a c))".parse().unwrap();
+    let mut runner = Runner::default()
+        .with_explanations_enabled()
+        .with_expr(&start)
+        .run(&rules());
+    let mut explanation = runner.explain_equivalence(&start, &goal);
+    explanation.check_proof(&rules());
+    assert!(explanation
+        .make_flat_explanation()
+        .iter()
+        .any(|term| term.forward_rule == Some(Symbol::from("distribute"))
+            || term.backward_rule == Some(Symbol::from("distribute"))));
+    // the reordering steps are checked by `check_proof` too
+    assert!(explanation
+        .make_flat_explanation()
+        .iter()
+        .any(|term| term.ac));
+}
+
+#[test]
+#[should_panic(expected = "non-equal terms")]
+fn ac_explain_does_not_union() {
+    let mut egraph = EGraph::<Arith, ()>::default().with_explanations_enabled();
+    egraph.add_expr(&"(+ a b)".parse().unwrap());
+    egraph.rebuild();
+    // equal modulo AC, but no rule has matched `(+ b a)`
+    egraph.explain_equivalence(&"(+ a b)".parse().unwrap(), &"(+ b a)".parse().unwrap());
+}
+
+#[test]
+fn ac_many_operands() {
+    let make = |max| {
+        let mut egraph = EGraph::<Arith, ()>::default().with_max_ac_operands(max);
+        let root = egraph.add_expr(&"(+ (+ c (+ d (+ e (+ f g)))) (+ a b))".parse().unwrap());
+        // the two halves of the split {a, g} and {b, c, d, e, f}
+        egraph.add_expr(&"(+ a g)".parse().unwrap());
+        egraph.add_expr(&"(+ b (+ c (+ d (+ e f))))".parse().unwrap());
+        egraph.rebuild();
+        (egraph, root)
+    };
+    let pattern: Pattern<Arith> = "(+ (+ g a) ?rest)".parse().unwrap();
+
+    // the split needs all seven operands, more than the default limit of six
+    let (egraph, root) = make(6);
+    assert!(pattern.search_eclass(&egraph, root).is_none());
+
+    let (egraph, root) = make(8);
+    assert!(pattern.search_eclass(&egraph, root).is_some());
+}
+
+#[test]
+fn ac_smaller_than_rules() {
+    let start = "(* (+ a (+ b c)) (+ d e))".parse().unwrap();
+
+    let ac = Runner::default().with_expr(&start).run(&rules());
+
+    // the same language without AC, so matching is purely syntactic
+    define_language! {
+        enum Plain {
+            "+" = Add([Id; 2]),
+            "*" = Mul([Id; 2]),
+            Symbol(Symbol),
+        }
+    }
+    let plain_rules: Vec<Rewrite<Plain, ()>> = vec![
+        rw!("distribute"; "(* ?a (+ ?b ?c))" => "(+ (* ?a ?b) (* ?a ?c))"),
+        rw!("add-comm"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+        rw!("mul-comm"; "(* ?a ?b)" => "(* ?b ?a)"),
+        rw!("add-assoc"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+        rw!("mul-assoc"; "(* ?a (* ?b ?c))" => "(* (* ?a ?b) ?c)"),
+    ];
+    let plain = Runner::default()
+        .with_expr(&start.to_string().parse().unwrap())
+        .run(&plain_rules);
+
+    let goal: Pattern<Arith> = "(+ (* (+ c b) (+ e d)) (* a (+ d e)))".parse().unwrap();
+    assert!(goal.search_eclass(&ac.egraph, ac.roots[0]).is_some());
+    assert!(ac.egraph.total_number_of_nodes() < plain.egraph.total_number_of_nodes());
+}
@@ -72,3 +72,19 @@ fn path2() {
     runner.egraph.check_not("(pred (path 4 1))");
     runner.egraph.check_not("(pred (path 3 1))");
 }
+
+#[test]
+fn variadic() {
+    let mut egraph = EGraph::<Lang, ()>::default();
+    egraph.assert("(fact 1 2 3), (fact 4), (fact 5 6)");
+    let rules = vec![
+        multi_rewrite!("first"; "?x = true = (fact ?a ?rest...)" => "?x = (first ?a)"),
+        multi_rewrite!("rest"; "?x = true = (fact ?a ?rest...)" => "?x = (rest ?rest...)"),
+        multi_rewrite!("last"; "?x = true = (fact ?init... ?z)" => "?x = (last ?z)"),
+    ];
+    let mut runner = Runner::default().with_egraph(egraph).run(&rules);
+    runner.egraph.check("(first 1), (first 4), (first 5)");
+    runner.egraph.check("(rest 2 3), (rest), (rest 6)");
+    runner.egraph.check("(last 3), (last 4), (last 6)");
+    runner.egraph.check_not("(first 2), (rest 3), (last 5)");
+}
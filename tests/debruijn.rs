@@ -0,0 +1,253 @@
+use egg::{rewrite as rw, *};
+
+// The same language as `lambda.rs`, but with de Bruijn indices instead of
+// named variables, so substitution is handled by `egg`.
+define_language! {
+    enum Lambda {
+        Bool(bool),
+        Num(i32),
+        Index(DeBruijnIndex),
+
+        "+" = Add([Id; 2]),
+        "=" = Eq([Id; 2]),
+
+        "app" = App([Id; 2]),
+        "lam" = Lambda(Id),
+        "let" = Let([Id; 2]),
+        "fix" = Fix(Id),
+
+        "if" = If([Id; 3]),
+
+        Symbol(egg::Symbol),
+    }
+}
+
+impl BinderLanguage for Lambda {
+    fn index(&self) -> Option<DeBruijnIndex> {
+        match self {
+            Lambda::Index(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn from_index(index: DeBruijnIndex) -> Self {
+        Lambda::Index(index)
+    }
+
+    fn binds(&self, child: usize) -> usize {
+        match (self, child) {
+            // `(let e body)` binds a variable in `body` only
+            (Lambda::Lambda(_), 0) | (Lambda::Fix(_), 0) | (Lambda::Let(_), 1) => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl Lambda {
+    fn num(&self) -> Option<i32> {
+        match self {
+            Lambda::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+type EGraph = egg::EGraph<Lambda, ConstantFold>;
+
+#[derive(Default)]
+struct ConstantFold;
+
+fn eval(egraph: &EGraph, enode: &Lambda) -> Option<(Lambda, RecExpr<Lambda>)> {
+    let x = |i: &Id| egraph[*i].data.as_ref().map(|c| &c.0);
+    match enode {
+        Lambda::Num(n) => Some((enode.clone(), format!("{}", n).parse().unwrap())),
+        Lambda::Bool(b) => Some((enode.clone(), format!("{}", b).parse().unwrap())),
+        Lambda::Add([a, b]) => Some((
+            Lambda::Num(x(a)?.num()?.checked_add(x(b)?.num()?)?),
+            format!("(+ {} {})", x(a)?, x(b)?).parse().unwrap(),
+        )),
+        Lambda::Eq([a, b]) => Some((
+            Lambda::Bool(x(a)? == x(b)?),
+            format!("(= {} {})", x(a)?, x(b)?).parse().unwrap(),
+        )),
+        _ => None,
+    }
+}
+
+impl Analysis<Lambda> for ConstantFold {
+    type Data = Option<(Lambda, RecExpr<Lambda>)>;
+
+    fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+        merge_option(to, from, |a, b| {
+            assert_eq!(a.0, b.0, "Merged non-equal constants");
+            DidMerge(false, false)
+        })
+    }
+
+    fn make(egraph: &mut EGraph, enode: &Lambda, _id: Id) -> Self::Data {
+        eval(egraph, enode)
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(c) = egraph[id].data.clone() {
+            if egraph.are_explanations_enabled() {
+                let from = egraph.add_expr_uncanonical(&c.1);
+                let to = egraph.add_uncanonical(c.0.clone());
+                let justification =
+                    AnalysisJustification::new("constant_fold", from, c.0.to_string());
+                egraph.union_analysis(from, to, justification);
+            } else {
+                let const_id = egraph.add(c.0);
+                egraph.union(id, const_id);
+            }
+        }
+    }
+}
+
+fn substitute(body: &str, arg: &str) -> Substitute<Lambda> {
+    Substitute::new(body.parse().unwrap(), arg.parse().unwrap())
+}
+
+fn rules() -> Vec<Rewrite<Lambda, ConstantFold>> {
+    vec![
+        rw!("if-true";  "(if  true ?then ?else)" => "?then"),
+        rw!("if-false"; "(if false ?then ?else)" => "?else"),
+        rw!("add-comm";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
+        rw!("add-assoc"; "(+ (+ ?a ?b) ?c)" => "(+ ?a (+ ?b ?c))"),
+        rw!("eq-comm";   "(= ?a ?b)"        => "(= ?b ?a)"),
+        // capture-avoiding substitution replaces all of lambda.rs's let rules
+        rw!("beta"; "(app (lam ?body) ?e)" => { substitute("?body", "?e") }),
+        rw!("let";  "(let ?e ?body)"       => { substitute("?body", "?e") }),
+        rw!("fix";  "(fix ?body)"          => { substitute("?body", "(fix ?body)") }),
+    ]
+}
+
+egg::test_fn! {
+    debruijn_under, rules(),
+    "(lam (+ 4
+             (app (lam $0)
+                  4)))"
+    =>
+    "(lam 8)",
+}
+
+egg::test_fn! {
+    debruijn_let_simple, rules(),
+    "(let 0
+     (let 1
+     (+ $1 $0)))"
+    =>
+    "1",
+}
+
+egg::test_fn! {
+    #[should_panic(expected = "Could not prove goal 0")]
+    debruijn_capture, rules(),
+    "(let 1 (lam $0))" => "(lam 1)"
+}
+
+egg::test_fn! {
+    debruijn_capture_free, rules(),
+    // λx. let y = x + x in λz. y
+    "(lam (let (+ $0 $0) (lam $1)))" => "(lam (lam (+ $1 $1)))"
+}
+
+egg::test_fn! {
+    #[should_panic(expected = "Could not prove goal 0")]
+    debruijn_capture_free_not_captured, rules(),
+    "(lam (let (+ $0 $0) (lam $1)))" => "(lam (lam (+ $0 $0)))"
+}
+
+egg::test_fn! {
+    debruijn_closure, rules(),
+    // let five = 5 in let add-five = λx. x + five in let five = 6 in add-five 1
+    "(let 5
+     (let (lam (+ $0 $1))
+     (let 6
+     (app $1 1))))"
+    =>
+    "6"
+}
+
+egg::test_fn! {
+    #[should_panic(expected = "Could not prove goal 0")]
+    debruijn_closure_not_seven, rules(),
+    "(let 5
+     (let (lam (+ $0 $1))
+     (let 6
+     (app $1 1))))"
+    =>
+    "7"
+}
+
+egg::test_fn! {
+    debruijn_partial_compose, rules(),
+    // (λf. λg. λx. f (g x)) applied to the free variable f
+    "(app (lam (lam (lam (app $2 (app $1 $0))))) f)"
+    =>
+    "(lam (lam (app f (app $1 $0))))"
+}
+
+egg::test_fn! {
+    debruijn_compose, rules(),
+    "(let (lam (lam (lam (app $2 (app $1 $0)))))
+     (let (lam (+ $0 1))
+     (app (app $1 $0) $0)))"
+    =>
+    "(lam (+ 1
+             (app (lam (+ 1 $0))
+                  $0)))",
+    "(lam (+ $0 2))"
+}
+
+egg::test_fn! {
+    debruijn_if_simple, rules(),
+    "(if (= 1 1) 7 9)" => "7"
+}
+
+egg::test_fn! {
+    debruijn_compose_many, rules(),
+    "(let (lam (lam (lam (app $2 (app $1 $0)))))
+     (let (lam (+ $0 1))
+     (app (app $1 $0)
+          (app (app $1 $0)
+               (app (app $1 $0)
+                    (app (app $1 $0)
+                         (app (app $1 $0)
+                              (app (app $1 $0)
+                                   $0))))))))"
+    =>
+    "(lam (+ $0 7))"
+}
+
+egg::test_fn! {
+    debruijn_if, rules(),
+    "(let (lam
+        (if (= $0 0)
+            0
+            1))
+        (+ (app $0 0)
+           (app $0 10)))"
+    =>
+    "1",
+}
+
+egg::test_fn! {
+    #[cfg(not(debug_assertions))]
+    #[cfg_attr(feature = "test-explanations", ignore)]
+    debruijn_fib, rules(),
+    runner = Runner::default()
+        .with_iter_limit(60)
+        .with_node_limit(500_000),
+    "(let (fix (lam
+        (if (= $0 0)
+            0
+        (if (= $0 1)
+            1
+        (+ (app $1
+                (+ $0 -1))
+            (app $1
+                (+ $0 -2)))))))
+        (app $0 4))"
+    => "3"
+}
@@ -39,10 +39,10 @@ struct LambdaAnalysis;
 #[derive(Debug)]
 struct Data {
     free: HashSet<Id>,
-    constant: Option<(Lambda, PatternAst<Lambda>)>,
+    constant: Option<(Lambda, RecExpr<Lambda>)>,
 }
 
-fn eval(egraph: &EGraph, enode: &Lambda) -> Option<(Lambda, PatternAst<Lambda>)> {
+fn eval(egraph: &EGraph, enode: &Lambda) -> Option<(Lambda, RecExpr<Lambda>)> {
     let x = |i: &Id| egraph[*i].data.constant.as_ref().map(|c| &c.0);
     match enode {
         Lambda::Num(n) => Some((enode.clone(), format!("{}", n).parse().unwrap())),
@@ -100,12 +100,11 @@ impl Analysis<Lambda> for LambdaAnalysis {
     fn modify(egraph: &mut EGraph, id: Id) {
         if let Some(c) = egraph[id].data.constant.clone() {
             if egraph.are_explanations_enabled() {
-                egraph.union_instantiations(
-                    &c.0.to_string().parse().unwrap(),
-                    &c.1,
-                    &Default::default(),
-                    "analysis".to_string(),
-                );
+                let from = egraph.add_expr_uncanonical(&c.1);
+                let to = egraph.add_uncanonical(c.0.clone());
+                let justification =
+                    AnalysisJustification::new("constant_fold", from, c.0.to_string());
+                egraph.union_analysis(from, to, justification);
             } else {
                 let const_id = egraph.add(c.0);
                 egraph.union(id, const_id);
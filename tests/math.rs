@@ -48,7 +48,7 @@ impl egg::CostFunction<Math> for MathCostFn {
 #[derive(Default)]
 pub struct ConstantFold;
 impl Analysis<Math> for ConstantFold {
-    type Data = Option<(Constant, PatternAst<Math>)>;
+    type Data = Option<(Constant, RecExpr<Math>)>;
 
     fn make(egraph: &mut EGraph, enode: &Math, _id: Id) -> Self::Data {
         let x = |i: &Id| egraph[*i].data.as_ref().map(|d| d.0);
@@ -83,14 +83,13 @@ impl Analysis<Math> for ConstantFold {
 
     fn modify(egraph: &mut EGraph, id: Id) {
         let data = egraph[id].data.clone();
-        if let Some((c, pat)) = data {
+        if let Some((c, expr)) = data {
             if egraph.are_explanations_enabled() {
-                egraph.union_instantiations(
-                    &pat,
-                    &format!("{}", c).parse().unwrap(),
-                    &Default::default(),
-                    "constant_fold".to_string(),
-                );
+                let from = egraph.add_expr_uncanonical(&expr);
+                let to = egraph.add_uncanonical(Math::Constant(c));
+                let justification =
+                    AnalysisJustification::new("constant_fold", from, c.to_string());
+                egraph.union_analysis(from, to, justification);
             } else {
                 let added = egraph.add(Math::Constant(c));
                 egraph.union(id, added);
@@ -408,6 +407,50 @@ fn math_ematching_bench() {
     egg::test::bench_egraph("math", rules(), exprs, extra_patterns);
 }
 
+#[test]
+fn math_shared_scheduler() {
+    let expr: RecExpr<Math> = "(d x (- (pow x 3) (* 7 (pow x 2))))".parse().unwrap();
+    let run = |runner: Runner<Math, ConstantFold>| {
+        runner.with_iter_limit(4).with_expr(&expr).run(&rules())
+    };
+    let simple = run(Runner::default().with_scheduler(SimpleScheduler));
+    let shared = run(Runner::default().with_scheduler(SharedScheduler::default()));
+    assert_eq!(
+        simple.egraph.number_of_classes(),
+        shared.egraph.number_of_classes()
+    );
+    assert_eq!(
+        simple.egraph.total_number_of_nodes(),
+        shared.egraph.total_number_of_nodes()
+    );
+
+    // a trie finds the same matches as searching the patterns one by one
+    let egraph = &shared.egraph;
+    let patterns: Vec<Pattern<Math>> = rules()
+        .iter()
+        .filter_map(|rw| rw.searcher.as_pattern().cloned())
+        .collect();
+    let trie = PatternTrie::new(patterns.clone());
+    let limits: Vec<usize> = (0..patterns.len())
+        .map(|i| [usize::MAX, 0, 1, 7][i % 4])
+        .collect();
+    let substs = |ms: &[SearchMatches<Math>]| -> Vec<(Id, Vec<Subst>)> {
+        ms.iter().map(|m| (m.eclass, m.substs.clone())).collect()
+    };
+    for ((pattern, matches), &limit) in patterns
+        .iter()
+        .zip(trie.search_with_limits(egraph, &limits))
+        .zip(&limits)
+    {
+        assert_eq!(
+            substs(&matches),
+            substs(&pattern.search_with_limit(egraph, limit)),
+            "{}",
+            pattern
+        );
+    }
+}
+
 #[test]
 fn test_basic_egraph_union_intersect() {
     let mut egraph1 = EGraph::new(ConstantFold {}).with_explanations_enabled();